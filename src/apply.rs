@@ -0,0 +1,403 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Applying manifests: creating or updating the objects they describe. This uses server-side
+//! apply, so the api server does the merging, and records click as the manager of the fields we
+//! set.
+
+use crate::discovery::{ApiResource, Discovery};
+use crate::error::KubeError;
+use crate::kube::{Kluster, PatchType};
+use crate::output::ClickWriter;
+use crate::values::val_str;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// The field manager we apply as
+pub const FIELD_MANAGER: &str = "click";
+
+/// Parse the manifests in contents, which can be JSON or YAML, with multiple YAML documents
+/// separated by ---. Lists (kind: List) are expanded into their items, and empty documents are
+/// skipped.
+fn parse_manifests(contents: &str) -> Result<Vec<Value>, KubeError> {
+    let mut objects = vec![];
+    for doc in serde_yaml::Deserializer::from_str(contents) {
+        let value = Value::deserialize(doc)?;
+        if value.is_null() {
+            continue;
+        }
+        if val_str("/kind", &value, "") == "List" {
+            if let Some(items) = value.get("items").and_then(Value::as_array) {
+                objects.extend(items.iter().cloned());
+            }
+            continue;
+        }
+        for field in ["/apiVersion", "/kind", "/metadata/name"].iter() {
+            if value.pointer(field).and_then(Value::as_str).is_none() {
+                return Err(KubeError::ParseErr(format!(
+                    "Manifest has no {}",
+                    &field[1..].replace('/', ".")
+                )));
+            }
+        }
+        objects.push(value);
+    }
+    Ok(objects)
+}
+
+/// Read the manifests at path, which can be a file, a directory (in which case all the .yaml,
+/// .yml and .json files in it are read, in name order), or - for stdin
+pub fn read_manifests(path: &str) -> Result<Vec<Value>, KubeError> {
+    if path == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        return parse_manifests(&contents);
+    }
+    let path = Path::new(path);
+    if path.is_dir() {
+        let mut files = vec![];
+        for entry in fs::read_dir(path)? {
+            let file = entry?.path();
+            let is_manifest = match file.extension().and_then(|ext| ext.to_str()) {
+                Some(ext) => ext == "yaml" || ext == "yml" || ext == "json",
+                None => false,
+            };
+            if is_manifest && file.is_file() {
+                files.push(file);
+            }
+        }
+        files.sort();
+        let mut objects = vec![];
+        for file in files.iter() {
+            let contents = fs::read_to_string(file)?;
+            objects.extend(parse_manifests(&contents).map_err(|e| {
+                KubeError::ParseErr(format!("Failed to read {}: {}", file.display(), e))
+            })?);
+        }
+        Ok(objects)
+    } else {
+        parse_manifests(&fs::read_to_string(path)?)
+    }
+}
+
+/// How kind/name is shown, like kubectl does: deployment.apps/web
+pub fn display_name(resource: &ApiResource, name: &str) -> String {
+    if resource.group().is_empty() {
+        format!("{}/{}", resource.kind.to_lowercase(), name)
+    } else {
+        format!(
+            "{}.{}/{}",
+            resource.kind.to_lowercase(),
+            resource.group(),
+            name
+        )
+    }
+}
+
+/// Find the resource for kind in api_version. Discovery only knows about the preferred version of
+/// each group, so we ask the server about others.
+pub fn find_resource(
+    kluster: &Kluster,
+    discovery: &Discovery,
+    api_version: &str,
+    kind: &str,
+) -> Result<ApiResource, KubeError> {
+    if let Some(res) = discovery
+        .resources
+        .iter()
+        .find(|res| res.group_version == api_version && res.kind == kind)
+    {
+        return Ok(res.clone());
+    }
+    let resources = match Discovery::fetch_group_version(&kluster.connector(), api_version) {
+        Ok(resources) => resources,
+        Err(ref e) if e.status_code() == Some(404) => vec![],
+        Err(e) => return Err(e),
+    };
+    resources
+        .into_iter()
+        .find(|res| res.kind == kind)
+        .ok_or_else(|| KubeError::NotServed(format!("{} ({})", kind, api_version)))
+}
+
+/// An object we applied
+pub struct Applied {
+    pub resource: ApiResource,
+    pub namespace: Option<String>,
+    pub name: String,
+    pub result: ApplyResult,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ApplyResult {
+    Created,
+    Configured,
+    Unchanged,
+}
+
+impl fmt::Display for ApplyResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApplyResult::Created => write!(f, "created"),
+            ApplyResult::Configured => write!(f, "configured"),
+            ApplyResult::Unchanged => write!(f, "unchanged"),
+        }
+    }
+}
+
+/// Remove the parts of an object that change whenever it's written, even if nothing else does
+pub fn without_write_metadata(obj: &Value) -> Value {
+    let mut obj = obj.clone();
+    if let Some(metadata) = obj.get_mut("metadata").and_then(Value::as_object_mut) {
+        metadata.remove("managedFields");
+        metadata.remove("resourceVersion");
+        metadata.remove("generation");
+    }
+    obj
+}
+
+/// Where an object from a manifest lives
+pub struct Target {
+    pub resource: ApiResource,
+    pub namespace: Option<String>,
+    pub name: String,
+    pub url: String,
+}
+
+/// Figure out where obj lives, which is in default_namespace if it's namespaced but doesn't
+/// specify one
+pub fn resolve(
+    kluster: &Kluster,
+    discovery: &Discovery,
+    obj: &Value,
+    default_namespace: &str,
+) -> Result<Target, KubeError> {
+    let resource = find_resource(
+        kluster,
+        discovery,
+        &val_str("/apiVersion", obj, ""),
+        &val_str("/kind", obj, ""),
+    )?;
+    let name = val_str("/metadata/name", obj, "").into_owned();
+    let namespace = if resource.namespaced {
+        Some(
+            obj.pointer("/metadata/namespace")
+                .and_then(Value::as_str)
+                .unwrap_or(default_namespace)
+                .to_owned(),
+        )
+    } else {
+        None
+    };
+    let url = resource.url(namespace.as_deref().unwrap_or(""), &name);
+    Ok(Target {
+        resource,
+        namespace,
+        name,
+        url,
+    })
+}
+
+/// Get the live object at url, or None if there isn't one
+pub fn get_live(kluster: &Kluster, url: &str) -> Result<Option<Value>, KubeError> {
+    match kluster.get_value(url) {
+        Ok(live) => Ok(Some(live)),
+        Err(ref e) if e.status_code() == Some(404) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Server-side apply obj to url, returning the result. With force, fields other managers own are
+/// taken over rather than being a conflict. With dry_run, the server says what the result would
+/// be, but doesn't change anything.
+pub fn server_apply(
+    kluster: &Kluster,
+    url: &str,
+    obj: &Value,
+    force: bool,
+    dry_run: bool,
+) -> Result<Value, KubeError> {
+    let mut apply_url = format!("{}?fieldManager={}", url, FIELD_MANAGER);
+    if force {
+        apply_url.push_str("&force=true");
+    }
+    if dry_run {
+        apply_url.push_str("&dryRun=All");
+    }
+    kluster.patch(apply_url.as_str(), &obj.to_string(), PatchType::Apply)
+}
+
+/// Apply obj, which goes in default_namespace if it doesn't specify one. See server_apply for
+/// force and dry_run.
+pub fn apply_object(
+    kluster: &Kluster,
+    discovery: &Discovery,
+    obj: &Value,
+    default_namespace: &str,
+    force: bool,
+    dry_run: bool,
+) -> Result<Applied, KubeError> {
+    let target = resolve(kluster, discovery, obj, default_namespace)?;
+    let before = get_live(kluster, &target.url)?;
+    let after = server_apply(kluster, &target.url, obj, force, dry_run)?;
+    let result = match before {
+        None => ApplyResult::Created,
+        Some(ref before) if without_write_metadata(before) == without_write_metadata(&after) => {
+            ApplyResult::Unchanged
+        }
+        Some(_) => ApplyResult::Configured,
+    };
+    Ok(Applied {
+        resource: target.resource,
+        namespace: target.namespace,
+        name: target.name,
+        result,
+    })
+}
+
+/// Has click applied any fields of obj
+fn applied_by_click(obj: &Value) -> bool {
+    obj.pointer("/metadata/managedFields")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .any(|field| {
+            val_str("/manager", field, "") == FIELD_MANAGER
+                && val_str("/operation", field, "") == "Apply"
+        })
+}
+
+/// Delete objects that match selector and were applied by click before, but aren't in applied.
+/// Only the kinds and namespaces of the objects in applied are looked at.
+pub fn prune(
+    kluster: &Kluster,
+    applied: &[Applied],
+    selector: &str,
+    dry_run: bool,
+    writer: &mut ClickWriter,
+) -> Result<(), KubeError> {
+    let mut lists: Vec<(&ApiResource, Option<&str>)> = vec![];
+    for app in applied.iter() {
+        let list = (&app.resource, app.namespace.as_deref());
+        if !lists.contains(&list) {
+            lists.push(list);
+        }
+    }
+    let suffix = if dry_run { " (server dry run)" } else { "" };
+    for (resource, namespace) in lists.into_iter() {
+        let url = format!(
+            "{}?labelSelector={}",
+            resource.list_url(namespace),
+            selector
+        );
+        let list = kluster.get_value(url.as_str())?;
+        for item in list
+            .get("items")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let name = val_str("/metadata/name", item, "");
+            let item_namespace = item.pointer("/metadata/namespace").and_then(Value::as_str);
+            let was_applied = applied.iter().any(|app| {
+                app.resource == *resource
+                    && app.namespace.as_deref() == item_namespace
+                    && app.name == name
+            });
+            if was_applied || !applied_by_click(item) {
+                continue;
+            }
+            let mut delete_url = resource.url(item_namespace.unwrap_or(""), &name);
+            if dry_run {
+                delete_url.push_str("?dryRun=All");
+            }
+            let resp = kluster.delete(delete_url.as_str(), None, true)?;
+            if !resp.status.is_success() {
+                return Err(KubeError::KubeServerError(format!(
+                    "Failed to delete {}: {}",
+                    display_name(resource, &name),
+                    resp.status
+                )));
+            }
+            clickwriteln!(writer, "{} pruned{}", display_name(resource, &name), suffix);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifests() {
+        let yaml = "\
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: one
+---
+---
+apiVersion: v1
+kind: List
+items:
+- apiVersion: v1
+  kind: Secret
+  metadata:
+    name: two
+- apiVersion: apps/v1
+  kind: Deployment
+  metadata:
+    name: three
+";
+        let objects = parse_manifests(yaml).unwrap();
+        let names: Vec<_> = objects
+            .iter()
+            .map(|obj| val_str("/metadata/name", obj, "").into_owned())
+            .collect();
+        assert_eq!(names, vec!["one", "two", "three"]);
+
+        let json = r#"{"apiVersion": "v1", "kind": "Namespace", "metadata": {"name": "ns"}}"#;
+        assert_eq!(parse_manifests(json).unwrap().len(), 1);
+
+        assert!(parse_manifests("apiVersion: v1\nkind: ConfigMap\n").is_err());
+    }
+
+    #[test]
+    fn test_write_metadata() {
+        let before = json!({
+            "metadata": {"name": "a", "resourceVersion": "1", "managedFields": []},
+            "data": {"k": "v"},
+        });
+        let after = json!({
+            "metadata": {"name": "a", "resourceVersion": "2", "managedFields": [{
+                "manager": "click",
+                "operation": "Apply",
+            }]},
+            "data": {"k": "v"},
+        });
+        assert_eq!(
+            without_write_metadata(&before),
+            without_write_metadata(&after)
+        );
+        assert!(!applied_by_click(&before));
+        assert!(applied_by_click(&after));
+    }
+}
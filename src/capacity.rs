@@ -0,0 +1,277 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! How much of each node's allocatable cpu and memory is requested by, and promised to (as
+//! limits), the pods scheduled there.
+
+use crate::error::KubeError;
+use crate::kube::{Kluster, Node, NodeList, Pod, PodList};
+use crate::output::ClickWriter;
+use crate::table::CellSpec;
+use crate::top::{self, Amounts};
+use crate::values::Quantity;
+
+use prettytable::{format, Table};
+
+use std::collections::HashMap;
+
+/// The allocatable resources of a node, and the totals for the pods on it
+#[derive(Debug, Default, PartialEq)]
+pub struct NodeCapacity {
+    pub name: String,
+    pub allocatable: Amounts,
+    pub requests: Amounts,
+    pub limits: Amounts,
+    pub pods: usize,
+}
+
+impl NodeCapacity {
+    fn add(&mut self, other: &NodeCapacity) {
+        self.allocatable.add(&other.allocatable);
+        self.requests.add(&other.requests);
+        self.limits.add(&other.limits);
+        self.pods += other.pods;
+    }
+}
+
+/// Sum up the requests and limits of pods for each of nodes. Pods on nodes that aren't in nodes
+/// are ignored.
+pub fn node_capacities(nodes: &[Node], pods: &[Pod]) -> Vec<NodeCapacity> {
+    let mut capacities: Vec<NodeCapacity> = nodes
+        .iter()
+        .map(|node| NodeCapacity {
+            name: node.metadata.name.clone(),
+            allocatable: match node.status.allocatable {
+                Some(ref alloc) => Amounts::from_map(alloc),
+                None => Amounts::default(),
+            },
+            ..Default::default()
+        })
+        .collect();
+    let index: HashMap<String, usize> = capacities
+        .iter()
+        .enumerate()
+        .map(|(idx, cap)| (cap.name.clone(), idx))
+        .collect();
+    for pod in pods.iter() {
+        let idx = pod.spec.node_name.as_ref().and_then(|node| index.get(node));
+        if let Some(idx) = idx {
+            let (requests, limits) = top::pod_requests_limits(pod);
+            let cap = &mut capacities[*idx];
+            cap.requests.add(&requests);
+            cap.limits.add(&limits);
+            cap.pods += 1;
+        }
+    }
+    capacities
+}
+
+/// The cells for one resource, with amounts shown using fmt: allocatable, requests, limits and
+/// what's left to request. The limits percent is red if the node is overcommitted.
+fn resource_cells(
+    allocatable: Option<Quantity>,
+    requests: Option<Quantity>,
+    limits: Option<Quantity>,
+    fmt: fn(&Quantity) -> String,
+    cells: &mut Vec<CellSpec<'static>>,
+) {
+    let alloc = allocatable.unwrap_or_default();
+    let requests = requests.unwrap_or_default();
+    let limits = limits.unwrap_or_default();
+    let percent = |amount: Quantity| match amount.percent_of(alloc) {
+        Some(p) => format!("{}%", p),
+        None => "-".to_owned(),
+    };
+    let right = |txt: String| CellSpec::with_align_owned(txt, format::Alignment::RIGHT);
+    cells.push(right(fmt(&alloc)));
+    cells.push(right(fmt(&requests)));
+    cells.push(right(percent(requests)));
+    cells.push(right(fmt(&limits)));
+    let mut limits_percent = right(percent(limits));
+    if limits > alloc {
+        limits_percent.style = Some("Fr");
+    }
+    cells.push(limits_percent);
+    // the scheduler won't put a pod somewhere its requests don't fit, but static pods can
+    // overcommit requests too
+    let mut free = right(fmt(&(alloc - requests)));
+    if requests > alloc {
+        free.style = Some("Fr");
+    }
+    cells.push(free);
+}
+
+fn capacity_row(cap: &NodeCapacity) -> Vec<CellSpec<'static>> {
+    let mut cells = vec![CellSpec::new_owned(cap.name.clone())];
+    resource_cells(
+        cap.allocatable.cpu,
+        cap.requests.cpu,
+        cap.limits.cpu,
+        Quantity::fmt_cpu,
+        &mut cells,
+    );
+    resource_cells(
+        cap.allocatable.memory,
+        cap.requests.memory,
+        cap.limits.memory,
+        Quantity::fmt_memory,
+        &mut cells,
+    );
+    cells.push(CellSpec::with_align_owned(
+        cap.pods.to_string(),
+        format::Alignment::RIGHT,
+    ));
+    cells
+}
+
+/// Print the capacity of the nodes matching label_selector (or all nodes if None), along with the
+/// cluster totals
+pub fn print_capacity(
+    kluster: &Kluster,
+    label_selector: Option<&str>,
+    writer: &mut ClickWriter,
+) -> Result<(), KubeError> {
+    let nodes_url = match label_selector {
+        Some(selector) => format!("/api/v1/nodes?labelSelector={}", selector),
+        None => "/api/v1/nodes".to_owned(),
+    };
+    let nodes: NodeList = kluster.get(&nodes_url)?;
+    // finished pods don't hold on to their resources
+    let pods: PodList =
+        kluster.get("/api/v1/pods?fieldSelector=status.phase!=Succeeded,status.phase!=Failed")?;
+    let capacities = node_capacities(&nodes.items, &pods.items);
+
+    let mut total = NodeCapacity {
+        name: "Total".to_owned(),
+        ..Default::default()
+    };
+    let mut rows: Vec<((), Vec<CellSpec>)> = vec![];
+    for cap in capacities.iter() {
+        total.add(cap);
+        rows.push(((), capacity_row(cap)));
+    }
+    let mut total_row = capacity_row(&total);
+    total_row[0].style = Some("b");
+    rows.push(((), total_row));
+
+    let mut table = Table::new();
+    table.set_titles(row![
+        "Node",
+        "CPU Alloc",
+        "CPU Req",
+        "CPU Req %",
+        "CPU Lim",
+        "CPU Lim %",
+        "CPU Free",
+        "Mem Alloc",
+        "Mem Req",
+        "Mem Req %",
+        "Mem Lim",
+        "Mem Lim %",
+        "Mem Free",
+        "Pods"
+    ]);
+    crate::table::print_table(&mut table, &rows, writer);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, cpu: &str, memory: &str) -> Node {
+        serde_json::from_value(json!({
+            "metadata": {"name": name},
+            "spec": {},
+            "status": {"conditions": [], "allocatable": {"cpu": cpu, "memory": memory}},
+        }))
+        .unwrap()
+    }
+
+    fn pod(node: &str, requests: serde_json::Value, limits: serde_json::Value) -> Pod {
+        serde_json::from_value(json!({
+            "metadata": {"name": "pod"},
+            "spec": {
+                "nodeName": node,
+                "containers": [{
+                    "name": "app",
+                    "resources": {"requests": requests, "limits": limits},
+                }],
+            },
+            "status": {"phase": "Running"},
+        }))
+        .unwrap()
+    }
+
+    fn q(s: &str) -> Option<Quantity> {
+        s.parse().ok()
+    }
+
+    #[test]
+    fn test_node_capacities() {
+        let nodes = vec![node("a", "4", "8Gi"), node("b", "3500m", "4Gi")];
+        let pods = vec![
+            pod(
+                "a",
+                json!({"cpu": "500m", "memory": "1Gi"}),
+                json!({"cpu": "2"}),
+            ),
+            pod("a", json!({"cpu": "1"}), json!({"cpu": "3"})),
+            pod("c", json!({"cpu": "1"}), json!({})),
+        ];
+        let caps = node_capacities(&nodes, &pods);
+        assert_eq!(
+            caps[0],
+            NodeCapacity {
+                name: "a".to_owned(),
+                allocatable: Amounts {
+                    cpu: q("4"),
+                    memory: q("8Gi"),
+                },
+                requests: Amounts {
+                    cpu: q("1500m"),
+                    memory: q("1Gi"),
+                },
+                limits: Amounts {
+                    cpu: q("5"),
+                    memory: None,
+                },
+                pods: 2,
+            }
+        );
+        assert_eq!(caps[1].pods, 0);
+        assert_eq!(caps[1].requests, Amounts::default());
+    }
+
+    #[test]
+    fn test_big_total_row() {
+        let mut total = NodeCapacity::default();
+        for _ in 0..32 {
+            total.add(&NodeCapacity {
+                allocatable: Amounts {
+                    cpu: q("64"),
+                    memory: q("256Gi"),
+                },
+                ..Default::default()
+            });
+        }
+        let row: Vec<String> = capacity_row(&total)
+            .iter()
+            .map(|cell| cell.to_cell(0).get_content())
+            .collect();
+        // cores never get a binary suffix
+        assert_eq!(row[1], "2048");
+        assert_eq!(row[7], "8Ti");
+    }
+}
@@ -14,19 +14,29 @@
 
 //!  The commands one can run from the repl
 
+use crate::apply;
+use crate::capacity;
+use crate::compare::{self, Scope};
 use crate::completer;
 use crate::config;
+use crate::cp;
+use crate::discovery::{ApiGroup, ApiResource};
+use crate::drain::{self, DrainOptions};
 use crate::env::{self, Env, ObjectSelection};
 use crate::error::KubeError;
+use crate::explain;
 use crate::kobj::{KObj, ObjType, VecWrap};
 use crate::kube::{
-    ConfigMapList, ContainerState, Deployment, DeploymentList, Event, EventList, JobList, Metadata,
-    NamespaceList, Node, NodeCondition, NodeList, Pod, PodList, ReplicaSetList, SecretList,
+    table_cell_string, ConfigMapList, ContainerState, Deployment, DeploymentList, DeploymentStatus,
+    Event, EventList, JobList, Kluster, Metadata, NamespaceList, Node, NodeCondition, NodeList,
+    PagedList, PatchType, Pod, PodList, ReplicaSetList, ResourceList, SecretList, ServerTable,
     Service, ServiceList, StatefulSetList,
 };
+use crate::logs::{self, LogFilter, LogSource};
 use crate::output::ClickWriter;
 use crate::table::{opt_sort, CellSpec};
-use crate::values::{get_val_as, val_item_count, val_str, val_u64};
+use crate::top;
+use crate::values::{get_val_as, label_selector, val_item_count, val_str, val_str_opt, val_u64};
 
 use ansi_term::Colour::Yellow;
 use chrono::offset::Local;
@@ -40,6 +50,7 @@ use prettytable::Row;
 use prettytable::{format, Table};
 use regex::Regex;
 use rustyline::completion::Pair as RustlinePair;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 use strfmt::strfmt;
 
@@ -47,13 +58,11 @@ use std::array::IntoIter;
 use std::cell::RefCell;
 use std::cmp;
 use std::collections::HashMap;
-use std::io::{self, stderr, BufRead, BufReader, Read, Write};
+use std::io::{self, stderr, BufRead, BufReader, Write};
 use std::iter::Iterator;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering;
 use std::sync::mpsc::{channel, RecvTimeoutError};
-use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
@@ -331,6 +340,111 @@ fn identity<T>(t: T) -> T {
     t
 }
 
+/// The --watch arg shared by all the list commands
+fn watch_arg() -> Arg<'static, 'static> {
+    Arg::with_name("watch")
+        .short("w")
+        .long("watch")
+        .help("Watch for changes, redrawing the list as they happen (stop with ^C)")
+        .takes_value(false)
+}
+
+fn limit_arg() -> Arg<'static, 'static> {
+    Arg::with_name("limit")
+        .long("limit")
+        .help("Only get this many objects")
+        .takes_value(true)
+        .validator(valid_u32)
+        .conflicts_with("watch")
+}
+
+/// How many objects to ask for at once when fetching a list
+const LIST_PAGE_SIZE: u32 = 500;
+
+/// Add the limit and continue parameters for fetching a page of a list to url
+fn page_url(url: &str, limit: u32, cont: Option<&str>) -> String {
+    let sep = if url.contains('?') { '&' } else { '?' };
+    match cont {
+        Some(cont) => format!("{}{}limit={}&continue={}", url, sep, limit, cont),
+        None => format!("{}{}limit={}", url, sep, limit),
+    }
+}
+
+/// Get the list at url and print it with print_list, which is passed the list to print, and the
+/// index to start numbering rows at. Large lists are fetched a page at a time, and each page is
+/// printed as it arrives (unless the list is being sorted, in which case we need it all before we
+/// can print anything). If watch is specified in matches, keep redrawing the list as it changes
+/// until Ctrl-C is pressed. Returns the list as it was printed.
+fn get_list<T, F>(
+    env: &Env,
+    url: &str,
+    matches: &ArgMatches,
+    writer: &mut ClickWriter,
+    print_list: F,
+) -> Option<T>
+where
+    T: DeserializeOwned + PagedList,
+    F: FnMut(T, usize, &mut ClickWriter) -> T,
+{
+    if matches.is_present("watch") {
+        crate::watch::watch_list(env, url, writer, print_list)
+    } else {
+        get_pages(env, url, matches, writer, |k, u| k.get_value(u), print_list)
+    }
+}
+
+/// Fetch the list at url a page at a time using fetch, printing it with print_list (see get_list)
+fn get_pages<T, G, F>(
+    env: &Env,
+    url: &str,
+    matches: &ArgMatches,
+    writer: &mut ClickWriter,
+    fetch: G,
+    mut print_list: F,
+) -> Option<T>
+where
+    T: DeserializeOwned + PagedList,
+    G: Fn(&Kluster, &str) -> Result<Value, KubeError>,
+    F: FnMut(T, usize, &mut ClickWriter) -> T,
+{
+    let progressive = !matches.is_present("sort") && !matches.is_present("reverse");
+    let mut remaining = matches.value_of("limit").map(|l| l.parse::<u32>().unwrap()); // validated
+    let mut list: Option<T> = None;
+    let mut cont: Option<String> = None;
+    env.ctrlcbool.store(false, Ordering::SeqCst);
+    loop {
+        let limit = remaining.map_or(LIST_PAGE_SIZE, |r| r.min(LIST_PAGE_SIZE));
+        let page_url = page_url(url, limit, cont.as_deref());
+        let page: Value = env.run_on_kluster(|k| fetch(k, page_url.as_str()))?;
+        cont = val_str_opt("/metadata/continue", &page).filter(|c| !c.is_empty());
+        let page: T = match serde_json::from_value(page) {
+            Ok(page) => page,
+            Err(e) => {
+                writeln!(stderr(), "Failed to parse list: {}", e).unwrap_or(());
+                return None;
+            }
+        };
+        remaining = remaining.map(|r| r.saturating_sub(page.len() as u32));
+        let page = if progressive {
+            print_list(page, list.as_ref().map_or(0, |l| l.len()), writer)
+        } else {
+            page
+        };
+        match list {
+            Some(ref mut l) => l.append(page),
+            None => list = Some(page),
+        }
+        if cont.is_none() || remaining == Some(0) || env.ctrlcbool.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    if progressive {
+        list
+    } else {
+        list.map(|l| print_list(l, 0, writer))
+    }
+}
+
 /// a clap validator for u32
 fn valid_u32(s: String) -> Result<(), String> {
     s.parse::<u32>().map(|_| ()).map_err(|e| e.to_string())
@@ -350,6 +464,13 @@ fn valid_date(s: String) -> Result<(), String> {
         .map_err(|e| e.to_string())
 }
 
+/// a clap validator for regexes
+fn valid_regex(s: String) -> Result<(), String> {
+    Regex::new(s.as_str())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
 /// a clap validator for boolean
 fn valid_bool(s: String) -> Result<(), String> {
     s.parse::<bool>().map(|_| ()).map_err(|e| e.to_string())
@@ -520,6 +641,7 @@ fn print_podlist(
     regex: Option<Regex>,
     sort: Option<&str>,
     reverse: bool,
+    start: usize,
     writer: &mut ClickWriter,
 ) -> PodList {
     let mut table = Table::new();
@@ -644,7 +766,7 @@ fn print_podlist(
         None => pods_specs.collect(),
     };
 
-    crate::table::print_table(&mut table, &filtered, writer);
+    crate::table::print_table_from(&mut table, &filtered, start, writer);
 
     let final_pods = filtered.into_iter().map(|pod_spec| pod_spec.0).collect();
     PodList { items: final_pods }
@@ -675,6 +797,7 @@ fn print_nodelist(
     regex: Option<Regex>,
     sort: Option<&str>,
     reverse: bool,
+    start: usize,
     writer: &mut ClickWriter,
 ) -> NodeList {
     let mut table = Table::new();
@@ -781,7 +904,7 @@ fn print_nodelist(
         None => nodes_specs.collect(),
     };
 
-    crate::table::print_table(&mut table, &filtered, writer);
+    crate::table::print_table_from(&mut table, &filtered, start, writer);
 
     let final_nodes = filtered.into_iter().map(|node_spec| node_spec.0).collect();
     NodeList { items: final_nodes }
@@ -794,6 +917,7 @@ fn print_deployments(
     regex: Option<Regex>,
     sort: Option<&str>,
     reverse: bool,
+    start: usize,
     writer: &mut ClickWriter,
 ) -> DeploymentList {
     let mut table = Table::new();
@@ -897,7 +1021,7 @@ fn print_deployments(
         None => deps_specs.collect(),
     };
 
-    crate::table::print_table(&mut table, &filtered, writer);
+    crate::table::print_table_from(&mut table, &filtered, start, writer);
 
     let final_deps = filtered.into_iter().map(|dep_spec| dep_spec.0).collect();
     DeploymentList { items: final_deps }
@@ -953,6 +1077,7 @@ fn get_ports(service: &Service) -> String {
 }
 
 /// Print out the specified list of services in a pretty format
+#[allow(clippy::too_many_arguments)]
 fn print_servicelist(
     servlist: ServiceList,
     regex: Option<Regex>,
@@ -960,6 +1085,7 @@ fn print_servicelist(
     show_namespace: bool,
     sort: Option<&str>,
     reverse: bool,
+    start: usize,
     writer: &mut ClickWriter,
 ) -> ServiceList {
     let mut table = Table::new();
@@ -1085,7 +1211,7 @@ fn print_servicelist(
         None => service_specs.collect(),
     };
 
-    crate::table::print_table(&mut table, &filtered, writer);
+    crate::table::print_table_from(&mut table, &filtered, start, writer);
 
     let final_services = filtered
         .into_iter()
@@ -1119,6 +1245,51 @@ fn print_namespaces(nslist: &NamespaceList, regex: Option<Regex>, writer: &mut C
     crate::table::print_table(&mut table, &filtered, writer);
 }
 
+/// Print out the kinds of resources the server serves
+/// All the group versions served in groups, sorted, and filtered by regex if there is one
+fn api_versions(groups: &[ApiGroup], regex: Option<Regex>) -> Vec<&str> {
+    let mut versions: Vec<&str> = groups
+        .iter()
+        .flat_map(|group| group.versions.iter())
+        .map(|gv| gv.group_version.as_str())
+        .filter(|gv| regex.as_ref().map(|r| r.is_match(gv)).unwrap_or(true))
+        .collect();
+    versions.sort_unstable();
+    versions.dedup();
+    versions
+}
+
+fn print_api_resources(resources: &[ApiResource], regex: Option<Regex>, writer: &mut ClickWriter) {
+    let mut table = Table::new();
+    table.set_titles(row![
+        "Name",
+        "Short Names",
+        "API Version",
+        "Namespaced",
+        "Kind",
+        "Verbs"
+    ]);
+
+    let resource_specs = resources.iter().map(|res| {
+        let specs = vec![
+            CellSpec::new(res.name.as_str()),
+            CellSpec::new_owned(res.short_names.join(",")),
+            CellSpec::new(res.group_version.as_str()),
+            CellSpec::new(if res.namespaced { "true" } else { "false" }),
+            CellSpec::new(res.kind.as_str()),
+            CellSpec::new_owned(res.verbs.join(",")),
+        ];
+        (res, specs)
+    });
+
+    let filtered = match regex {
+        Some(r) => crate::table::filter(resource_specs, r),
+        None => resource_specs.collect(),
+    };
+
+    crate::table::print_table(&mut table, &filtered, writer);
+}
+
 // Command defintions below.  See documentation for the command! macro for an explanation of
 // arguments passed here
 
@@ -1332,7 +1503,9 @@ command!(
                 .long("reverse")
                 .help("Reverse the order of the returned list")
                 .takes_value(false)
-        ),
+        )
+        .arg(limit_arg())
+        .arg(watch_arg()),
     vec!["pods"],
     noop_complete!(),
     IntoIter::new([(
@@ -1374,23 +1547,29 @@ command!(
             }
         }
 
-        let pl: Option<PodList> = env.run_on_kluster(|k| k.get(urlstr.as_str()));
-
-        match pl {
-            Some(l) => {
-                let end_list = print_podlist(
+        let pl = get_list(
+            env,
+            urlstr.as_str(),
+            &matches,
+            writer,
+            |l: PodList, start, writer| {
+                print_podlist(
                     l,
                     matches.is_present("showlabels"),
                     matches.is_present("showannot"),
                     matches.is_present("shownode"),
                     env.namespace.is_none(),
-                    regex,
+                    regex.clone(),
                     matches.value_of("sort"),
                     matches.is_present("reverse"),
+                    start,
                     writer,
-                );
-                env.set_last_objs(end_list);
-            }
+                )
+            },
+        );
+
+        match pl {
+            Some(end_list) => env.set_last_objs(end_list),
             None => env.clear_last_objs(),
         }
     }
@@ -1409,20 +1588,59 @@ fn pick_container<'a>(obj: &'a KObj, writer: &mut ClickWriter) -> &'a str {
     }
 }
 
+/// Figure out which editor to use. editor_opt (from an --editor argument) is used if specified,
+/// otherwise the click environment editor, otherwise the $EDITOR environment variable
+fn get_editor(env: &Env, editor_opt: Option<&str>, writer: &mut ClickWriter) -> Option<String> {
+    if let Some(v) = editor_opt {
+        Some(v.to_owned())
+    } else if let Some(ref e) = env.click_config.editor {
+        Some(e.clone())
+    } else {
+        match std::env::var("EDITOR") {
+            Ok(ed) => Some(ed),
+            Err(e) => {
+                clickwriteln!(
+                    writer,
+                    "Could not get EDITOR environment \
+                     variable: {}",
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Build an expression that will run editor on the file at file_path
+fn editor_expr(editor: &str, file_path: &Path) -> duct::Expression {
+    if editor.contains(' ') {
+        // split the whitespace
+        let mut eargs: Vec<&str> = editor.split_whitespace().collect();
+        eargs.push(file_path.to_str().unwrap());
+        duct::cmd(eargs[0], &eargs[1..])
+    } else {
+        cmd!(editor, file_path)
+    }
+}
+
 #[allow(clippy::ptr_arg)]
 fn write_logs_to_file(
     env: &Env,
     path: &PathBuf,
     mut reader: BufReader<Response>,
+    filter: &LogFilter,
 ) -> Result<(), KubeError> {
     let mut file = std::fs::File::create(path)?;
-    let mut buffer = [0; 1024];
+    let mut line = Vec::new();
     while !env.ctrlcbool.load(Ordering::SeqCst) {
-        let amt = reader.read(&mut buffer[..])?;
+        line.clear();
+        let amt = reader.read_until(b'\n', &mut line)?;
         if amt == 0 {
             break;
         }
-        file.write_all(&buffer[0..amt])?;
+        if filter.keep(&String::from_utf8_lossy(&line)) {
+            file.write_all(&line)?;
+        }
     }
     file.flush().map_err(KubeError::from)
 }
@@ -1437,6 +1655,7 @@ fn do_logs(
     editor: bool,
     editor_opt: Option<&str>,
     timeout: Option<Duration>,
+    filter: &LogFilter,
     writer: &mut ClickWriter,
 ) {
     let cont = cont_opt.unwrap_or_else(|| pick_container(obj, writer));
@@ -1464,7 +1683,7 @@ fn do_logs(
             match strfmt(output, &fmtvars) {
                 Ok(file_path) => {
                     let pbuf = file_path.into();
-                    match write_logs_to_file(env, &pbuf, reader) {
+                    match write_logs_to_file(env, &pbuf, reader, filter) {
                         Ok(_) => {
                             println!("Wrote logs to {}", pbuf.to_str().unwrap());
                         }
@@ -1481,23 +1700,9 @@ fn do_logs(
             }
         } else if editor {
             // We're opening in an editor, save to a temp
-            let editor = if let Some(v) = editor_opt {
-                v.to_owned()
-            } else if let Some(ref e) = env.click_config.editor {
-                e.clone()
-            } else {
-                match std::env::var("EDITOR") {
-                    Ok(ed) => ed,
-                    Err(e) => {
-                        clickwriteln!(
-                            writer,
-                            "Could not get EDITOR environment \
-                             variable: {}",
-                            e
-                        );
-                        return;
-                    }
-                }
+            let editor = match get_editor(env, editor_opt, writer) {
+                Some(e) => e,
+                None => return,
             };
             let tmpdir = match env.tempdir {
                 Ok(ref td) => td,
@@ -1512,30 +1717,27 @@ fn do_logs(
                 cont,
                 Local::now().to_rfc3339()
             ));
-            if let Err(e) = write_logs_to_file(env, &file_path, reader) {
+            if let Err(e) = write_logs_to_file(env, &file_path, reader, filter) {
                 clickwriteln!(writer, "Error writing logs to file: {}", e);
                 return;
             }
 
             clickwriteln!(writer, "Logs downloaded, starting editor");
-            let expr = if editor.contains(' ') {
-                // split the whitespace
-                let mut eargs: Vec<&str> = editor.split_whitespace().collect();
-                eargs.push(file_path.to_str().unwrap());
-                duct::cmd(eargs[0], &eargs[1..])
-            } else {
-                cmd!(editor, file_path)
-            };
+            let expr = editor_expr(&editor, &file_path);
             if let Err(e) = expr.start() {
                 clickwriteln!(writer, "Could not start editor: {}", e);
             }
         } else {
             let (sender, receiver) = channel();
+            let filter = filter.clone();
             thread::spawn(move || {
                 loop {
                     let mut line = String::new();
                     if let Ok(amt) = reader.read_line(&mut line) {
                         if amt > 0 {
+                            if !filter.keep(&line) {
+                                continue;
+                            }
                             if sender.send(line).is_err() {
                                 // probably user hit ctrl-c, just stop
                                 break;
@@ -1579,7 +1781,22 @@ command!(
             Arg::with_name("follow")
                 .short("f")
                 .long("follow")
-                .help("Follow the logs as new records arrive (stop with ^C)")
+                .help(
+                    "Follow the logs as new records arrive (stop with ^C). On a range, all the \
+                     pods are followed at once",
+                )
+                .conflicts_with("editor")
+                .conflicts_with("output")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("allcontainers")
+                .long("all-containers")
+                .help(
+                    "Get logs from all the containers in the pod(s) at once. Each line is \
+                     prefixed with the pod/container it came from",
+                )
+                .conflicts_with("container")
                 .conflicts_with("editor")
                 .conflicts_with("output")
                 .takes_value(false),
@@ -1621,6 +1838,26 @@ command!(
                 )
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("timestamps")
+                .long("timestamps")
+                .help("Include a timestamp at the start of each line")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("grep")
+                .long("grep")
+                .validator(valid_regex)
+                .help("Only show lines that match the specified regex")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .validator(valid_regex)
+                .help("Don't show lines that match the specified regex")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("editor")
                 .long("editor")
@@ -1662,6 +1899,9 @@ command!(
         if matches.is_present("previous") {
             url_args.push_str("&previous=true");
         }
+        if matches.is_present("timestamps") {
+            url_args.push_str("&timestamps=true");
+        }
         if matches.is_present("tail") {
             url_args.push_str(format!("&tailLines={}", matches.value_of("tail").unwrap()).as_str());
         }
@@ -1682,6 +1922,60 @@ command!(
             Some(Duration::new(20, 0)) // TODO what's a reasonable timeout here?
         };
 
+        // all unwraps already validated
+        let filter = LogFilter {
+            grep: matches.value_of("grep").map(|re| Regex::new(re).unwrap()),
+            exclude: matches
+                .value_of("exclude")
+                .map(|re| Regex::new(re).unwrap()),
+        };
+
+        let all_containers = matches.is_present("allcontainers");
+        let is_range = matches!(env.current_selection(), ObjectSelection::Range(_));
+        if all_containers || (is_range && matches.is_present("follow")) {
+            // open all the streams at once, and interleave the lines
+            let mut sources = vec![];
+            env.apply_to_selection(writer, None, |obj, writer| match obj.typ {
+                ObjType::Pod { ref containers } => {
+                    let conts = if all_containers {
+                        containers.iter().map(|c| c.as_str()).collect()
+                    } else {
+                        vec![matches
+                            .value_of("container")
+                            .unwrap_or_else(|| pick_container(obj, writer))]
+                    };
+                    for cont in conts.into_iter() {
+                        if containers.iter().any(|c| c == cont) {
+                            sources.push(LogSource {
+                                namespace: obj.namespace.clone().unwrap(),
+                                pod: obj.name().to_owned(),
+                                container: cont.to_owned(),
+                            });
+                        } else {
+                            clickwriteln!(writer, "{} has no container {}", obj.name(), cont);
+                        }
+                    }
+                }
+                _ => {
+                    clickwriteln!(writer, "Logs only available on a pod");
+                }
+            });
+            if !sources.is_empty() {
+                if let Some(connector) = env.run_on_kluster(|k| Ok(k.connector())) {
+                    logs::stream_logs(
+                        &connector,
+                        &sources,
+                        &url_args,
+                        timeout,
+                        &filter,
+                        &env.ctrlcbool,
+                        writer,
+                    );
+                }
+            }
+            return;
+        }
+
         env.apply_to_selection(
             writer,
             Some(&env.click_config.range_separator),
@@ -1696,6 +1990,7 @@ command!(
                         matches.is_present("editor"),
                         matches.value_of("editor"),
                         timeout,
+                        &filter,
                         writer,
                     );
                 } else {
@@ -1737,13 +2032,240 @@ command!(
     }
 );
 
+static EDIT_HEADER: &str =
+    "# Please edit the object below. Lines beginning with a '#' will be ignored,
+# and an empty file will abort the edit. If an error occurs while saving this file will be
+# reopened with the relevant failures.
+#
+";
+
+/// Remove the block of comment lines at the top of an edited file
+fn strip_edit_header(contents: &str) -> &str {
+    let mut rest = contents;
+    while rest.starts_with('#') {
+        rest = match rest.find('\n') {
+            Some(pos) => &rest[(pos + 1)..],
+            None => "",
+        };
+    }
+    rest
+}
+
+/// Build the contents for the edit file, with any errors from the last attempt listed at the top
+fn edit_contents(body: &str, err: Option<&KubeError>) -> String {
+    let mut contents = EDIT_HEADER.to_string();
+    if let Some(e) = err {
+        let msg = e.to_string();
+        for line in msg.lines() {
+            contents.push_str("# ");
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        if msg.contains("the object has been modified") {
+            contents.push_str(
+                "# The object was changed on the server while you were editing it. Remove\n\
+                 # metadata.resourceVersion to overwrite those changes, or cancel and edit again.\n",
+            );
+        }
+        contents.push_str("#\n");
+    }
+    contents.push_str(body);
+    contents
+}
+
+/// What came back from the editor
+enum EditOutcome {
+    /// nothing to save, and why
+    Cancelled(&'static str),
+    Changed(Value),
+    Invalid(KubeError),
+}
+
+/// Work out what to do with body, the edited object with the header stripped, given that it
+/// started out as orig
+fn parse_edit(body: &str, orig: &Value) -> EditOutcome {
+    if body.trim().is_empty() {
+        return EditOutcome::Cancelled("file was empty");
+    }
+    match serde_yaml::from_str::<Value>(body) {
+        Ok(ref new_val) if new_val == orig => EditOutcome::Cancelled("no changes made"),
+        Ok(new_val) => EditOutcome::Changed(new_val),
+        Err(e) => EditOutcome::Invalid(KubeError::from(e)),
+    }
+}
+
+fn edit_obj(env: &Env, obj: &KObj, editor_opt: Option<&str>, writer: &mut ClickWriter) {
+    let namespace = match obj.typ {
+        ObjType::Node => "",
+        ObjType::Dynamic(ref res) if !res.namespaced => "",
+        _ => match obj.namespace {
+            Some(ref ns) => ns,
+            None => {
+                clickwriteln!(writer, "Don't know namespace for {}", obj.name());
+                return;
+            }
+        },
+    };
+    let kluster = match env.kluster {
+        Some(ref k) => k,
+        None => {
+            clickwriteln!(writer, "Need to have an active context");
+            return;
+        }
+    };
+    let url = match obj.url(kluster, namespace) {
+        Ok(url) => url,
+        Err(e) => {
+            clickwriteln!(
+                writer,
+                "Failed to edit {} {}: {}",
+                obj.type_str(),
+                obj.name(),
+                e
+            );
+            return;
+        }
+    };
+    let orig = match kluster.get_value(url.as_str()) {
+        Ok(v) => v,
+        Err(e) => {
+            clickwriteln!(
+                writer,
+                "Failed to fetch {} {}: {}",
+                obj.type_str(),
+                obj.name(),
+                e
+            );
+            return;
+        }
+    };
+    let editor = match get_editor(env, editor_opt, writer) {
+        Some(e) => e,
+        None => return,
+    };
+    let tmpdir = match env.tempdir {
+        Ok(ref td) => td,
+        Err(ref e) => {
+            clickwriteln!(writer, "Failed to create tempdir: {}", e);
+            return;
+        }
+    };
+    let file_path = tmpdir
+        .path()
+        .join(format!("{}_{}.yaml", obj.type_str(), obj.name()));
+    let mut contents = match serde_yaml::to_string(&orig) {
+        Ok(yaml) => edit_contents(&yaml, None),
+        Err(e) => {
+            clickwriteln!(writer, "Failed to convert object to yaml: {}", e);
+            return;
+        }
+    };
+
+    loop {
+        if let Err(e) = std::fs::write(&file_path, &contents) {
+            clickwriteln!(writer, "Failed to write {}: {}", file_path.display(), e);
+            return;
+        }
+        if let Err(e) = editor_expr(&editor, &file_path).run() {
+            clickwriteln!(writer, "Could not run editor: {}", e);
+            return;
+        }
+        let edited = match std::fs::read_to_string(&file_path) {
+            Ok(e) => e,
+            Err(e) => {
+                clickwriteln!(writer, "Failed to read {}: {}", file_path.display(), e);
+                return;
+            }
+        };
+        let body = strip_edit_header(&edited);
+        let result = match parse_edit(body, &orig) {
+            EditOutcome::Cancelled(why) => {
+                clickwriteln!(writer, "Edit cancelled, {}", why);
+                return;
+            }
+            EditOutcome::Changed(new_val) => kluster.put(url.as_str(), &new_val.to_string()),
+            EditOutcome::Invalid(e) => Err(e),
+        };
+
+        match result {
+            Ok(_) => {
+                clickwriteln!(writer, "{} {} edited", obj.type_str(), obj.name());
+                return;
+            }
+            Err(e) => {
+                clickwriteln!(
+                    writer,
+                    "Failed to save {} {}: {}",
+                    obj.type_str(),
+                    obj.name(),
+                    e
+                );
+                clickwrite!(writer, "Reopen editor with your changes [y/N]? ");
+                io::stdout().flush().expect("Could not flush stdout");
+                let mut conf = String::new();
+                if io::stdin().read_line(&mut conf).is_ok()
+                    && (conf.trim() == "y" || conf.trim() == "yes")
+                {
+                    contents = edit_contents(body, Some(&e));
+                } else {
+                    clickwriteln!(
+                        writer,
+                        "Not saving. Your edited copy is at {}",
+                        file_path.display()
+                    );
+                    return;
+                }
+            }
+        }
+    }
+}
+
+command!(
+    Edit,
+    "edit",
+    "Edit the active object in an editor, and save the result back to the cluster",
+    |clap: App<'static, 'static>| clap
+        .arg(
+            Arg::with_name("editor")
+                .long("editor")
+                .short("e")
+                .help(
+                    "Use the specified editor command. Without this option, the click \
+                     environment editor (see set/env commands) is used, otherwise the $EDITOR \
+                     environment variable is used."
+                )
+                .takes_value(true)
+        )
+        .after_help(
+            "The object is opened as yaml. When you save and exit the editor, the changed object \
+is sent to the cluster. If the cluster rejects it (for example because it's invalid, or because \
+the object was modified while you were editing it), you can reopen the editor with your changes \
+intact.
+
+If a range is selected, each object in the range is edited in turn."
+        ),
+    vec!["edit"],
+    noop_complete!(),
+    no_named_complete!(),
+    |matches, env, writer| {
+        env.apply_to_selection(
+            writer,
+            Some(&env.click_config.range_separator),
+            |obj, writer| {
+                edit_obj(env, obj, matches.value_of("editor"), writer);
+            },
+        );
+    }
+);
+
 #[allow(clippy::too_many_arguments)]
 fn do_exec(
     env: &Env,
     pod: &KObj,
-    kluster_name: &str,
+    kluster: &Kluster,
     cmd: &[&str],
-    it_arg: &str,
+    tty: bool,
+    stdin: bool,
     cont_opt: &Option<&str>,
     term_opt: &Option<&str>,
     do_terminal: bool,
@@ -1758,13 +2280,19 @@ fn do_exec(
         } else {
             "xterm -e"
         };
+        let it_arg = match (tty, stdin) {
+            (true, true) => "-it",
+            (true, false) => "-t",
+            (false, true) => "-i",
+            (false, false) => "",
+        };
         let mut targs: Vec<&str> = terminal.split_whitespace().collect();
         let mut kubectl_args = vec![
             "kubectl",
             "--namespace",
             ns,
             "--context",
-            kluster_name,
+            &kluster.name,
             "exec",
             it_arg,
             pod.name(),
@@ -1781,35 +2309,24 @@ fn do_exec(
             clickwriteln!(writer, "Could not launch in terminal: {}", e);
         }
     } else {
-        let mut command = Command::new("kubectl");
-        command
-            .arg("--namespace")
-            .arg(ns)
-            .arg("--context")
-            .arg(kluster_name)
-            .arg("exec")
-            .arg(it_arg)
-            .arg(pod.name());
-        let command = if let Some(cont) = cont_opt {
-            command.arg("-c").arg(cont).arg("--").args(cmd)
-        } else {
-            command.arg("--").args(cmd)
-        };
-        match command.status() {
-            Ok(s) => {
-                if !s.success() {
-                    writeln!(stderr(), "kubectl exited abnormally").unwrap_or(());
-                }
+        let cont = cont_opt.unwrap_or_else(|| pick_container(pod, writer));
+        match crate::exec::exec(
+            kluster,
+            ns,
+            pod.name(),
+            Some(cont),
+            cmd,
+            tty,
+            stdin,
+            &env.ctrlcbool,
+            writer,
+        ) {
+            Ok(Some(0)) | Ok(None) => {}
+            Ok(Some(code)) => {
+                writeln!(stderr(), "command terminated with exit code {}", code).unwrap_or(());
             }
             Err(e) => {
-                if let io::ErrorKind::NotFound = e.kind() {
-                    writeln!(
-                        stderr(),
-                        "Could not find kubectl binary. Is it in your PATH?"
-                    )
-                    .unwrap_or(());
-                    return;
-                }
+                writeln!(stderr(), "Exec failed: {}", e).unwrap_or(());
             }
         }
     }
@@ -1842,7 +2359,8 @@ command!(
                     "Run the command in a new terminal.  With --terminal ARG, ARG is used as the \
                      terminal command, otherwise the default is used ('set terminal <value>' to \
                      specify default). If a range of objects is selected, a new terminal is opened \
-                     for each object."
+                     for each object. Note that the new terminal runs kubectl, so it must be in \
+                     your PATH."
                 )
                 .takes_value(true)
                 .min_values(0)
@@ -1895,12 +2413,6 @@ command!(
             } else {
                 true
             };
-            let it_arg = match (tty, stdin) {
-                (true, true) => "-it",
-                (true, false) => "-t",
-                (false, true) => "-i",
-                (false, false) => "",
-            };
             env.apply_to_selection(
                 writer,
                 Some(&env.click_config.range_separator),
@@ -1909,9 +2421,10 @@ command!(
                         do_exec(
                             env,
                             obj,
-                            &kluster.name,
+                            kluster,
                             &cmd,
-                            it_arg,
+                            tty,
+                            stdin,
                             &matches.value_of("container"),
                             &matches.value_of("terminal"),
                             matches.is_present("terminal"),
@@ -1933,6 +2446,7 @@ fn delete_obj(env: &Env, obj: &KObj, delete_body: &str, writer: &mut ClickWriter
     let name = obj.name();
     let namespace = match obj.typ {
         ObjType::Node => "",
+        ObjType::Dynamic(ref res) if !res.namespaced => "",
         _ => match obj.namespace {
             Some(ref ns) => ns,
             None => {
@@ -1946,7 +2460,10 @@ fn delete_obj(env: &Env, obj: &KObj, delete_body: &str, writer: &mut ClickWriter
     let mut conf = String::new();
     if io::stdin().read_line(&mut conf).is_ok() {
         if conf.trim() == "y" || conf.trim() == "yes" {
-            let url = obj.url(namespace);
+            let url = match env.run_on_kluster(|k| obj.url(k, namespace)) {
+                Some(url) => url,
+                None => return,
+            };
             let body = if obj.is(ObjType::Service) {
                 None
             } else {
@@ -2058,6 +2575,1117 @@ command!(
     }
 );
 
+/// Wait for the object at url to report that it has replicas available replicas, or until timeout
+/// or Ctrl-C
+fn wait_for_replicas(
+    env: &Env,
+    obj: &KObj,
+    url: &str,
+    replicas: u32,
+    timeout: Duration,
+    writer: &mut ClickWriter,
+) {
+    let start = std::time::Instant::now();
+    env.ctrlcbool.store(false, Ordering::SeqCst);
+    let mut last_available = None;
+    while !env.ctrlcbool.load(Ordering::SeqCst) {
+        let status = match env.run_on_kluster(|k| k.get_value(url)) {
+            Some(mut val) => match val.get_mut("status").map(Value::take) {
+                Some(status) => serde_json::from_value::<DeploymentStatus>(status).ok(),
+                None => None,
+            },
+            None => return,
+        };
+        if let Some(status) = status {
+            if status.replicas == replicas && status.available == replicas {
+                clickwriteln!(
+                    writer,
+                    "{} {} has {} available replicas",
+                    obj.type_str(),
+                    obj.name(),
+                    replicas
+                );
+                return;
+            }
+            if last_available != Some(status.available) {
+                clickwriteln!(
+                    writer,
+                    "Waiting for {} {}: {} of {} replicas available",
+                    obj.type_str(),
+                    obj.name(),
+                    status.available,
+                    replicas
+                );
+                last_available = Some(status.available);
+            }
+        }
+        if start.elapsed() >= timeout {
+            clickwriteln!(
+                writer,
+                "Timed out waiting for {} {} to have {} available replicas",
+                obj.type_str(),
+                obj.name(),
+                replicas
+            );
+            return;
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+    clickwriteln!(writer, "Stopped waiting");
+}
+
+/// The number of replicas to scale to from current. Err has the target if it's not a valid number
+/// of replicas.
+fn scale_target(current: i64, replicas: i64, relative: bool) -> Result<i64, i64> {
+    let target = if relative {
+        current + replicas
+    } else {
+        replicas
+    };
+    if target < 0 || target > i64::from(i32::MAX) {
+        Err(target)
+    } else {
+        Ok(target)
+    }
+}
+
+fn scale_obj(
+    env: &Env,
+    obj: &KObj,
+    replicas: i64,
+    relative: bool,
+    wait: Option<Duration>,
+    writer: &mut ClickWriter,
+) {
+    match obj.typ {
+        ObjType::Deployment | ObjType::StatefulSet | ObjType::ReplicaSet => {}
+        _ => {
+            clickwriteln!(writer, "Can't scale a {}", obj.type_str());
+            return;
+        }
+    }
+    let url = match obj.namespace {
+        Some(ref ns) => match env.run_on_kluster(|k| obj.url(k, ns)) {
+            Some(url) => url,
+            None => return,
+        },
+        None => {
+            clickwriteln!(writer, "Don't know namespace for {}", obj.name());
+            return;
+        }
+    };
+    let scale_url = format!("{}/scale", url);
+
+    let current = match env.run_on_kluster(|k| k.get_value(scale_url.as_str())) {
+        Some(scale) => val_u64("/spec/replicas", &scale, 0) as i64,
+        None => return,
+    };
+    let target = match scale_target(current, replicas, relative) {
+        Ok(target) => target,
+        Err(target) => {
+            clickwriteln!(
+                writer,
+                "Can't scale {} {} from {} to {} replicas",
+                obj.type_str(),
+                obj.name(),
+                current,
+                target
+            );
+            return;
+        }
+    };
+
+    let patch = json!({"spec": {"replicas": target}}).to_string();
+    let res = env.run_on_kluster(|k| k.patch(scale_url.as_str(), &patch, PatchType::Merge));
+    if res.is_some() {
+        clickwriteln!(
+            writer,
+            "Scaled {} {} from {} to {} replicas",
+            obj.type_str(),
+            obj.name(),
+            current,
+            target
+        );
+        if let Some(timeout) = wait {
+            wait_for_replicas(env, obj, url.as_str(), target as u32, timeout, writer);
+        }
+    }
+}
+
+/// a clap validator for relative replica counts like +2 or -1
+fn valid_relative(s: String) -> Result<(), String> {
+    if s.starts_with('+') || s.starts_with('-') {
+        s[1..].parse::<u32>().map(|_| ()).map_err(|e| e.to_string())
+    } else {
+        Err("Relative replica counts must start with + or -".to_owned())
+    }
+}
+
+command!(
+    Scale,
+    "scale",
+    "Scale the active deployment, statefulset or replicaset",
+    |clap: App<'static, 'static>| clap
+        .arg(
+            Arg::with_name("replicas")
+                .help("The number of replicas to scale to")
+                .validator(valid_u32)
+                .required_unless("relative")
+                .conflicts_with("relative")
+                .index(1)
+        )
+        .arg(
+            Arg::with_name("relative")
+                .short("r")
+                .long("relative")
+                .help("Scale relative to the current number of replicas (i.e. +2 or -1)")
+                .validator(valid_relative)
+                .allow_hyphen_values(true)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("wait")
+                .short("w")
+                .long("wait")
+                .help(
+                    "Wait until the new number of replicas are available (Ctrl-C to stop waiting)"
+                )
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .short("t")
+                .long("timeout")
+                .help(
+                    "When waiting, give up after this long. Specify as a duration, \
+                     i.e. 30s or 5m (default: 5m)"
+                )
+                .validator(valid_duration)
+                .requires("wait")
+                .takes_value(true)
+        ),
+    vec!["scale"],
+    noop_complete!(),
+    no_named_complete!(),
+    |matches, env, writer| {
+        // safe unwraps as validated
+        let (replicas, relative) = match matches.value_of("relative") {
+            Some(rel) => {
+                let amount = i64::from(rel[1..].parse::<u32>().unwrap());
+                if rel.starts_with('-') {
+                    (-amount, true)
+                } else {
+                    (amount, true)
+                }
+            }
+            None => (
+                i64::from(
+                    matches
+                        .value_of("replicas")
+                        .unwrap()
+                        .parse::<u32>()
+                        .unwrap(),
+                ),
+                false,
+            ),
+        };
+        let wait = if matches.is_present("wait") {
+            Some(
+                matches
+                    .value_of("timeout")
+                    .map(|t| parse_duration(t).unwrap())
+                    .unwrap_or_else(|| Duration::from_secs(300)),
+            )
+        } else {
+            None
+        };
+        env.apply_to_selection(
+            writer,
+            Some(&env.click_config.range_separator),
+            |obj, writer| {
+                scale_obj(env, obj, replicas, relative, wait, writer);
+            },
+        );
+    }
+);
+
+/// Parse label or annotation changes. key=value sets key to value, and key- removes key. Returns
+/// a list of (key, Some(value)) to set and (key, None) to remove
+fn parse_metadata_changes<'a>(
+    args: impl Iterator<Item = &'a str>,
+) -> Result<Vec<(&'a str, Option<&'a str>)>, String> {
+    args.map(|arg| match arg.find('=') {
+        Some(0) => Err(format!("Invalid change, no key: {}", arg)),
+        Some(pos) => Ok((&arg[..pos], Some(&arg[(pos + 1)..]))),
+        None if arg.len() > 1 && arg.ends_with('-') => Ok((&arg[..(arg.len() - 1)], None)),
+        None => Err(format!(
+            "Invalid change, should be key=value or key-: {}",
+            arg
+        )),
+    })
+    .collect()
+}
+
+/// Apply changes (see parse_metadata_changes) to the labels or annotations of obj, as a merge patch
+/// to metadata.<field>. Unless overwrite is true, won't change the value of a key that's already
+/// set.
+fn update_metadata(
+    env: &Env,
+    obj: &KObj,
+    field: &str,
+    changes: &[(&str, Option<&str>)],
+    overwrite: bool,
+    writer: &mut ClickWriter,
+) {
+    let namespace = match obj.typ {
+        ObjType::Node => "",
+        ObjType::Dynamic(ref res) if !res.namespaced => "",
+        _ => match obj.namespace {
+            Some(ref ns) => ns,
+            None => {
+                clickwriteln!(writer, "Don't know namespace for {}", obj.name());
+                return;
+            }
+        },
+    };
+    let url = match env.run_on_kluster(|k| obj.url(k, namespace)) {
+        Some(url) => url,
+        None => return,
+    };
+    if !overwrite {
+        let current = match env.run_on_kluster(|k| k.get_value(url.as_str())) {
+            Some(val) => val,
+            None => return,
+        };
+        for (key, value) in changes.iter() {
+            if let Some(value) = value {
+                let pointer = format!("/metadata/{}/{}", field, key.replace('/', "~1"));
+                if let Some(existing) = val_str_opt(&pointer, &current) {
+                    if existing != *value {
+                        clickwriteln!(
+                            writer,
+                            "{} {} already has a value ({}) for {}, and --overwrite is not set",
+                            obj.type_str(),
+                            obj.name(),
+                            existing,
+                            key
+                        );
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    let values: serde_json::Map<String, Value> = changes
+        .iter()
+        .map(|(key, value)| (key.to_string(), json!(value)))
+        .collect();
+    let mut metadata = serde_json::Map::new();
+    metadata.insert(field.to_owned(), Value::Object(values));
+    let patch = json!({ "metadata": metadata }).to_string();
+    if env
+        .run_on_kluster(|k| k.patch(url.as_str(), &patch, PatchType::Merge))
+        .is_some()
+    {
+        let verb = if field == "labels" {
+            "labeled"
+        } else {
+            "annotated"
+        };
+        clickwriteln!(writer, "{} {} {}", obj.type_str(), obj.name(), verb);
+    }
+}
+
+/// The arguments shared by label and annotate
+fn metadata_change_args(clap: App<'static, 'static>, what: &'static str) -> App<'static, 'static> {
+    clap.arg(
+        Arg::with_name("changes")
+            .help(what)
+            .required(true)
+            .multiple(true)
+            .index(1),
+    )
+    .arg(
+        Arg::with_name("overwrite")
+            .long("overwrite")
+            .help("Allow changing the value of keys that are already set")
+            .takes_value(false),
+    )
+}
+
+/// Run a label or annotate command on the current selection
+fn run_metadata_changes(field: &str, matches: &ArgMatches, env: &Env, writer: &mut ClickWriter) {
+    let changes = match parse_metadata_changes(matches.values_of("changes").unwrap()) {
+        Ok(changes) => changes,
+        Err(e) => {
+            clickwriteln!(writer, "{}", e);
+            return;
+        }
+    };
+    let overwrite = matches.is_present("overwrite");
+    env.apply_to_selection(writer, None, |obj, writer| {
+        update_metadata(env, obj, field, &changes, overwrite, writer);
+    });
+}
+
+command!(
+    Label,
+    "label",
+    "Add, change or remove labels on the active object(s)",
+    |clap| metadata_change_args(
+        clap,
+        "Labels to change. Use key=value to set a label, and key- to remove it"
+    ),
+    vec!["label"],
+    noop_complete!(),
+    no_named_complete!(),
+    |matches, env, writer| {
+        run_metadata_changes("labels", &matches, env, writer);
+    }
+);
+
+command!(
+    Annotate,
+    "annotate",
+    "Add, change or remove annotations on the active object(s)",
+    |clap| metadata_change_args(
+        clap,
+        "Annotations to change. Use key=value to set an annotation, and key- to remove it"
+    ),
+    vec!["annotate"],
+    noop_complete!(),
+    no_named_complete!(),
+    |matches, env, writer| {
+        run_metadata_changes("annotations", &matches, env, writer);
+    }
+);
+
+/// Cordon or uncordon the nodes in the current selection
+fn cordon_selection(env: &Env, unschedulable: bool, writer: &mut ClickWriter) {
+    let what = if unschedulable {
+        "cordoned"
+    } else {
+        "uncordoned"
+    };
+    env.apply_to_selection(writer, None, |obj, writer| {
+        if let ObjType::Node = obj.typ {
+            if env
+                .run_on_kluster(|k| drain::set_unschedulable(k, obj.name(), unschedulable))
+                .is_some()
+            {
+                clickwriteln!(writer, "Node {} {}", obj.name(), what);
+            }
+        } else {
+            clickwriteln!(writer, "Only nodes can be {}", what);
+        }
+    });
+}
+
+command!(
+    Cordon,
+    "cordon",
+    "Mark the active node(s) as unschedulable",
+    identity,
+    vec!["cordon"],
+    noop_complete!(),
+    no_named_complete!(),
+    |_matches, env, writer| {
+        cordon_selection(env, true, writer);
+    }
+);
+
+command!(
+    Uncordon,
+    "uncordon",
+    "Mark the active node(s) as schedulable",
+    identity,
+    vec!["uncordon"],
+    noop_complete!(),
+    no_named_complete!(),
+    |_matches, env, writer| {
+        cordon_selection(env, false, writer);
+    }
+);
+
+command!(
+    Drain,
+    "drain",
+    "Cordon the active node(s) and evict all their pods (stop with ^C)",
+    |clap: App<'static, 'static>| {
+        clap
+        .arg(
+            Arg::with_name("ignoredaemonsets")
+                .long("ignore-daemonsets")
+                .help("Leave pods managed by DaemonSets where they are, rather than refusing to drain")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("deleteemptydirdata")
+                .long("delete-emptydir-data")
+                .help("Evict pods using emptyDir volumes, even though their data will be lost")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Evict pods that aren't managed by a controller, so won't be recreated")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .short("t")
+                .long("timeout")
+                .help(
+                    "Give up draining a node after this long. Specify as a duration, \
+                     i.e. 30s or 5m (default: wait forever)"
+                )
+                .validator(valid_duration)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("dryrun")
+                .long("dry-run")
+                .help("Only list the pods that would be evicted")
+                .takes_value(false)
+        )
+    },
+    vec!["drain"],
+    noop_complete!(),
+    no_named_complete!(),
+    |matches, env, writer| {
+        let opts = DrainOptions {
+            ignore_daemonsets: matches.is_present("ignoredaemonsets"),
+            delete_emptydir_data: matches.is_present("deleteemptydirdata"),
+            force: matches.is_present("force"),
+            // safe unwrap as validated
+            timeout: matches
+                .value_of("timeout")
+                .map(|t| parse_duration(t).unwrap()),
+            dry_run: matches.is_present("dryrun"),
+        };
+        env.ctrlcbool.store(false, Ordering::SeqCst);
+        env.apply_to_selection(writer, None, |obj, writer| {
+            if env.ctrlcbool.load(Ordering::SeqCst) {
+                // stopped while draining a previous node
+                return;
+            }
+            if let ObjType::Node = obj.typ {
+                if let Some(ref k) = env.kluster {
+                    if let Err(e) = drain::drain(k, obj.name(), &opts, &env.ctrlcbool, writer) {
+                        clickwriteln!(writer, "Failed to drain node {}: {}", obj.name(), e);
+                    }
+                } else {
+                    clickwriteln!(writer, "Need to have an active context");
+                }
+            } else {
+                clickwriteln!(writer, "Only nodes can be drained");
+            }
+        });
+    }
+);
+
+command!(
+    Apply,
+    "apply",
+    "Create or update the objects in manifest files, using server-side apply",
+    |clap: App<'static, 'static>| clap
+        .arg(
+            Arg::with_name("filename")
+                .short("f")
+                .long("filename")
+                .help(
+                    "A file or directory of manifests to apply, or - to read them from stdin. \
+                     Can be specified more than once"
+                )
+                .required(true)
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("dryrun")
+                .long("dry-run")
+                .help("With server, the server says what would happen, but nothing is changed")
+                .possible_values(&["none", "server"])
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("forceconflicts")
+                .long("force-conflicts")
+                .help("Take over fields that other managers (like kubectl) set")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("prune")
+                .long("prune")
+                .help(
+                    "Delete objects that match --selector, and were applied by click before, \
+                     but aren't in the manifests"
+                )
+                .requires("selector")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("selector")
+                .short("l")
+                .long("selector")
+                .help("The label selector for --prune (example: app=kinesis2prom)")
+                .takes_value(true)
+        ),
+    vec!["apply"],
+    noop_complete!(),
+    no_named_complete!(),
+    |matches, env, writer| {
+        let kluster = match env.kluster {
+            Some(ref k) => k,
+            None => {
+                clickwriteln!(writer, "Need to have an active context");
+                return;
+            }
+        };
+        let mut objects = vec![];
+        for filename in matches.values_of("filename").unwrap() {
+            match apply::read_manifests(filename) {
+                Ok(objs) => objects.extend(objs),
+                Err(e) => {
+                    clickwriteln!(writer, "Failed to read manifests from {}: {}", filename, e);
+                    return;
+                }
+            }
+        }
+        let discovery = match kluster.discovery() {
+            Ok(d) => d,
+            Err(e) => {
+                clickwriteln!(writer, "Failed to discover api resources: {}", e);
+                return;
+            }
+        };
+
+        let dry_run = matches.value_of("dryrun") == Some("server");
+        let suffix = if dry_run { " (server dry run)" } else { "" };
+        let namespace = env.namespace.as_deref().unwrap_or("default");
+        let mut applied = vec![];
+        let mut failed = false;
+        for obj in objects.iter() {
+            match apply::apply_object(
+                kluster,
+                &discovery,
+                obj,
+                namespace,
+                matches.is_present("forceconflicts"),
+                dry_run,
+            ) {
+                Ok(app) => {
+                    clickwriteln!(
+                        writer,
+                        "{} {}{}",
+                        apply::display_name(&app.resource, &app.name),
+                        app.result,
+                        suffix
+                    );
+                    applied.push(app);
+                }
+                Err(e) => {
+                    clickwriteln!(
+                        writer,
+                        "Failed to apply {} {}: {}",
+                        val_str("/kind", obj, ""),
+                        val_str("/metadata/name", obj, ""),
+                        e
+                    );
+                    failed = true;
+                }
+            }
+        }
+
+        if matches.is_present("prune") {
+            if failed {
+                clickwriteln!(writer, "Not pruning, as some objects failed to apply");
+            } else if let Err(e) = apply::prune(
+                kluster,
+                &applied,
+                matches.value_of("selector").unwrap(),
+                dry_run,
+                writer,
+            ) {
+                clickwriteln!(writer, "Failed to prune: {}", e);
+            }
+        }
+    }
+);
+
+/// How an object is shown in a diff, without the fields that are noise there
+fn diff_yaml(obj: &Value) -> Result<String, serde_yaml::Error> {
+    let mut obj = apply::without_write_metadata(obj);
+    if let Some(fields) = obj.as_object_mut() {
+        fields.remove("status");
+    }
+    serde_yaml::to_string(&obj)
+}
+
+command!(
+    Diff,
+    "diff",
+    "Show how applying the objects in manifest files would change the live objects",
+    |clap: App<'static, 'static>| clap
+        .arg(
+            Arg::with_name("filename")
+                .short("f")
+                .long("filename")
+                .help(
+                    "A file or directory of manifests to diff, or - to read them from stdin. \
+                     Can be specified more than once"
+                )
+                .required(true)
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("forceconflicts")
+                .long("force-conflicts")
+                .help(
+                    "Show the result of taking over fields that other managers (like kubectl) set"
+                )
+                .takes_value(false)
+        ),
+    vec!["diff"],
+    noop_complete!(),
+    no_named_complete!(),
+    |matches, env, writer| {
+        let kluster = match env.kluster {
+            Some(ref k) => k,
+            None => {
+                clickwriteln!(writer, "Need to have an active context");
+                return;
+            }
+        };
+        let mut objects = vec![];
+        for filename in matches.values_of("filename").unwrap() {
+            match apply::read_manifests(filename) {
+                Ok(objs) => objects.extend(objs),
+                Err(e) => {
+                    clickwriteln!(writer, "Failed to read manifests from {}: {}", filename, e);
+                    return;
+                }
+            }
+        }
+        let discovery = match kluster.discovery() {
+            Ok(d) => d,
+            Err(e) => {
+                clickwriteln!(writer, "Failed to discover api resources: {}", e);
+                return;
+            }
+        };
+
+        let namespace = env.namespace.as_deref().unwrap_or("default");
+        let force = matches.is_present("forceconflicts");
+        let mut any_diff = false;
+        for obj in objects.iter() {
+            let res = apply::resolve(kluster, &discovery, obj, namespace).and_then(|target| {
+                let live = apply::get_live(kluster, &target.url)?;
+                let merged = apply::server_apply(kluster, &target.url, obj, force, true)?;
+                let live_yaml = match live {
+                    Some(ref live) => diff_yaml(live)?,
+                    None => String::new(),
+                };
+                Ok((target, live_yaml, diff_yaml(&merged)?))
+            });
+            match res {
+                Ok((target, live_yaml, merged_yaml)) => {
+                    let name = apply::display_name(&target.resource, &target.name);
+                    any_diff |= crate::diff::print_diff(
+                        &live_yaml,
+                        &merged_yaml,
+                        &format!("live/{}", name),
+                        &format!("merged/{}", name),
+                        writer,
+                    );
+                }
+                Err(e) => {
+                    clickwriteln!(
+                        writer,
+                        "Failed to diff {} {}: {}",
+                        val_str("/kind", obj, ""),
+                        val_str("/metadata/name", obj, ""),
+                        e
+                    );
+                }
+            }
+        }
+        if !any_diff {
+            clickwriteln!(writer, "No differences");
+        }
+    }
+);
+
+/// Compare obj in the current context with the same object in each of contexts
+fn compare_obj(
+    env: &Env,
+    obj: &KObj,
+    others: &[(&str, Kluster)],
+    scope: Scope,
+    writer: &mut ClickWriter,
+) {
+    let namespace = match obj.typ {
+        ObjType::Node => "",
+        ObjType::Dynamic(ref res) if !res.namespaced => "",
+        _ => match obj.namespace {
+            Some(ref ns) => ns,
+            None => {
+                clickwriteln!(writer, "Don't know namespace for {}", obj.name());
+                return;
+            }
+        },
+    };
+    let (current, base) = match env.run_on_kluster(|k| {
+        let val = k.get_value(obj.url(k, namespace)?.as_str())?;
+        Ok((k.name.clone(), val))
+    }) {
+        Some(res) => res,
+        None => return,
+    };
+    let base = compare::fields(&base, scope);
+    for (context, kluster) in others.iter() {
+        let other = obj
+            .url(kluster, namespace)
+            .and_then(|url| kluster.get_value(url.as_str()));
+        match other {
+            Ok(other) => {
+                let other = compare::fields(&other, scope);
+                compare::print_comparison(&current, &base, context, &other, writer);
+            }
+            Err(ref e) if e.status_code() == Some(404) => {
+                clickwriteln!(
+                    writer,
+                    "{} {} doesn't exist in {}",
+                    obj.type_str(),
+                    obj.name(),
+                    context
+                );
+            }
+            Err(e) => {
+                clickwriteln!(
+                    writer,
+                    "Failed to get {} {} from {}: {}",
+                    obj.type_str(),
+                    obj.name(),
+                    context,
+                    e
+                );
+            }
+        }
+    }
+}
+
+command!(
+    Compare,
+    "compare",
+    "Compare the active object(s) with the same objects in other contexts",
+    |clap: App<'static, 'static>| clap
+        .arg(
+            Arg::with_name("contexts")
+                .help("The contexts to compare with")
+                .required(true)
+                .multiple(true)
+                .index(1)
+        )
+        .arg(
+            Arg::with_name("only")
+                .long("only")
+                .help("Only compare this part of the objects")
+                .possible_values(&["spec", "labels", "images"])
+                .takes_value(true)
+        ),
+    vec!["compare"],
+    vec![&completer::context_complete],
+    no_named_complete!(),
+    |matches, env, writer| {
+        let scope = match matches.value_of("only") {
+            Some("spec") => Scope::Spec,
+            Some("labels") => Scope::Labels,
+            Some("images") => Scope::Images,
+            _ => Scope::All,
+        };
+        let contexts: Vec<&str> = matches.values_of("contexts").unwrap().collect();
+        // new Klusters, so the active one is left alone. They're made once, so that discovery is
+        // only done once per context, however many objects are being compared
+        let mut others = vec![];
+        for context in contexts.into_iter() {
+            if !env.config.contexts.contains_key(context) {
+                clickwriteln!(writer, "No context named {}", context);
+                return;
+            }
+            match env.config.cluster_for_context(context, &env.click_config) {
+                Ok(kluster) => others.push((context, kluster)),
+                Err(e) => {
+                    clickwriteln!(writer, "Couldn't connect to {}: {}", context, e);
+                    return;
+                }
+            }
+        }
+        env.apply_to_selection(
+            writer,
+            Some(&env.click_config.range_separator),
+            |obj, writer| {
+                compare_obj(env, obj, &others, scope, writer);
+            },
+        );
+    }
+);
+
+command!(
+    Cp,
+    "cp",
+    "Copy files or directories to or from the active pod. The path in the container is marked with \
+     a leading ':', like 'cp :/tmp/heap.hprof .' or 'cp ./app.conf :/etc/app/'",
+    |clap: App<'static, 'static>| clap
+        .arg(
+            Arg::with_name("src")
+                .help("What to copy. Prefix with ':' for a path in the container")
+                .required(true)
+                .index(1)
+        )
+        .arg(
+            Arg::with_name("dest")
+                .help(
+                    "Where to copy to. Prefix with ':' for a path in the container. A container \
+                     path ending in '/' is a directory to copy into"
+                )
+                .required(true)
+                .index(2)
+        )
+        .arg(
+            Arg::with_name("container")
+                .short("c")
+                .long("container")
+                .help("Copy to or from the specified container")
+                .takes_value(true)
+        ),
+    vec!["cp"],
+    noop_complete!(),
+    IntoIter::new([(
+        "container".to_string(),
+        completer::container_completer as fn(&str, &Env) -> Vec<RustlinePair>
+    )])
+    .collect(),
+    |matches, env, writer| {
+        let kluster = match env.kluster {
+            Some(ref k) => k,
+            None => {
+                clickwriteln!(writer, "Need to have an active context");
+                return;
+            }
+        };
+        let pod = match env.current_pod() {
+            Some(pod) => pod,
+            None => {
+                clickwriteln!(writer, "No active pod");
+                return;
+            }
+        };
+        let src = matches.value_of("src").unwrap(); // safe as required
+        let dest = matches.value_of("dest").unwrap(); // safe as required
+        let ns = pod.namespace.as_ref().unwrap();
+        let cont = matches
+            .value_of("container")
+            .unwrap_or_else(|| pick_container(pod, writer));
+        let res = match (src.strip_prefix(':'), dest.strip_prefix(':')) {
+            (Some(remote), None) => cp::copy_from_pod(
+                kluster,
+                ns,
+                pod.name(),
+                Some(cont),
+                remote,
+                Path::new(dest),
+                &env.ctrlcbool,
+                writer,
+            ),
+            (None, Some(remote)) => cp::copy_to_pod(
+                kluster,
+                ns,
+                pod.name(),
+                Some(cont),
+                Path::new(src),
+                remote,
+                &env.ctrlcbool,
+                writer,
+            ),
+            _ => {
+                clickwriteln!(
+                    writer,
+                    "Exactly one of the paths must be in the container (start with ':')"
+                );
+                return;
+            }
+        };
+        if let Err(e) = res {
+            clickwriteln!(writer, "Copy failed: {}", e);
+        }
+    }
+);
+
+command!(
+    Top,
+    "top",
+    "Show cpu and memory usage of pods or nodes (needs metrics-server in the cluster). If a node \
+     is active, 'top pods' only shows pods on that node",
+    |clap: App<'static, 'static>| clap
+        .arg(
+            Arg::with_name("kind")
+                .help("What to show usage for")
+                .required(true)
+                .possible_values(&["pods", "nodes"])
+                .index(1)
+        )
+        .arg(
+            Arg::with_name("label")
+                .short("l")
+                .long("label")
+                .help("Only show pods with specified label selector")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("regex")
+                .short("r")
+                .long("regex")
+                .help("Filter rows by the specified regex")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("sort")
+                .short("s")
+                .long("sort")
+                .help("Sort by specified column")
+                .takes_value(true)
+                .possible_values(&[
+                    "Name",
+                    "name",
+                    "CPU",
+                    "cpu",
+                    "Memory",
+                    "memory",
+                    "Namespace",
+                    "namespace"
+                ])
+        )
+        .arg(
+            Arg::with_name("reverse")
+                .short("R")
+                .long("reverse")
+                .help("Reverse the order of the returned list")
+                .takes_value(false)
+        ),
+    vec!["top"],
+    noop_complete!(),
+    no_named_complete!(),
+    |matches, env, writer| {
+        let regex = match crate::table::get_regex(&matches) {
+            Ok(r) => r,
+            Err(s) => {
+                writeln!(stderr(), "{}", s).unwrap_or(());
+                return;
+            }
+        };
+        let node = match env.current_selection() {
+            ObjectSelection::Single(obj) if obj.is(ObjType::Node) => Some(obj.name().to_owned()),
+            _ => None,
+        };
+        let sort = matches.value_of("sort");
+        let reverse = matches.is_present("reverse");
+        if matches.value_of("kind") == Some("nodes") {
+            let nodes = env.run_on_kluster(|k| {
+                top::print_node_usage(k, node.as_deref(), regex.clone(), sort, reverse, writer)
+            });
+            match nodes {
+                Some(nodes) => env.set_last_objs(nodes),
+                None => env.clear_last_objs(),
+            }
+        } else {
+            let usages = env.run_on_kluster(|k| {
+                top::pod_usage(
+                    k,
+                    env.namespace.as_deref(),
+                    node.as_deref(),
+                    matches.value_of("label"),
+                )
+            });
+            match usages {
+                Some(usages) => {
+                    let pods = top::print_pod_usage(
+                        usages,
+                        env.namespace.is_none(),
+                        regex,
+                        sort,
+                        reverse,
+                        writer,
+                    );
+                    env.set_last_objs(pods);
+                }
+                None => env.clear_last_objs(),
+            }
+        }
+    }
+);
+
+command!(
+    Capacity,
+    "capacity",
+    "Show how much of each node's allocatable cpu and memory is requested and limited by the pods \
+     on it, with cluster totals. A limit % over 100% means the node is overcommitted",
+    |clap: App<'static, 'static>| clap.arg(
+        Arg::with_name("label")
+            .short("l")
+            .long("label")
+            .help("Only show nodes with specified label selector (example: pool=batch)")
+            .takes_value(true)
+    ),
+    vec!["capacity"],
+    noop_complete!(),
+    no_named_complete!(),
+    |matches, env, writer| {
+        env.run_on_kluster(|k| capacity::print_capacity(k, matches.value_of("label"), writer));
+    }
+);
+
+command!(
+    Explain,
+    "explain",
+    "Describe the fields of a kind of resource, using the schema the server publishes. The \
+     schema is cached for each context, use --refresh to fetch it again (like after an upgrade)",
+    |clap: App<'static, 'static>| clap
+        .arg(
+            Arg::with_name("what")
+                .help(
+                    "The kind of resource, optionally followed by the path to a field \
+                     (example: pods.spec.containers.resources)"
+                )
+                .required(true)
+                .index(1)
+        )
+        .arg(
+            Arg::with_name("refresh")
+                .long("refresh")
+                .help("Fetch the schema from the server again, rather than using the cached one")
+                .takes_value(false)
+        ),
+    vec!["explain"],
+    vec![&completer::explain_completer],
+    no_named_complete!(),
+    |matches, env, writer| {
+        let what = matches.value_of("what").unwrap(); // safe as required
+        env.run_on_kluster(|k| {
+            let discovery = k.discovery()?;
+            let (resource, path) = explain::split_path(&discovery, what)
+                .ok_or_else(|| KubeError::NotServed(what.to_owned()))?;
+            let cache_path = explain::cache_path(env.config_dir(), &k.name);
+            let defs = k.openapi_definitions(&cache_path, matches.is_present("refresh"))?;
+            explain::print_explanation(&defs, resource, &path, writer)
+        });
+    }
+);
+
 fn containers_string(pod: &Pod) -> String {
     let mut buf = String::new();
     if let Some(ref stats) = pod.status.container_statuses {
@@ -2237,7 +3865,9 @@ command!(
                 .long("reverse")
                 .help("Reverse the order of the returned list")
                 .takes_value(false)
-        ),
+        )
+        .arg(limit_arg())
+        .arg(watch_arg()),
     vec!["nodes"],
     noop_complete!(),
     IntoIter::new([(
@@ -2255,19 +3885,19 @@ command!(
         };
 
         let url = "/api/v1/nodes";
-        let nl: Option<NodeList> = env.run_on_kluster(|k| k.get(url));
+        let nl = get_list(env, url, &matches, writer, |n: NodeList, start, writer| {
+            print_nodelist(
+                n,
+                matches.is_present("labels"),
+                regex.clone(),
+                matches.value_of("sort"),
+                matches.is_present("reverse"),
+                start,
+                writer,
+            )
+        });
         match nl {
-            Some(n) => {
-                let final_list = print_nodelist(
-                    n,
-                    matches.is_present("labels"),
-                    regex,
-                    matches.value_of("sort"),
-                    matches.is_present("reverse"),
-                    writer,
-                );
-                env.set_last_objs(final_list);
-            }
+            Some(final_list) => env.set_last_objs(final_list),
             None => env.clear_last_objs(),
         }
     }
@@ -2324,7 +3954,9 @@ command!(
                 .long("reverse")
                 .help("Reverse the order of the returned list")
                 .takes_value(false)
-        ),
+        )
+        .arg(limit_arg())
+        .arg(watch_arg()),
     vec!["services"],
     noop_complete!(),
     IntoIter::new([(
@@ -2346,17 +3978,25 @@ command!(
         } else {
             "/api/v1/services".to_owned()
         };
-        let sl: Option<ServiceList> = env.run_on_kluster(|k| k.get(url.as_str()));
-        if let Some(s) = sl {
-            let filtered = print_servicelist(
-                s,
-                regex,
-                matches.is_present("labels"),
-                env.namespace.is_none(),
-                matches.value_of("sort"),
-                matches.is_present("reverse"),
-                writer,
-            );
+        let sl = get_list(
+            env,
+            url.as_str(),
+            &matches,
+            writer,
+            |s: ServiceList, start, writer| {
+                print_servicelist(
+                    s,
+                    regex.clone(),
+                    matches.is_present("labels"),
+                    env.namespace.is_none(),
+                    matches.value_of("sort"),
+                    matches.is_present("reverse"),
+                    start,
+                    writer,
+                )
+            },
+        );
+        if let Some(filtered) = sl {
             env.set_last_objs(filtered);
         } else {
             clickwriteln!(writer, "no services");
@@ -2447,135 +4087,528 @@ Example:
                     .unwrap_or(());
                     failed = true;
                 }
-            },
-            "editor" => {
-                env.set_editor(Some(value));
-            }
-            "terminal" => {
-                env.set_terminal(Some(value));
-            }
-            "range_separator" => {
-                env.click_config.range_separator = value.to_string();
-            }
-            _ => {
-                // this shouldn't happen
-                write!(stderr(), "Invalid option\n").unwrap_or(());
-                failed = true;
+            },
+            "editor" => {
+                env.set_editor(Some(value));
+            }
+            "terminal" => {
+                env.set_terminal(Some(value));
+            }
+            "range_separator" => {
+                env.click_config.range_separator = value.to_string();
+            }
+            _ => {
+                // this shouldn't happen
+                write!(stderr(), "Invalid option\n").unwrap_or(());
+                failed = true;
+            }
+        }
+        if !failed {
+            clickwriteln!(writer, "Set {} to '{}'", option, value);
+        }
+    }
+);
+
+command!(
+    Deployments,
+    "deployments",
+    "Get deployments (in current namespace if set)",
+    |clap: App<'static, 'static>| clap
+        .arg(
+            Arg::with_name("label")
+                .short("l")
+                .long("label")
+                .help("Get deployments with specified label selector")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("regex")
+                .short("r")
+                .long("regex")
+                .help("Filter deployments by the specified regex")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("showlabels")
+                .short("L")
+                .long("labels")
+                .help("Show labels as column in output")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("sort")
+                .short("s")
+                .long("sort")
+                .help(
+                    "Sort by specified column (if column isn't shown by default, it will \
+                     be shown)"
+                )
+                .takes_value(true)
+                .possible_values(&[
+                    "Name",
+                    "name",
+                    "Desired",
+                    "desired",
+                    "Current",
+                    "current",
+                    "UpToDate",
+                    "uptodate",
+                    "Available",
+                    "available",
+                    "Age",
+                    "age",
+                    "Labels",
+                    "labels"
+                ])
+        )
+        .arg(
+            Arg::with_name("reverse")
+                .short("R")
+                .long("reverse")
+                .help("Reverse the order of the returned list")
+                .takes_value(false)
+        )
+        .arg(limit_arg())
+        .arg(watch_arg()),
+    vec!["deps", "deployments"],
+    noop_complete!(),
+    IntoIter::new([(
+        "sort".to_string(),
+        completer::deployment_sort_values_completer as fn(&str, &Env) -> Vec<RustlinePair>
+    )])
+    .collect(),
+    |matches, env, writer| {
+        let regex = match crate::table::get_regex(&matches) {
+            Ok(r) => r,
+            Err(s) => {
+                write!(stderr(), "{}\n", s).unwrap_or(());
+                return;
+            }
+        };
+
+        let mut urlstr = match env.run_on_kluster(|k| ObjType::Deployment.api_resource(k)) {
+            Some(res) => res.list_url(env.namespace.as_deref()),
+            None => return,
+        };
+
+        if let Some(label_selector) = matches.value_of("label") {
+            urlstr.push_str("?labelSelector=");
+            urlstr.push_str(label_selector);
+        }
+
+        let dl = get_list(
+            env,
+            urlstr.as_str(),
+            &matches,
+            writer,
+            |d: DeploymentList, start, writer| {
+                print_deployments(
+                    d,
+                    matches.is_present("showlabels"),
+                    regex.clone(),
+                    matches.value_of("sort"),
+                    matches.is_present("reverse"),
+                    start,
+                    writer,
+                )
+            },
+        );
+        match dl {
+            Some(final_list) => env.set_last_objs(final_list),
+            None => env.clear_last_objs(),
+        }
+    }
+);
+
+// Rollouts
+
+static REVISION_ANNOTATION: &str = "deployment.kubernetes.io/revision";
+static CHANGE_CAUSE_ANNOTATION: &str = "kubernetes.io/change-cause";
+static RESTARTED_AT_ANNOTATION: &str = "kubectl.kubernetes.io/restartedAt";
+
+fn get_annotation<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value
+        .get("metadata")?
+        .get("annotations")?
+        .get(key)?
+        .as_str()
+}
+
+fn get_revision(value: &Value) -> Option<u64> {
+    get_annotation(value, REVISION_ANNOTATION).and_then(|r| r.parse().ok())
+}
+
+enum RolloutStatus {
+    Progressing(String),
+    Done(String),
+    Failed(String),
+}
+
+/// Figure out how far along the rollout of the given deployment is. Follows the same rules as
+/// kubectl rollout status
+fn deployment_rollout_status(name: &str, dep: &Value) -> RolloutStatus {
+    if val_u64("/metadata/generation", dep, 0) > val_u64("/status/observedGeneration", dep, 0) {
+        return RolloutStatus::Progressing(
+            "Waiting for deployment spec update to be observed...".to_owned(),
+        );
+    }
+    if let Some(conds) = dep.pointer("/status/conditions").and_then(Value::as_array) {
+        let deadline_exceeded = conds.iter().any(|cond| {
+            val_str("/type", cond, "") == "Progressing"
+                && val_str("/reason", cond, "") == "ProgressDeadlineExceeded"
+        });
+        if deadline_exceeded {
+            return RolloutStatus::Failed(format!(
+                "deployment {} exceeded its progress deadline",
+                name
+            ));
+        }
+    }
+    let desired = val_u64("/spec/replicas", dep, 1);
+    let replicas = val_u64("/status/replicas", dep, 0);
+    let updated = val_u64("/status/updatedReplicas", dep, 0);
+    let available = val_u64("/status/availableReplicas", dep, 0);
+    if updated < desired {
+        RolloutStatus::Progressing(format!(
+            "Waiting for deployment {} rollout to finish: {} out of {} new replicas have been \
+             updated...",
+            name, updated, desired
+        ))
+    } else if replicas > updated {
+        RolloutStatus::Progressing(format!(
+            "Waiting for deployment {} rollout to finish: {} old replicas are pending \
+             termination...",
+            name,
+            replicas - updated
+        ))
+    } else if available < updated {
+        RolloutStatus::Progressing(format!(
+            "Waiting for deployment {} rollout to finish: {} of {} updated replicas are \
+             available...",
+            name, available, updated
+        ))
+    } else {
+        RolloutStatus::Done(format!("deployment {} successfully rolled out", name))
+    }
+}
+
+/// Print the status of the rollout of the deployment at url until it finishes (or Ctrl-C)
+fn rollout_status(env: &Env, obj: &KObj, url: &str, writer: &mut ClickWriter) {
+    env.ctrlcbool.store(false, Ordering::SeqCst);
+    let mut last_msg = String::new();
+    while !env.ctrlcbool.load(Ordering::SeqCst) {
+        let dep = match env.run_on_kluster(|k| k.get_value(url)) {
+            Some(d) => d,
+            None => return,
+        };
+        match deployment_rollout_status(obj.name(), &dep) {
+            RolloutStatus::Progressing(msg) => {
+                if msg != last_msg {
+                    clickwriteln!(writer, "{}", msg);
+                    last_msg = msg;
+                }
+            }
+            RolloutStatus::Done(msg) => {
+                clickwriteln!(writer, "{}", msg);
+                if let Ok(dep) = serde_json::from_value::<Deployment>(dep) {
+                    let deplist = DeploymentList { items: vec![dep] };
+                    print_deployments(deplist, false, None, None, false, 0, writer);
+                }
+                return;
+            }
+            RolloutStatus::Failed(msg) => {
+                clickwriteln!(writer, "{}", Yellow.paint(msg));
+                return;
+            }
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Get the ReplicaSets owned by the given deployment, sorted by revision
+fn owned_replicasets(env: &Env, namespace: &str, dep: &Value) -> Option<Vec<Value>> {
+    let uid = val_str("/metadata/uid", dep, "");
+    let url = env.run_on_kluster(|k| {
+        Ok(format!(
+            "{}?labelSelector={}",
+            ObjType::ReplicaSet
+                .api_resource(k)?
+                .list_url(Some(namespace)),
+            label_selector(dep.pointer("/spec/selector").unwrap_or(&Value::Null))?
+        ))
+    })?;
+    let mut rslist = env.run_on_kluster(|k| k.get_value(url.as_str()))?;
+    let mut owned: Vec<Value> = match rslist.get_mut("items").map(Value::take) {
+        Some(Value::Array(items)) => items
+            .into_iter()
+            .filter(|rs| {
+                rs.pointer("/metadata/ownerReferences")
+                    .and_then(Value::as_array)
+                    .map(|refs| refs.iter().any(|r| val_str("/uid", r, "") == uid))
+                    .unwrap_or(false)
+            })
+            .collect(),
+        _ => vec![],
+    };
+    owned.sort_by_key(get_revision);
+    Some(owned)
+}
+
+fn rollout_history(env: &Env, namespace: &str, dep: &Value, writer: &mut ClickWriter) {
+    let rss = match owned_replicasets(env, namespace, dep) {
+        Some(rss) => rss,
+        None => return,
+    };
+    if rss.is_empty() {
+        clickwriteln!(writer, "No rollout history found");
+        return;
+    }
+    let current = get_revision(dep);
+    let mut table = Table::new();
+    table.set_titles(row![
+        "####",
+        "Revision",
+        "Name",
+        "Desired",
+        "Current",
+        "Ready",
+        "Age",
+        "Change Cause"
+    ]);
+    let specs: Vec<(&Value, Vec<CellSpec>)> = rss
+        .iter()
+        .map(|rs| {
+            let mut specs = Vec::new();
+            specs.push(CellSpec::new_index());
+            let revision = get_revision(rs);
+            let revision_str = revision
+                .map(|r| r.to_string())
+                .unwrap_or_else(|| "<none>".to_owned());
+            if revision.is_some() && revision == current {
+                specs.push(CellSpec::with_style_owned(revision_str, "Fg"));
+            } else {
+                specs.push(CellSpec::new_owned(revision_str));
+            }
+            specs.push(CellSpec::new_owned(
+                val_str("/metadata/name", rs, "<none>").into_owned(),
+            ));
+            specs.push(CellSpec::new_owned(
+                val_u64("/spec/replicas", rs, 0).to_string(),
+            ));
+            specs.push(CellSpec::new_owned(
+                val_u64("/status/replicas", rs, 0).to_string(),
+            ));
+            specs.push(CellSpec::new_owned(
+                val_u64("/status/readyReplicas", rs, 0).to_string(),
+            ));
+            let age = get_val_as::<DateTime<Utc>>("/metadata/creationTimestamp", rs)
+                .map(time_since)
+                .unwrap_or_else(|_| "unknown".to_owned());
+            specs.push(CellSpec::new_owned(age));
+            specs.push(CellSpec::new_owned(
+                get_annotation(rs, CHANGE_CAUSE_ANNOTATION)
+                    .unwrap_or("<none>")
+                    .to_owned(),
+            ));
+            (rs, specs)
+        })
+        .collect();
+    crate::table::print_table(&mut table, &specs, writer);
+}
+
+/// Pick the ReplicaSet to roll back to from rss (sorted by revision): the one at to_revision if
+/// given, otherwise the latest one before the current revision
+fn undo_target(rss: &[Value], current: u64, to_revision: Option<u64>) -> Option<&Value> {
+    match to_revision {
+        Some(rev) => rss.iter().find(|rs| get_revision(rs) == Some(rev)),
+        None => rss
+            .iter()
+            .rev()
+            .find(|rs| get_revision(rs).map(|r| r < current).unwrap_or(false)),
+    }
+}
+
+/// The pod template to put back in the deployment to roll back to the given ReplicaSet
+fn revert_template(rs: &Value) -> Value {
+    let mut template = rs.pointer("/spec/template").cloned().unwrap_or(Value::Null);
+    // the replicaset's template has the hash label added, which the deployment won't have
+    if let Some(labels) = template
+        .pointer_mut("/metadata/labels")
+        .and_then(Value::as_object_mut)
+    {
+        labels.remove("pod-template-hash");
+    }
+    template
+}
+
+fn rollout_undo(
+    env: &Env,
+    obj: &KObj,
+    namespace: &str,
+    url: &str,
+    dep: &Value,
+    to_revision: Option<u64>,
+    writer: &mut ClickWriter,
+) {
+    let rss = match owned_replicasets(env, namespace, dep) {
+        Some(rss) => rss,
+        None => return,
+    };
+    let current = get_revision(dep).unwrap_or(0);
+    let target = match undo_target(&rss, current, to_revision) {
+        Some(t) => t,
+        None => {
+            match to_revision {
+                Some(rev) => clickwriteln!(writer, "Unable to find revision {}", rev),
+                None => clickwriteln!(writer, "No previous revision to roll back to"),
+            }
+            return;
+        }
+    };
+    let revision = get_revision(target).unwrap_or(0);
+
+    let template = revert_template(target);
+    if dep.pointer("/spec/template") == Some(&template) {
+        clickwriteln!(
+            writer,
+            "Skipped rollback: deployment {} already matches revision {}",
+            obj.name(),
+            revision
+        );
+        return;
+    }
+
+    let patch = json!([{"op": "replace", "path": "/spec/template", "value": template}]);
+    if env
+        .run_on_kluster(|k| k.patch(url, &patch.to_string(), PatchType::Json))
+        .is_some()
+    {
+        clickwriteln!(
+            writer,
+            "deployment {} rolled back to revision {}",
+            obj.name(),
+            revision
+        );
+    }
+}
+
+fn rollout_obj(
+    env: &Env,
+    obj: &KObj,
+    action: &str,
+    to_revision: Option<u64>,
+    writer: &mut ClickWriter,
+) {
+    if !obj.is(ObjType::Deployment) {
+        clickwriteln!(
+            writer,
+            "rollout only works on deployments, not a {}",
+            obj.type_str()
+        );
+        return;
+    }
+    let namespace = match obj.namespace {
+        Some(ref ns) => ns,
+        None => {
+            clickwriteln!(writer, "Don't know namespace for {}", obj.name());
+            return;
+        }
+    };
+    let url = match env.run_on_kluster(|k| obj.url(k, namespace)) {
+        Some(url) => url,
+        None => return,
+    };
+    match action {
+        "status" => rollout_status(env, obj, &url, writer),
+        "restart" => {
+            let patch = json!({
+                "spec": {
+                    "template": {
+                        "metadata": {
+                            "annotations": {
+                                RESTARTED_AT_ANNOTATION: Utc::now().to_rfc3339()
+                            }
+                        }
+                    }
+                }
+            });
+            if env
+                .run_on_kluster(|k| k.patch(&url, &patch.to_string(), PatchType::StrategicMerge))
+                .is_some()
+            {
+                clickwriteln!(writer, "deployment {} restarted", obj.name());
             }
         }
-        if !failed {
-            clickwriteln!(writer, "Set {} to '{}'", option, value);
+        "history" | "undo" => {
+            let dep = match env.run_on_kluster(|k| k.get_value(&url)) {
+                Some(d) => d,
+                None => return,
+            };
+            if action == "history" {
+                rollout_history(env, namespace, &dep, writer);
+            } else {
+                rollout_undo(env, obj, namespace, &url, &dep, to_revision, writer);
+            }
         }
+        _ => unreachable!(), // clap only allows the above
     }
-);
+}
 
 command!(
-    Deployments,
-    "deployments",
-    "Get deployments (in current namespace if set)",
+    Rollout,
+    "rollout",
+    "Manage the rollout of the active deployment",
     |clap: App<'static, 'static>| clap
         .arg(
-            Arg::with_name("label")
-                .short("l")
-                .long("label")
-                .help("Get deployments with specified label selector")
-                .takes_value(true)
-        )
-        .arg(
-            Arg::with_name("regex")
-                .short("r")
-                .long("regex")
-                .help("Filter deployments by the specified regex")
-                .takes_value(true)
-        )
-        .arg(
-            Arg::with_name("showlabels")
-                .short("L")
-                .long("labels")
-                .help("Show labels as column in output")
-                .takes_value(false)
+            Arg::with_name("action")
+                .help("Action to take")
+                .required(true)
+                .possible_values(&["status", "restart", "history", "undo"])
+                .index(1)
         )
         .arg(
-            Arg::with_name("sort")
-                .short("s")
-                .long("sort")
+            Arg::with_name("torevision")
+                .long("to-revision")
                 .help(
-                    "Sort by specified column (if column isn't shown by default, it will \
-                     be shown)"
+                    "When undoing, the revision to roll back to (see 'rollout history'). \
+                     Default is the previous revision."
                 )
+                .validator(valid_u32)
                 .takes_value(true)
-                .possible_values(&[
-                    "Name",
-                    "name",
-                    "Desired",
-                    "desired",
-                    "Current",
-                    "current",
-                    "UpToDate",
-                    "uptodate",
-                    "Available",
-                    "available",
-                    "Age",
-                    "age",
-                    "Labels",
-                    "labels"
-                ])
         )
-        .arg(
-            Arg::with_name("reverse")
-                .short("R")
-                .long("reverse")
-                .help("Reverse the order of the returned list")
-                .takes_value(false)
+        .after_help(
+            "Actions:
+  status   Show the progress of the current rollout, until it finishes (Ctrl-C to stop)
+  restart  Restart all the pods of the deployment, by triggering a new rollout
+  history  List the revisions of the deployment (the current revision is shown in green)
+  undo     Roll back to the previous revision, or the one specified by --to-revision
+
+Example:
+  # Roll back to revision 3 of the active deployment
+  rollout undo --to-revision 3"
         ),
-    vec!["deps", "deployments"],
-    noop_complete!(),
-    IntoIter::new([(
-        "sort".to_string(),
-        completer::deployment_sort_values_completer as fn(&str, &Env) -> Vec<RustlinePair>
-    )])
-    .collect(),
+    vec!["rollout"],
+    vec![&completer::rolloutaction_values_completer],
+    no_named_complete!(),
     |matches, env, writer| {
-        let regex = match crate::table::get_regex(&matches) {
-            Ok(r) => r,
-            Err(s) => {
-                write!(stderr(), "{}\n", s).unwrap_or(());
-                return;
-            }
-        };
-
-        let mut urlstr = if let Some(ref ns) = env.namespace {
-            format!("/apis/extensions/v1beta1/namespaces/{}/deployments", ns)
-        } else {
-            "/apis/extensions/v1beta1/deployments".to_owned()
-        };
-
-        if let Some(label_selector) = matches.value_of("label") {
-            urlstr.push_str("?labelSelector=");
-            urlstr.push_str(label_selector);
-        }
-
-        let dl: Option<DeploymentList> = env.run_on_kluster(|k| k.get(urlstr.as_str()));
-        match dl {
-            Some(d) => {
-                let final_list = print_deployments(
-                    d,
-                    matches.is_present("showlabels"),
-                    regex,
-                    matches.value_of("sort"),
-                    matches.is_present("reverse"),
-                    writer,
-                );
-                env.set_last_objs(final_list);
-            }
-            None => env.clear_last_objs(),
-        }
+        let action = matches.value_of("action").unwrap(); // safe as required
+        let to_revision = matches
+            .value_of("torevision")
+            .map(|r| r.parse::<u64>().unwrap()); // safe as validated
+        env.apply_to_selection(
+            writer,
+            Some(&env.click_config.range_separator),
+            |obj, writer| {
+                rollout_obj(env, obj, action, to_revision, writer);
+            },
+        );
     }
 );
 
 fn print_replicasets(
     list: ReplicaSetList,
     regex: Option<Regex>,
+    start: usize,
     writer: &mut ClickWriter,
 ) -> ReplicaSetList {
     let mut table = Table::new();
@@ -2606,7 +4639,7 @@ fn print_replicasets(
         None => rss_specs.collect(),
     };
 
-    crate::table::print_table(&mut table, &filtered, writer);
+    crate::table::print_table_from(&mut table, &filtered, start, writer);
 
     let final_rss = filtered.into_iter().map(|rs_spec| rs_spec.0).collect();
     ReplicaSetList { items: final_rss }
@@ -2630,7 +4663,9 @@ command!(
                 .long("regex")
                 .help("Filter replicasets by the specified regex")
                 .takes_value(true)
-        ),
+        )
+        .arg(limit_arg())
+        .arg(watch_arg()),
     vec!["rs", "replicasets"],
     noop_complete!(),
     no_named_complete!(),
@@ -2643,19 +4678,21 @@ command!(
             }
         };
 
-        let urlstr = if let Some(ref ns) = env.namespace {
-            format!("/apis/extensions/v1beta1/namespaces/{}/replicasets", ns)
-        } else {
-            "/apis/extensions/v1beta1/replicasets".to_owned()
+        let urlstr = match env.run_on_kluster(|k| ObjType::ReplicaSet.api_resource(k)) {
+            Some(res) => res.list_url(env.namespace.as_deref()),
+            None => return,
         };
 
-        let rsl: Option<ReplicaSetList> = env.run_on_kluster(|k| k.get(urlstr.as_str()));
+        let rsl = get_list(
+            env,
+            urlstr.as_str(),
+            &matches,
+            writer,
+            |l: ReplicaSetList, start, writer| print_replicasets(l, regex.clone(), start, writer),
+        );
 
         match rsl {
-            Some(l) => {
-                let final_list = print_replicasets(l, regex, writer);
-                env.set_last_objs(VecWrap::from(final_list));
-            }
+            Some(final_list) => env.set_last_objs(VecWrap::from(final_list)),
             None => env.clear_last_objs(),
         }
     }
@@ -2664,6 +4701,7 @@ command!(
 fn print_statefulsets(
     list: StatefulSetList,
     regex: Option<Regex>,
+    start: usize,
     writer: &mut ClickWriter,
 ) -> StatefulSetList {
     let mut table = Table::new();
@@ -2694,7 +4732,7 @@ fn print_statefulsets(
         None => statefulsets_specs.collect(),
     };
 
-    crate::table::print_table(&mut table, &filtered, writer);
+    crate::table::print_table_from(&mut table, &filtered, start, writer);
 
     let final_statefulsets = filtered
         .into_iter()
@@ -2724,7 +4762,9 @@ command!(
                 .long("regex")
                 .help("Filter statefulsets by the specified regex")
                 .takes_value(true)
-        ),
+        )
+        .arg(limit_arg())
+        .arg(watch_arg()),
     vec!["ss", "statefulsets"],
     noop_complete!(),
     no_named_complete!(),
@@ -2737,20 +4777,21 @@ command!(
             }
         };
 
-        let urlstr = if let Some(ref ns) = env.namespace {
-            format!("/apis/apps/v1beta1/namespaces/{}/statefulsets", ns)
-        } else {
-            "/apis/apps/v1beta1/statefulsets".to_owned()
+        let urlstr = match env.run_on_kluster(|k| ObjType::StatefulSet.api_resource(k)) {
+            Some(res) => res.list_url(env.namespace.as_deref()),
+            None => return,
         };
 
-        let statefulset_list: Option<StatefulSetList> =
-            env.run_on_kluster(|k| k.get(urlstr.as_str()));
+        let statefulset_list = get_list(
+            env,
+            urlstr.as_str(),
+            &matches,
+            writer,
+            |l: StatefulSetList, start, writer| print_statefulsets(l, regex.clone(), start, writer),
+        );
 
         match statefulset_list {
-            Some(l) => {
-                let final_list = print_statefulsets(l, regex, writer);
-                env.set_last_objs(VecWrap::from(final_list));
-            }
+            Some(final_list) => env.set_last_objs(VecWrap::from(final_list)),
             None => {
                 env.clear_last_objs();
             }
@@ -2761,6 +4802,7 @@ command!(
 fn print_configmaps(
     list: ConfigMapList,
     regex: Option<Regex>,
+    start: usize,
     writer: &mut ClickWriter,
 ) -> ConfigMapList {
     let mut table = Table::new();
@@ -2783,7 +4825,7 @@ fn print_configmaps(
         None => cm_specs.collect(),
     };
 
-    crate::table::print_table(&mut table, &filtered, writer);
+    crate::table::print_table_from(&mut table, &filtered, start, writer);
 
     let final_rss = filtered.into_iter().map(|cm_spec| cm_spec.0).collect();
     ConfigMapList { items: final_rss }
@@ -2807,7 +4849,9 @@ command!(
                 .long("regex")
                 .help("Filter replicasets by the specified regex")
                 .takes_value(true)
-        ),
+        )
+        .arg(limit_arg())
+        .arg(watch_arg()),
     vec!["cm", "configmaps"],
     noop_complete!(),
     no_named_complete!(),
@@ -2826,13 +4870,16 @@ command!(
             "/api/v1/configmaps".to_owned()
         };
 
-        let cml: Option<ConfigMapList> = env.run_on_kluster(|k| k.get(urlstr.as_str()));
+        let cml = get_list(
+            env,
+            urlstr.as_str(),
+            &matches,
+            writer,
+            |l: ConfigMapList, start, writer| print_configmaps(l, regex.clone(), start, writer),
+        );
 
         match cml {
-            Some(l) => {
-                let final_list = print_configmaps(l, regex, writer);
-                env.set_last_objs(VecWrap::from(final_list));
-            }
+            Some(final_list) => env.set_last_objs(VecWrap::from(final_list)),
             None => {
                 env.clear_last_objs();
             }
@@ -2840,7 +4887,12 @@ command!(
     }
 );
 
-fn print_secrets(list: SecretList, regex: Option<Regex>, writer: &mut ClickWriter) -> SecretList {
+fn print_secrets(
+    list: SecretList,
+    regex: Option<Regex>,
+    start: usize,
+    writer: &mut ClickWriter,
+) -> SecretList {
     let mut table = Table::new();
     table.set_titles(row!["####", "Name", "Type", "Data", "Age"]);
     let rss_specs = list.items.into_iter().map(|rs| {
@@ -2867,7 +4919,7 @@ fn print_secrets(list: SecretList, regex: Option<Regex>, writer: &mut ClickWrite
         None => rss_specs.collect(),
     };
 
-    crate::table::print_table(&mut table, &filtered, writer);
+    crate::table::print_table_from(&mut table, &filtered, start, writer);
 
     let final_rss = filtered.into_iter().map(|rs_spec| rs_spec.0).collect();
     SecretList { items: final_rss }
@@ -2891,7 +4943,9 @@ command!(
                 .long("regex")
                 .help("Filter secrets by the specified regex")
                 .takes_value(true)
-        ),
+        )
+        .arg(limit_arg())
+        .arg(watch_arg()),
     vec!["secrets"],
     noop_complete!(),
     no_named_complete!(),
@@ -2910,13 +4964,16 @@ command!(
             "/api/v1/secrets".to_owned()
         };
 
-        let sl: Option<SecretList> = env.run_on_kluster(|k| k.get(urlstr.as_str()));
+        let sl = get_list(
+            env,
+            urlstr.as_str(),
+            &matches,
+            writer,
+            |l: SecretList, start, writer| print_secrets(l, regex.clone(), start, writer),
+        );
 
         match sl {
-            Some(l) => {
-                let final_list = print_secrets(l, regex, writer);
-                env.set_last_objs(VecWrap::from(final_list));
-            }
+            Some(final_list) => env.set_last_objs(VecWrap::from(final_list)),
             None => {
                 env.clear_last_objs();
             }
@@ -2955,6 +5012,99 @@ command!(
     }
 );
 
+command!(
+    ApiResources,
+    "api-resources",
+    "List the kinds of resources the current context serves (in the preferred version of each \
+     group)",
+    |clap: App<'static, 'static>| clap
+        .arg(
+            Arg::with_name("regex")
+                .short("r")
+                .long("regex")
+                .help("Filter resources by the specified regex")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("refresh")
+                .long("refresh")
+                .help(
+                    "Ask the server what it serves again, rather than using what click found \
+                     before (like after installing new custom resources)"
+                )
+                .takes_value(false)
+        ),
+    vec!["api-resources"],
+    noop_complete!(),
+    no_named_complete!(),
+    |matches, env, writer| {
+        let regex = match crate::table::get_regex(&matches) {
+            Ok(r) => r,
+            Err(s) => {
+                writeln!(stderr(), "{}", s).unwrap_or(());
+                return;
+            }
+        };
+        let discovery = env.run_on_kluster(|k| {
+            if matches.is_present("refresh") {
+                k.refresh_discovery()
+            } else {
+                k.discovery()
+            }
+        });
+        if let Some(discovery) = discovery {
+            print_api_resources(&discovery.resources, regex, writer);
+        }
+    }
+);
+
+command!(
+    ApiVersions,
+    "api-versions",
+    "List the group versions the current context serves",
+    |clap: App<'static, 'static>| clap
+        .arg(
+            Arg::with_name("regex")
+                .short("r")
+                .long("regex")
+                .help("Filter versions by the specified regex")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("refresh")
+                .long("refresh")
+                .help(
+                    "Ask the server what it serves again, rather than using what click found \
+                     before"
+                )
+                .takes_value(false)
+        ),
+    vec!["api-versions"],
+    noop_complete!(),
+    no_named_complete!(),
+    |matches, env, writer| {
+        let regex = match crate::table::get_regex(&matches) {
+            Ok(r) => r,
+            Err(s) => {
+                writeln!(stderr(), "{}", s).unwrap_or(());
+                return;
+            }
+        };
+        let discovery = env.run_on_kluster(|k| {
+            if matches.is_present("refresh") {
+                k.refresh_discovery()
+            } else {
+                k.discovery()
+            }
+        });
+        if let Some(discovery) = discovery {
+            for version in api_versions(&discovery.groups, regex).iter() {
+                clickwriteln!(writer, "{}", version);
+            }
+        }
+    }
+);
+
 command!(
     UtcCmd,
     "utc",
@@ -2971,29 +5121,16 @@ command!(
 command!(
     PortForward,
     "port-forward",
-    "Forward one (or more) local ports to the currently active pod",
+    "Forward one (or more) local ports to the currently active pod, service or deployment",
     |clap: App<'static, 'static>| clap
         .arg(
             Arg::with_name("ports")
                 .help("the ports to forward")
                 .multiple(true)
                 .validator(|s: String| {
-                    let parts: Vec<&str> = s.split(':').collect();
-                    if parts.len() > 2 {
-                        Err(format!(
-                            "Invalid port specification '{}', can only contain one ':'",
-                            s
-                        ))
-                    } else {
-                        for part in parts {
-                            if !part.is_empty() {
-                                if let Err(e) = part.parse::<u32>() {
-                                    return Err(e.to_string());
-                                }
-                            }
-                        }
-                        Ok(())
-                    }
+                    crate::portforward::parse_port_spec(&s)
+                        .map(|_| ())
+                        .map_err(|e| e.to_string())
                 })
                 .required(true)
                 .index(1)
@@ -3011,7 +5148,15 @@ Examples:
   port-forward 0:3456
 
   # Forwards a random port locally to port 3456 on the pod
-  port-forward :3456"
+  port-forward :3456
+
+  # Forward port 5432 locally to the port named postgres on the pod
+  port-forward 5432:postgres
+
+With a service selected, remote ports are service ports (by number or name), and are mapped to
+the targetPort of a ready pod backing the service. With a deployment selected, a ready pod of the
+deployment is used. In both cases, if the pod goes away a new one is found for the next
+connection."
         ),
     vec!["pf", "port-forward"],
     noop_complete!(),
@@ -3019,91 +5164,35 @@ Examples:
     |matches, env, writer| {
         let ports: Vec<_> = matches.values_of("ports").unwrap().collect();
 
-        let (pod, ns) = {
-            let epod = env.current_pod();
-            match epod {
-                Some(p) => (
-                    p.name().to_string(),
-                    p.namespace.as_ref().unwrap().to_string(),
-                ),
-                None => {
-                    write!(stderr(), "No active pod").unwrap_or(());
-                    return;
-                }
+        let kluster = match env.kluster {
+            Some(ref k) => k,
+            None => {
+                write!(stderr(), "No active context").unwrap_or(());
+                return;
             }
         };
 
-        let context = if let Some(ref kluster) = env.kluster {
-            kluster.name.clone()
-        } else {
-            write!(stderr(), "No active context").unwrap_or(());
-            return;
-        };
-
-        match Command::new("kubectl")
-            .arg("--namespace")
-            .arg(ns)
-            .arg("--context")
-            .arg(context)
-            .arg("port-forward")
-            .arg(&pod)
-            .args(ports.iter())
-            .stdout(Stdio::piped())
-            .spawn()
-        {
-            Ok(mut child) => {
-                let mut stdout = child.stdout.take().unwrap();
-                let output = Arc::new(Mutex::new(String::new()));
-                let output_clone = output.clone();
-
-                thread::spawn(move || {
-                    let mut buffer = [0; 128];
-                    loop {
-                        match stdout.read(&mut buffer[..]) {
-                            Ok(read) => {
-                                if read > 0 {
-                                    let readstr = String::from_utf8_lossy(&buffer[0..read]);
-                                    let mut res = output_clone.lock().unwrap();
-                                    res.push_str(&*readstr);
-                                } else {
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                write!(stderr(), "Error reading child output: {}", e).unwrap_or(());
-                                break;
-                            }
-                        }
-                    }
-                });
-
-                let pvec: Vec<String> = ports.iter().map(|s| (*s).to_owned()).collect();
-                clickwriteln!(writer, "Forwarding port(s): {}", pvec.join(", "));
-
-                env.add_port_forward(env::PortForward {
-                    child,
-                    pod,
-                    ports: pvec,
-                    output,
-                });
+        let obj = match env.current_selection() {
+            ObjectSelection::Single(obj)
+                if obj.is_pod() || obj.is(ObjType::Service) || obj.is(ObjType::Deployment) =>
+            {
+                obj
+            }
+            _ => {
+                write!(stderr(), "No active pod, service or deployment").unwrap_or(());
+                return;
+            }
+        };
+        let ns = obj.namespace.as_ref().unwrap();
+
+        match crate::portforward::start(kluster, ns, obj, &ports) {
+            Ok(pf) => {
+                clickwriteln!(writer, "Forwarding port(s): {}", pf.ports.join(", "));
+                env.add_port_forward(pf);
+            }
+            Err(e) => {
+                writeln!(stderr(), "Couldn't start port forward: {}", e).unwrap_or(());
             }
-            Err(e) => match e.kind() {
-                io::ErrorKind::NotFound => {
-                    writeln!(
-                        stderr(),
-                        "Could not find kubectl binary. Is it in your PATH?"
-                    )
-                    .unwrap_or(());
-                }
-                _ => {
-                    write!(
-                        stderr(),
-                        "Couldn't execute kubectl, not forwarding.  Error is: {}",
-                        e
-                    )
-                    .unwrap_or(());
-                }
-            },
         }
     }
 );
@@ -3111,25 +5200,46 @@ Examples:
 /// Print out port forwards found in iterator
 fn print_pfs(pfs: std::slice::Iter<env::PortForward>) {
     let mut table = Table::new();
-    table.set_titles(row!["####", "Pod", "Ports"]);
+    table.set_titles(row![
+        "####",
+        "Target",
+        "Ports",
+        "Connections",
+        "Bytes In",
+        "Bytes Out",
+        "Status"
+    ]);
     for (i, pf) in pfs.enumerate() {
         let mut row = Vec::new();
         row.push(Cell::new_align(
             format!("{}", i).as_str(),
             format::Alignment::RIGHT,
         ));
-        row.push(Cell::new(pf.pod.as_str()));
+        match *pf.stats.pod.lock().unwrap() {
+            Some(ref pod) if *pod != pf.target => {
+                row.push(Cell::new(format!("{} ({})", pf.target, pod).as_str()))
+            }
+            _ => row.push(Cell::new(pf.target.as_str())),
+        }
         row.push(Cell::new(pf.ports.join(", ").as_str()));
-
-        // TODO: Add this when try_wait stabalizes
-        // let status =
-        //     match pf.child.try_wait() {
-        //         Ok(Some(stat)) => format!("Exited with code {}", stat),
-        //         Ok(None) => format!("Running"),
-        //         Err(e) => format!("Error: {}", e.description()),
-        //     };
-        // row.push(Cell::new(status.as_str()));
-
+        row.push(Cell::new(
+            format!(
+                "{} ({} active)",
+                pf.stats.connections.load(Ordering::SeqCst),
+                pf.stats.active.load(Ordering::SeqCst)
+            )
+            .as_str(),
+        ));
+        row.push(Cell::new(
+            format!("{}", pf.stats.bytes_in.load(Ordering::SeqCst)).as_str(),
+        ));
+        row.push(Cell::new(
+            format!("{}", pf.stats.bytes_out.load(Ordering::SeqCst)).as_str(),
+        ));
+        match *pf.stats.error.lock().unwrap() {
+            Some(ref e) => row.push(Cell::new(e.as_str()).style_spec("Fr")),
+            None => row.push(Cell::new("Running").style_spec("Fg")),
+        }
         table.add_row(Row::new(row));
     }
     table.set_format(*TBLFMT);
@@ -3177,7 +5287,12 @@ command!(
                     if stop {
                         clickwrite!(writer, "Stop port-forward: ");
                     }
-                    clickwrite!(writer, "Pod: {}, Port(s): {}", pf.pod, pf.ports.join(", "));
+                    clickwrite!(
+                        writer,
+                        "Target: {}, Port(s): {}",
+                        pf.target,
+                        pf.ports.join(", ")
+                    );
 
                     if output {
                         clickwriteln!(writer, " Output:{}", *pf.output.lock().unwrap());
@@ -3195,14 +5310,8 @@ command!(
                 let mut conf = String::new();
                 if io::stdin().read_line(&mut conf).is_ok() {
                     if conf.trim() == "y" || conf.trim() == "yes" {
-                        match env.stop_port_forward(i) {
-                            Ok(()) => {
-                                clickwriteln!(writer, "Stopped");
-                            }
-                            Err(e) => {
-                                write!(stderr(), "Failed to stop: {}", e).unwrap_or(());
-                            }
-                        }
+                        env.stop_port_forward(i);
+                        clickwriteln!(writer, "Stopped");
                     } else {
                         clickwriteln!(writer, "Not stopping");
                     }
@@ -3236,7 +5345,9 @@ command!(
                 .long("regex")
                 .help("Filter jobs by the specified regex")
                 .takes_value(true)
-        ),
+        )
+        .arg(limit_arg())
+        .arg(watch_arg()),
     vec!["job", "jobs"],
     noop_complete!(),
     no_named_complete!(),
@@ -3260,12 +5371,23 @@ command!(
             urlstr.push_str(label_selector);
         }
 
-        let jl: Option<JobList> = env.run_on_kluster(|k| k.get(urlstr.as_str()));
+        let jl = get_list(
+            env,
+            urlstr.as_str(),
+            &matches,
+            writer,
+            |j: JobList, start, writer| {
+                print_jobs(
+                    j,
+                    matches.is_present("labels"),
+                    regex.clone(),
+                    start,
+                    writer,
+                )
+            },
+        );
         match jl {
-            Some(j) => {
-                let final_list = print_jobs(j, matches.is_present("labels"), regex, writer);
-                env.set_last_objs(VecWrap::from(final_list));
-            }
+            Some(final_list) => env.set_last_objs(VecWrap::from(final_list)),
             None => env.clear_last_objs(),
         }
     }
@@ -3275,6 +5397,7 @@ fn print_jobs(
     joblist: JobList,
     _show_labels: bool,
     regex: Option<Regex>,
+    start: usize,
     writer: &mut ClickWriter,
 ) -> JobList {
     let mut table = Table::new();
@@ -3307,12 +5430,317 @@ fn print_jobs(
         None => jobs_specs.collect(),
     };
 
-    crate::table::print_table(&mut table, &filtered, writer);
+    crate::table::print_table_from(&mut table, &filtered, start, writer);
 
     let final_jobs = filtered.into_iter().map(|job_spec| job_spec.0).collect();
     JobList { items: final_jobs }
 }
 
+command!(
+    Get,
+    "get",
+    "Get any kind of resource (in current namespace if set), including custom resources",
+    |clap: App<'static, 'static>| {
+        clap
+        .arg(
+            Arg::with_name("kind")
+                .help(
+                    "The kind of resource to get. Can be the kind, plural or short name, and can \
+                     be followed by .group if the name is ambiguous (i.e. certificates.cert-manager.io)"
+                )
+                .required(true)
+                .index(1)
+        )
+        .arg(
+            Arg::with_name("show_label")
+                .short("L")
+                .long("labels")
+                .help("Show labels")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("regex")
+                .short("r")
+                .long("regex")
+                .help("Filter resources by the specified regex")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("sort")
+                .short("s")
+                .long("sort")
+                .help(
+                    "Sort by specified column (if column isn't shown by default, it will \
+                     be shown)"
+                )
+                .takes_value(true)
+                .conflicts_with("watch")
+        )
+        .arg(
+            Arg::with_name("reverse")
+                .short("R")
+                .long("reverse")
+                .help("Reverse the order of the returned list")
+                .takes_value(false)
+                .conflicts_with("watch")
+        )
+        .arg(limit_arg())
+        .arg(watch_arg())
+    },
+    vec!["get"],
+    vec![&completer::resource_kind_completer],
+    no_named_complete!(),
+    |matches, env, writer| {
+        let regex = match crate::table::get_regex(&matches) {
+            Ok(r) => r,
+            Err(s) => {
+                writeln!(stderr(), "{}", s).unwrap_or(());
+                return;
+            }
+        };
+
+        let kind = matches.value_of("kind").unwrap(); // safe as required
+        let resource = match env.run_on_kluster(|k| k.discovery()) {
+            Some(discovery) => match discovery.find(kind) {
+                Some(res) => res.clone(),
+                None => {
+                    writeln!(stderr(), "Server doesn't have a resource type \"{}\"", kind)
+                        .unwrap_or(());
+                    return;
+                }
+            },
+            None => return,
+        };
+        if !resource.has_verb("list") {
+            writeln!(stderr(), "{} can't be listed", resource.kind).unwrap_or(());
+            return;
+        }
+
+        let url = resource.list_url(env.namespace.as_deref());
+        let show_namespace = resource.namespaced && env.namespace.is_none();
+        if !matches.is_present("watch") {
+            // let the server decide what columns to show
+            let table = get_pages(
+                env,
+                url.as_str(),
+                &matches,
+                writer,
+                |k, u| k.get_table(u),
+                |t: ServerTable, start, writer| {
+                    print_server_table(
+                        t,
+                        matches.is_present("show_label"),
+                        show_namespace,
+                        regex.clone(),
+                        matches.value_of("sort"),
+                        matches.is_present("reverse"),
+                        start,
+                        writer,
+                    )
+                },
+            );
+            match table {
+                Some(table) => {
+                    let objs: Vec<KObj> = table
+                        .rows
+                        .iter()
+                        .filter_map(|row| {
+                            KObj::from_value(&row.object, ObjType::Dynamic(resource.clone()))
+                        })
+                        .collect();
+                    env.set_last_objs(objs)
+                }
+                None => env.clear_last_objs(),
+            }
+            return;
+        }
+
+        let list = get_list(
+            env,
+            url.as_str(),
+            &matches,
+            writer,
+            |l: ResourceList, start, writer| {
+                print_resources(
+                    l,
+                    matches.is_present("show_label"),
+                    show_namespace,
+                    regex.clone(),
+                    start,
+                    writer,
+                )
+            },
+        );
+        match list {
+            Some(final_list) => {
+                let objs: Vec<KObj> = final_list
+                    .items
+                    .iter()
+                    .filter_map(|v| KObj::from_value(v, ObjType::Dynamic(resource.clone())))
+                    .collect();
+                env.set_last_objs(objs)
+            }
+            None => env.clear_last_objs(),
+        }
+    }
+);
+
+/// Print a table rendered by the server, returning the rows in the order they were printed.
+/// Nothing is printed if sort is a column that doesn't exist
+#[allow(clippy::too_many_arguments)]
+fn print_server_table(
+    mut server_table: ServerTable,
+    show_labels: bool,
+    show_namespace: bool,
+    regex: Option<Regex>,
+    sort: Option<&str>,
+    reverse: bool,
+    start: usize,
+    writer: &mut ClickWriter,
+) -> ServerTable {
+    let sort_col = match sort {
+        Some(name) => match server_table.column_index(name) {
+            Some(col) => Some(col),
+            None => {
+                let names: Vec<&str> = server_table
+                    .columns
+                    .iter()
+                    .map(|col| col.name.as_str())
+                    .collect();
+                writeln!(
+                    stderr(),
+                    "Can't sort by {}, valid columns are: {}",
+                    name,
+                    names.join(", ")
+                )
+                .unwrap_or(());
+                server_table.rows.clear();
+                return server_table;
+            }
+        },
+        None => None,
+    };
+    if let Some(col) = sort_col {
+        server_table.sort_by_column(col);
+    }
+    if reverse {
+        server_table.rows.reverse();
+    }
+
+    // wide columns are only shown if we're sorting by them
+    let shown: Vec<usize> = (0..server_table.columns.len())
+        .filter(|i| server_table.columns[*i].priority == 0 || Some(*i) == sort_col)
+        .collect();
+
+    let mut table = Table::new();
+    let mut title_row = row!["####"];
+    for i in shown.iter() {
+        title_row.add_cell(Cell::new(&server_table.columns[*i].name));
+    }
+    if show_namespace {
+        title_row.add_cell(Cell::new("Namespace"));
+    }
+    if show_labels {
+        title_row.add_cell(Cell::new("Labels"));
+    }
+    table.set_titles(title_row);
+
+    let columns = server_table.columns;
+    let specs = server_table.rows.into_iter().map(|row| {
+        let mut specs = vec![CellSpec::new_index()];
+        for i in shown.iter() {
+            let text = match row.cells.get(*i) {
+                Some(Value::String(s)) if columns[*i].typ == "date" => {
+                    match s.parse::<DateTime<Utc>>() {
+                        Ok(ts) => time_since(ts),
+                        Err(_) => s.clone(),
+                    }
+                }
+                Some(cell) => table_cell_string(cell),
+                None => "".to_owned(),
+            };
+            specs.push(CellSpec::new_owned(text));
+        }
+        if show_namespace {
+            specs.push(CellSpec::new_owned(
+                val_str("/metadata/namespace", &row.object, "[Unknown]").into_owned(),
+            ));
+        }
+        if show_labels {
+            let labels = row
+                .object
+                .pointer("/metadata/labels")
+                .and_then(Value::as_object)
+                .cloned();
+            specs.push(CellSpec::new_owned(keyval_string(&labels)));
+        }
+        (row, specs)
+    });
+
+    let filtered = match regex {
+        Some(r) => crate::table::filter(specs, r),
+        None => specs.collect(),
+    };
+
+    crate::table::print_table_from(&mut table, &filtered, start, writer);
+
+    let rows = filtered.into_iter().map(|spec| spec.0).collect();
+    ServerTable { columns, rows }
+}
+
+/// Print a list of any kind of resource. Since we don't know anything about what's in them, this
+/// only shows things from the metadata. This is used when watching, since the server can't
+/// render a table for us then
+fn print_resources(
+    list: ResourceList,
+    show_labels: bool,
+    show_namespace: bool,
+    regex: Option<Regex>,
+    start: usize,
+    writer: &mut ClickWriter,
+) -> ResourceList {
+    let mut table = Table::new();
+    let mut title_row = row!["####", "Name"];
+    if show_namespace {
+        title_row.add_cell(Cell::new("Namespace"));
+    }
+    title_row.add_cell(Cell::new("Age"));
+    if show_labels {
+        title_row.add_cell(Cell::new("Labels"));
+    }
+    table.set_titles(title_row);
+
+    let specs = list.items.into_iter().map(|item| {
+        let mut specs = Vec::new();
+        let metadata: Metadata = get_val_as("/metadata", &item).unwrap();
+        specs.push(CellSpec::new_index());
+        specs.push(CellSpec::new_owned(metadata.name));
+        if show_namespace {
+            specs.push(CellSpec::new_owned(
+                metadata.namespace.unwrap_or_else(|| "[Unknown]".to_owned()),
+            ));
+        }
+        specs.push(match metadata.creation_timestamp {
+            Some(ts) => CellSpec::new_owned(time_since(ts)),
+            None => CellSpec::new("unknown"),
+        });
+        if show_labels {
+            specs.push(CellSpec::new_owned(keyval_string(&metadata.labels)));
+        }
+        (item, specs)
+    });
+
+    let filtered = match regex {
+        Some(r) => crate::table::filter(specs, r),
+        None => specs.collect(),
+    };
+
+    crate::table::print_table_from(&mut table, &filtered, start, writer);
+
+    let final_items = filtered.into_iter().map(|spec| spec.0).collect();
+    ResourceList { items: final_items }
+}
+
 command!(
     Alias,
     "alias",
@@ -3401,3 +5829,361 @@ command!(
         }
     }
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_url() {
+        assert_eq!(
+            page_url("/api/v1/pods", 500, None),
+            "/api/v1/pods?limit=500"
+        );
+        assert_eq!(
+            page_url("/api/v1/pods", 20, Some("abc")),
+            "/api/v1/pods?limit=20&continue=abc"
+        );
+        assert_eq!(
+            page_url("/api/v1/pods?labelSelector=app%3Dweb", 500, None),
+            "/api/v1/pods?labelSelector=app%3Dweb&limit=500"
+        );
+        assert_eq!(
+            page_url(
+                "/api/v1/pods?fieldSelector=spec.nodeName%3Dn1",
+                7,
+                Some("abc")
+            ),
+            "/api/v1/pods?fieldSelector=spec.nodeName%3Dn1&limit=7&continue=abc"
+        );
+    }
+
+    /// Run get_pages against a fake server that serves count configmaps, at most two per page.
+    /// Returns the urls fetched, the start index each page was printed with, and the (index, name)
+    /// of each row, in the order they were printed
+    fn fetch_pages(
+        count: usize,
+        args: &[&str],
+    ) -> (Vec<String>, Vec<usize>, Vec<(String, String)>) {
+        let mut env = Env::new(
+            config::get_test_config(),
+            config::ClickConfig::default(),
+            PathBuf::from("/tmp/click.config"),
+        );
+        env.kluster = Some(
+            Kluster::new(
+                "test",
+                None,
+                "https://kube.test:443",
+                None,
+                None,
+                true,
+                10,
+                20,
+            )
+            .unwrap(),
+        );
+        let matches = App::new("configmaps")
+            .arg(limit_arg())
+            .arg(Arg::with_name("sort").long("sort").takes_value(true))
+            .get_matches_from(args);
+        let urls = RefCell::new(vec![]);
+        let fetch = |_: &Kluster, url: &str| {
+            urls.borrow_mut().push(url.to_owned());
+            let param = |name: &str| {
+                url.split(&['?', '&'][..])
+                    .find_map(|p| p.strip_prefix(name))
+                    .map(|v| v.parse::<usize>().unwrap())
+            };
+            let from = param("continue=").unwrap_or(0);
+            let to = (from + param("limit=").unwrap().min(2)).min(count);
+            let items: Vec<Value> = (from..to)
+                .map(|i| json!({"metadata": {"name": format!("cm{}", i)}}))
+                .collect();
+            let cont = if to < count {
+                to.to_string()
+            } else {
+                "".to_owned()
+            };
+            Ok(json!({"metadata": {"continue": cont}, "items": items}))
+        };
+        let mut starts = vec![];
+        let mut rows = vec![];
+        let list = get_pages(
+            &env,
+            "/api/v1/configmaps",
+            &matches,
+            &mut ClickWriter::new(),
+            fetch,
+            |list: ConfigMapList, start, _| {
+                starts.push(start);
+                let specs = list
+                    .items
+                    .into_iter()
+                    .map(|cm| {
+                        let name = val_str("/metadata/name", &cm, "").into_owned();
+                        (cm, vec![CellSpec::new_index(), CellSpec::new_owned(name)])
+                    })
+                    .collect();
+                let mut table = Table::new();
+                crate::table::add_rows_from(&mut table, &specs, start);
+                rows.extend(table.row_iter().map(|row| {
+                    let content = |i| row.get_cell(i).unwrap().get_content();
+                    (content(0), content(1))
+                }));
+                ConfigMapList {
+                    items: specs.into_iter().map(|(cm, _)| cm).collect(),
+                }
+            },
+        )
+        .unwrap();
+        assert_eq!(list.items.len(), rows.len());
+        (urls.into_inner(), starts, rows)
+    }
+
+    #[test]
+    fn test_get_pages() {
+        let numbered = |count: usize| -> Vec<(String, String)> {
+            (0..count)
+                .map(|i| (i.to_string(), format!("cm{}", i)))
+                .collect()
+        };
+        let (urls, starts, rows) = fetch_pages(5, &["configmaps"]);
+        assert_eq!(
+            urls,
+            vec![
+                "/api/v1/configmaps?limit=500",
+                "/api/v1/configmaps?limit=500&continue=2",
+                "/api/v1/configmaps?limit=500&continue=4",
+            ]
+        );
+        assert_eq!(starts, vec![0, 2, 4]);
+        assert_eq!(rows, numbered(5));
+
+        // with a limit, later pages only ask for what's left
+        let (urls, starts, rows) = fetch_pages(5, &["configmaps", "--limit", "3"]);
+        assert_eq!(
+            urls,
+            vec![
+                "/api/v1/configmaps?limit=3",
+                "/api/v1/configmaps?limit=1&continue=2",
+            ]
+        );
+        assert_eq!(starts, vec![0, 2]);
+        assert_eq!(rows, numbered(3));
+
+        // sorting needs the whole list, so it's printed once at the end
+        let (urls, starts, rows) = fetch_pages(5, &["configmaps", "--sort", "name"]);
+        assert_eq!(urls.len(), 3);
+        assert_eq!(starts, vec![0]);
+        assert_eq!(rows, numbered(5));
+    }
+
+    #[test]
+    fn test_api_versions() {
+        let groups: Vec<ApiGroup> = serde_json::from_value(json!([
+            {"name": "", "versions": [{"groupVersion": "v1", "version": "v1"}]},
+            {
+                "name": "apps",
+                "versions": [{"groupVersion": "apps/v1", "version": "v1"}],
+                "preferredVersion": {"groupVersion": "apps/v1", "version": "v1"},
+            },
+            {
+                "name": "autoscaling",
+                "versions": [
+                    {"groupVersion": "autoscaling/v2", "version": "v2"},
+                    {"groupVersion": "autoscaling/v1", "version": "v1"},
+                ],
+            },
+            {"name": "apps", "versions": [{"groupVersion": "apps/v1", "version": "v1"}]},
+        ]))
+        .unwrap();
+        assert_eq!(
+            api_versions(&groups, None),
+            vec!["apps/v1", "autoscaling/v1", "autoscaling/v2", "v1"]
+        );
+        assert_eq!(
+            api_versions(&groups, Some(Regex::new("^auto.*v2$").unwrap())),
+            vec!["autoscaling/v2"]
+        );
+        assert_eq!(
+            api_versions(&groups, Some(Regex::new("v1").unwrap())),
+            vec!["apps/v1", "autoscaling/v1", "v1"]
+        );
+        assert!(api_versions(&groups, Some(Regex::new("batch").unwrap())).is_empty());
+        assert!(api_versions(&[], None).is_empty());
+    }
+
+    #[test]
+    fn test_edit() {
+        let body = "metadata:\n  name: web\n";
+        let edited = edit_contents(body, None);
+        assert_eq!(strip_edit_header(&edited), body);
+        let failed = edit_contents(body, Some(&KubeError::ParseErr("bad\nthings".to_owned())));
+        assert_eq!(strip_edit_header(&failed), body);
+        assert_eq!(strip_edit_header("# only comments"), "");
+
+        let orig = json!({"metadata": {"name": "web"}});
+        assert!(matches!(
+            parse_edit(" \n", &orig),
+            EditOutcome::Cancelled("file was empty")
+        ));
+        assert!(matches!(
+            parse_edit(body, &orig),
+            EditOutcome::Cancelled("no changes made")
+        ));
+        match parse_edit("metadata:\n  name: api\n", &orig) {
+            EditOutcome::Changed(val) => assert_eq!(val, json!({"metadata": {"name": "api"}})),
+            _ => panic!("edit should have changed the object"),
+        }
+        assert!(matches!(
+            parse_edit("metadata: [name: web\n", &orig),
+            EditOutcome::Invalid(_)
+        ));
+    }
+
+    #[test]
+    fn test_scale() {
+        assert!(valid_relative("+2".to_owned()).is_ok());
+        assert!(valid_relative("-1".to_owned()).is_ok());
+        assert!(valid_relative("3".to_owned()).is_err());
+        assert!(valid_relative("+".to_owned()).is_err());
+        assert!(valid_relative("+two".to_owned()).is_err());
+        assert!(valid_relative("--1".to_owned()).is_err());
+
+        assert_eq!(scale_target(3, 2, true), Ok(5));
+        assert_eq!(scale_target(3, -1, true), Ok(2));
+        assert_eq!(scale_target(3, 7, false), Ok(7));
+        assert_eq!(scale_target(3, 0, false), Ok(0));
+        assert_eq!(scale_target(1, -2, true), Err(-1));
+        assert_eq!(scale_target(i64::from(i32::MAX), 1, true), Err(1 << 31));
+    }
+
+    fn rollout_msg(dep: Value) -> String {
+        match deployment_rollout_status("web", &dep) {
+            RolloutStatus::Progressing(msg) => format!("progressing: {}", msg),
+            RolloutStatus::Done(msg) => format!("done: {}", msg),
+            RolloutStatus::Failed(msg) => format!("failed: {}", msg),
+        }
+    }
+
+    fn status_dep(desired: u64, replicas: u64, updated: u64, available: u64) -> Value {
+        json!({
+            "metadata": {"generation": 2},
+            "spec": {"replicas": desired},
+            "status": {
+                "observedGeneration": 2,
+                "replicas": replicas,
+                "updatedReplicas": updated,
+                "availableReplicas": available,
+            },
+        })
+    }
+
+    #[test]
+    fn test_rollout_status() {
+        assert_eq!(
+            rollout_msg(
+                json!({"metadata": {"generation": 3}, "status": {"observedGeneration": 2}})
+            ),
+            "progressing: Waiting for deployment spec update to be observed..."
+        );
+        let mut failed = status_dep(3, 3, 3, 3);
+        failed["status"]["conditions"] = json!([
+            {"type": "Available", "reason": "MinimumReplicasAvailable"},
+            {"type": "Progressing", "reason": "ProgressDeadlineExceeded"},
+        ]);
+        assert_eq!(
+            rollout_msg(failed),
+            "failed: deployment web exceeded its progress deadline"
+        );
+        assert_eq!(
+            rollout_msg(status_dep(3, 4, 1, 3)),
+            "progressing: Waiting for deployment web rollout to finish: 1 out of 3 new replicas \
+             have been updated..."
+        );
+        assert_eq!(
+            rollout_msg(status_dep(3, 5, 3, 3)),
+            "progressing: Waiting for deployment web rollout to finish: 2 old replicas are \
+             pending termination..."
+        );
+        assert_eq!(
+            rollout_msg(status_dep(3, 3, 3, 1)),
+            "progressing: Waiting for deployment web rollout to finish: 1 of 3 updated replicas \
+             are available..."
+        );
+        assert_eq!(
+            rollout_msg(status_dep(3, 3, 3, 3)),
+            "done: deployment web successfully rolled out"
+        );
+        assert_eq!(
+            rollout_msg(status_dep(0, 0, 0, 0)),
+            "done: deployment web successfully rolled out"
+        );
+        // spec.replicas defaults to 1
+        assert_eq!(
+            rollout_msg(json!({"status": {"replicas": 1}})),
+            "progressing: Waiting for deployment web rollout to finish: 0 out of 1 new replicas \
+             have been updated..."
+        );
+    }
+
+    fn revision_rs(revision: &str, image: &str) -> Value {
+        json!({
+            "metadata": {
+                "name": format!("web-{}", revision),
+                "annotations": {REVISION_ANNOTATION: revision},
+            },
+            "spec": {"template": {
+                "metadata": {"labels": {"app": "web", "pod-template-hash": revision}},
+                "spec": {"containers": [{"name": "web", "image": image}]},
+            }},
+        })
+    }
+
+    #[test]
+    fn test_rollout_undo() {
+        let rss = vec![
+            revision_rs("1", "web:1"),
+            revision_rs("3", "web:3"),
+            revision_rs("4", "web:4"),
+        ];
+        let target_name = |current, to_revision| {
+            undo_target(&rss, current, to_revision)
+                .map(|rs| val_str("/metadata/name", rs, "").into_owned())
+        };
+        assert_eq!(target_name(4, None), Some("web-3".to_owned()));
+        assert_eq!(target_name(3, None), Some("web-1".to_owned()));
+        assert_eq!(target_name(1, None), None);
+        assert_eq!(target_name(4, Some(1)), Some("web-1".to_owned()));
+        assert_eq!(target_name(4, Some(4)), Some("web-4".to_owned()));
+        assert_eq!(target_name(4, Some(2)), None);
+
+        assert_eq!(
+            revert_template(&rss[1]),
+            json!({
+                "metadata": {"labels": {"app": "web"}},
+                "spec": {"containers": [{"name": "web", "image": "web:3"}]},
+            })
+        );
+        assert_eq!(revert_template(&json!({})), Value::Null);
+    }
+
+    #[test]
+    fn test_parse_metadata_changes() {
+        let changes =
+            parse_metadata_changes(vec!["app=web", "tier-", "note=a=b", "empty="].into_iter());
+        assert_eq!(
+            changes.unwrap(),
+            vec![
+                ("app", Some("web")),
+                ("tier", None),
+                ("note", Some("a=b")),
+                ("empty", Some("")),
+            ]
+        );
+        assert!(parse_metadata_changes(vec!["app"].into_iter()).is_err());
+        assert!(parse_metadata_changes(vec!["=web"].into_iter()).is_err());
+        assert!(parse_metadata_changes(vec!["-"].into_iter()).is_err());
+    }
+}
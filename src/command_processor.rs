@@ -172,6 +172,7 @@ impl CommandProcessor {
             Box::new(crate::cmd::Namespace::new()),
             Box::new(crate::cmd::Logs::new()),
             Box::new(crate::cmd::Describe::new()),
+            Box::new(crate::cmd::Edit::new()),
             Box::new(crate::cmd::Exec::new()),
             Box::new(crate::cmd::Containers::new()),
             Box::new(crate::cmd::Events::new()),
@@ -179,12 +180,29 @@ impl CommandProcessor {
             Box::new(crate::cmd::EnvCmd::new()),
             Box::new(crate::cmd::SetCmd::new()),
             Box::new(crate::cmd::Delete::new()),
+            Box::new(crate::cmd::Scale::new()),
+            Box::new(crate::cmd::Label::new()),
+            Box::new(crate::cmd::Annotate::new()),
+            Box::new(crate::cmd::Cordon::new()),
+            Box::new(crate::cmd::Uncordon::new()),
+            Box::new(crate::cmd::Drain::new()),
+            Box::new(crate::cmd::Apply::new()),
+            Box::new(crate::cmd::Diff::new()),
+            Box::new(crate::cmd::Compare::new()),
+            Box::new(crate::cmd::Cp::new()),
+            Box::new(crate::cmd::Top::new()),
+            Box::new(crate::cmd::Capacity::new()),
+            Box::new(crate::cmd::Explain::new()),
+            Box::new(crate::cmd::ApiResources::new()),
+            Box::new(crate::cmd::ApiVersions::new()),
+            Box::new(crate::cmd::Rollout::new()),
             Box::new(crate::cmd::UtcCmd::new()),
             Box::new(crate::cmd::Namespaces::new()),
             Box::new(crate::cmd::Secrets::new()),
             Box::new(crate::cmd::PortForward::new()),
             Box::new(crate::cmd::PortForwards::new()),
             Box::new(crate::cmd::Jobs::new()),
+            Box::new(crate::cmd::Get::new()),
             Box::new(crate::cmd::Alias::new()),
             Box::new(crate::cmd::Unalias::new()),
         ];
@@ -469,7 +487,8 @@ to verify your commands will operate on the objects you expect.
 Once you have selected a range, you can run any of the following commands which will operate on each
 item in the range in turn:
 
-containers, describe, delete, events, exec, logs
+annotate, compare, containers, cordon, describe, delete, drain, edit, events, exec, label, logs, rollout,
+scale, uncordon
 
 \u{001b}[33;1mRANGE SEPARATOR\u{001b}[0m
 When printing output for the above commands over a range, Click will print a header for each item.
@@ -579,6 +598,7 @@ mod tests {
             },
             status: crate::kube::NodeStatus {
                 conditions: Vec::new(),
+                allocatable: None,
             },
         }
     }
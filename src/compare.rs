@@ -0,0 +1,320 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Comparing the same object in different clusters. Objects are flattened into a map of field
+//! paths (like spec.template.spec.containers[name=web].image) to values, after removing the fields
+//! that are always different between clusters, and the maps are compared.
+
+use crate::output::ClickWriter;
+
+use ansi_term::Colour::{Green, Red, Yellow};
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Which part of the objects to compare
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scope {
+    All,
+    Spec,
+    Labels,
+    Images,
+}
+
+/// Metadata that's set by the cluster, so is always different
+const CLUSTER_METADATA: [&str; 6] = [
+    "uid",
+    "resourceVersion",
+    "creationTimestamp",
+    "generation",
+    "managedFields",
+    "selfLink",
+];
+
+/// Annotations that are set by the cluster or tools, so are expected to be different
+const CLUSTER_ANNOTATIONS: [&str; 2] = [
+    "deployment.kubernetes.io/revision",
+    "kubectl.kubernetes.io/last-applied-configuration",
+];
+
+/// Remove status, and metadata the cluster sets
+fn normalize(obj: &Value) -> Value {
+    let mut obj = obj.clone();
+    if let Some(fields) = obj.as_object_mut() {
+        fields.remove("status");
+    }
+    if let Some(metadata) = obj.get_mut("metadata").and_then(Value::as_object_mut) {
+        for field in CLUSTER_METADATA.iter() {
+            metadata.remove(*field);
+        }
+    }
+    if let Some(annotations) = obj
+        .pointer_mut("/metadata/annotations")
+        .and_then(Value::as_object_mut)
+    {
+        for annotation in CLUSTER_ANNOTATIONS.iter() {
+            annotations.remove(*annotation);
+        }
+    }
+    obj
+}
+
+/// Flatten value into fields, with paths starting with path. Lists of objects that all have a
+/// name are keyed by the name rather than the index, so reordering them isn't a difference. Empty
+/// objects and lists are left out, as they mean the same as a missing field.
+fn flatten(value: &Value, path: String, fields: &mut BTreeMap<String, String>) {
+    let join = |key: &str| {
+        if path.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match value {
+        Value::Object(map) => {
+            for (key, val) in map.iter() {
+                flatten(val, join(key), fields);
+            }
+        }
+        Value::Array(items) => {
+            let names: Option<Vec<&str>> = items
+                .iter()
+                .map(|item| item.get("name").and_then(Value::as_str))
+                .collect();
+            for (idx, item) in items.iter().enumerate() {
+                let key = match names {
+                    Some(ref names) => format!("{}[name={}]", path, names[idx]),
+                    None => format!("{}[{}]", path, idx),
+                };
+                flatten(item, key, fields);
+            }
+        }
+        Value::String(s) => {
+            fields.insert(path, s.clone());
+        }
+        _ => {
+            fields.insert(path, value.to_string());
+        }
+    }
+}
+
+/// The images of all the containers in obj, which can be a pod or anything with a pod template
+fn images(obj: &Value) -> BTreeMap<String, String> {
+    let spec = obj
+        .pointer("/spec/template/spec")
+        .or_else(|| obj.pointer("/spec/jobTemplate/spec/template/spec"))
+        .or_else(|| obj.get("spec"));
+    let mut images = BTreeMap::new();
+    if let Some(spec) = spec {
+        for list in ["initContainers", "containers"].iter() {
+            for cont in spec
+                .get(*list)
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                let name = cont.get("name").and_then(Value::as_str).unwrap_or("");
+                let image = cont.get("image").and_then(Value::as_str).unwrap_or("");
+                images.insert(format!("{}[name={}]", list, name), image.to_owned());
+            }
+        }
+    }
+    images
+}
+
+/// The fields of obj to compare for scope
+pub fn fields(obj: &Value, scope: Scope) -> BTreeMap<String, String> {
+    let obj = normalize(obj);
+    let mut fields = BTreeMap::new();
+    match scope {
+        Scope::All => flatten(&obj, String::new(), &mut fields),
+        Scope::Spec => {
+            if let Some(spec) = obj.get("spec") {
+                flatten(spec, "spec".to_owned(), &mut fields);
+            }
+        }
+        Scope::Labels => {
+            if let Some(labels) = obj.pointer("/metadata/labels") {
+                flatten(labels, "metadata.labels".to_owned(), &mut fields);
+            }
+        }
+        Scope::Images => fields = images(&obj),
+    }
+    fields
+}
+
+#[derive(Debug, PartialEq)]
+enum FieldDiff<'a> {
+    Changed(&'a str, &'a str, &'a str),
+    OnlyFirst(&'a str, &'a str),
+    OnlySecond(&'a str, &'a str),
+}
+
+fn field_diffs<'a>(
+    first: &'a BTreeMap<String, String>,
+    second: &'a BTreeMap<String, String>,
+) -> Vec<FieldDiff<'a>> {
+    let mut diffs = vec![];
+    for (path, val) in first.iter() {
+        match second.get(path) {
+            Some(other) if other != val => diffs.push(FieldDiff::Changed(path, val, other)),
+            Some(_) => {}
+            None => diffs.push(FieldDiff::OnlyFirst(path, val)),
+        }
+    }
+    for (path, val) in second.iter() {
+        if !first.contains_key(path) {
+            diffs.push(FieldDiff::OnlySecond(path, val));
+        }
+    }
+    // keep things in path order, so related fields are together
+    diffs.sort_by_key(|diff| match diff {
+        FieldDiff::Changed(path, ..)
+        | FieldDiff::OnlyFirst(path, _)
+        | FieldDiff::OnlySecond(path, _) => *path,
+    });
+    diffs
+}
+
+/// Print the differences between the fields of an object in the contexts first_name and
+/// second_name
+pub fn print_comparison(
+    first_name: &str,
+    first: &BTreeMap<String, String>,
+    second_name: &str,
+    second: &BTreeMap<String, String>,
+    writer: &mut ClickWriter,
+) {
+    let diffs = field_diffs(first, second);
+    if diffs.is_empty() {
+        clickwriteln!(
+            writer,
+            "No differences between {} and {}",
+            first_name,
+            second_name
+        );
+        return;
+    }
+    let color = writer.is_stdout();
+    let mark = |colour: ansi_term::Colour, s: &str| {
+        if color {
+            colour.paint(s).to_string()
+        } else {
+            s.to_owned()
+        }
+    };
+    clickwriteln!(
+        writer,
+        "Differences between {} and {}:",
+        first_name,
+        second_name
+    );
+    for diff in diffs.iter() {
+        match diff {
+            FieldDiff::Changed(path, val, other) => clickwriteln!(
+                writer,
+                "  {} {}: {} -> {}",
+                mark(Yellow, "~"),
+                path,
+                val,
+                other
+            ),
+            FieldDiff::OnlyFirst(path, val) => clickwriteln!(
+                writer,
+                "  {} {}: {} (only in {})",
+                mark(Red, "-"),
+                path,
+                val,
+                first_name
+            ),
+            FieldDiff::OnlySecond(path, val) => clickwriteln!(
+                writer,
+                "  {} {}: {} (only in {})",
+                mark(Green, "+"),
+                path,
+                val,
+                second_name
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deployment(replicas: u64, image: &str, sidecar: bool) -> Value {
+        let mut containers = vec![json!({"name": "web", "image": image})];
+        if sidecar {
+            containers.insert(0, json!({"name": "proxy", "image": "envoy:1"}));
+        }
+        json!({
+            "metadata": {
+                "name": "web",
+                "uid": "abc",
+                "labels": {"app": "web"},
+                "annotations": {"deployment.kubernetes.io/revision": "4"},
+            },
+            "spec": {
+                "replicas": replicas,
+                "template": {"spec": {"containers": containers}},
+            },
+            "status": {"readyReplicas": replicas},
+        })
+    }
+
+    #[test]
+    fn test_fields() {
+        let fields = fields(&deployment(3, "web:1", true), Scope::All);
+        assert_eq!(fields.get("spec.replicas").map(|s| s.as_str()), Some("3"));
+        assert_eq!(
+            fields
+                .get("spec.template.spec.containers[name=web].image")
+                .map(|s| s.as_str()),
+            Some("web:1")
+        );
+        assert!(!fields.contains_key("metadata.uid"));
+        assert!(!fields.keys().any(|k| k.starts_with("status")));
+        assert!(!fields.keys().any(|k| k.starts_with("metadata.annotations")));
+
+        let images = super::fields(&deployment(3, "web:1", true), Scope::Images);
+        assert_eq!(images.len(), 2);
+        assert_eq!(
+            images.get("containers[name=web]").map(|s| s.as_str()),
+            Some("web:1")
+        );
+    }
+
+    #[test]
+    fn test_field_diffs() {
+        let first = fields(&deployment(3, "web:1", true), Scope::Spec);
+        let second = fields(&deployment(5, "web:2", false), Scope::Spec);
+        assert_eq!(
+            field_diffs(&first, &second),
+            vec![
+                FieldDiff::Changed("spec.replicas", "3", "5"),
+                FieldDiff::OnlyFirst("spec.template.spec.containers[name=proxy].image", "envoy:1"),
+                FieldDiff::OnlyFirst("spec.template.spec.containers[name=proxy].name", "proxy"),
+                FieldDiff::Changed(
+                    "spec.template.spec.containers[name=web].image",
+                    "web:1",
+                    "web:2"
+                ),
+            ]
+        );
+        let labels = fields(&deployment(3, "web:1", true), Scope::Labels);
+        assert!(field_diffs(&labels, &labels).is_empty());
+    }
+}
@@ -294,6 +294,63 @@ pub fn container_completer(prefix: &str, env: &Env) -> Vec<Pair> {
     v
 }
 
+/// Complete kinds of resource. This only uses discovery if it's already been fetched, so it doesn't
+/// hang waiting for the cluster
+pub fn resource_kind_completer(prefix: &str, env: &Env) -> Vec<Pair> {
+    let discovery = match env.kluster.as_ref().and_then(|k| k.cached_discovery()) {
+        Some(d) => d,
+        None => return vec![],
+    };
+    let mut v = vec![];
+    for res in discovery.resources.iter() {
+        if let Some(rest) = res.name.strip_prefix(prefix) {
+            if !v.iter().any(|p: &Pair| p.display == res.name) {
+                v.push(Pair {
+                    display: res.name.clone(),
+                    replacement: rest.to_string(),
+                });
+            }
+        }
+    }
+    v
+}
+
+/// Complete what to explain: a kind of resource, and then the path of a field in it. Like
+/// resource_kind_completer, this only uses discovery and the OpenAPI schema if they're already
+/// loaded (or the schema is cached on disk)
+pub fn explain_completer(prefix: &str, env: &Env) -> Vec<Pair> {
+    let (parent, field_prefix) = match prefix.rfind('.') {
+        Some(pos) => (&prefix[..pos], &prefix[(pos + 1)..]),
+        None => return resource_kind_completer(prefix, env),
+    };
+    let kluster = match env.kluster.as_ref() {
+        Some(k) => k,
+        None => return vec![],
+    };
+    let discovery = match kluster.cached_discovery() {
+        Some(d) => d,
+        None => return vec![],
+    };
+    let (resource, path) = match crate::explain::split_path(&discovery, parent) {
+        Some(split) => split,
+        None => return vec![],
+    };
+    let cache_path = crate::explain::cache_path(env.config_dir(), &kluster.name);
+    let defs = match kluster.cached_openapi_definitions(&cache_path) {
+        Some(defs) => defs,
+        None => return vec![],
+    };
+    crate::explain::complete_fields(&defs, resource, &path)
+        .into_iter()
+        .filter_map(|field| {
+            field.strip_prefix(field_prefix).map(|rest| Pair {
+                display: field.to_string(),
+                replacement: rest.to_string(),
+            })
+        })
+        .collect()
+}
+
 macro_rules! possible_values_completer {
     ($name: ident, $values: expr) => {
         pub fn $name(prefix: &str, _env: &Env) -> Vec<Pair> {
@@ -318,6 +375,11 @@ possible_values_completer!(
     ["list", "output", "stop"]
 );
 
+possible_values_completer!(
+    rolloutaction_values_completer,
+    ["status", "restart", "history", "undo"]
+);
+
 possible_values_completer!(
     deployment_sort_values_completer,
     [
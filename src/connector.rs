@@ -20,11 +20,13 @@
 
 use std::io;
 use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::time::Duration;
 
 use hyper::error::Result;
 use hyper::net::{HttpStream, HttpsStream, NetworkConnector, SslClient};
 
+#[derive(Clone)]
 pub struct ClickSslConnector<S: SslClient> {
     ssl: S,
     host_addr: Option<(String, String)>,
@@ -84,15 +86,32 @@ impl<S: SslClient> ClickSslConnector<S> {
     }
 }
 
-impl<S: SslClient> NetworkConnector for ClickSslConnector<S> {
-    type Stream = HttpsStream<S::Stream>;
-
-    fn connect(&self, host: &str, port: u16, scheme: &str) -> Result<Self::Stream> {
+impl<S: SslClient> ClickSslConnector<S> {
+    /// Like connect, but also returns the fd of the underlying socket, so it can be polled. Note
+    /// that with https, data can be buffered above the socket, so it not being readable doesn't
+    /// mean there's nothing to read.
+    pub fn connect_fd(
+        &self,
+        host: &str,
+        port: u16,
+        scheme: &str,
+    ) -> Result<(HttpsStream<S::Stream>, RawFd)> {
         let stream = self.click_connect(host, port, "http")?;
+        let fd = stream.0.as_raw_fd();
         if scheme == "https" {
-            self.ssl.wrap_client(stream, host).map(HttpsStream::Https)
+            let stream = self.ssl.wrap_client(stream, host).map(HttpsStream::Https)?;
+            Ok((stream, fd))
         } else {
-            Ok(HttpsStream::Http(stream))
+            Ok((HttpsStream::Http(stream), fd))
         }
     }
 }
+
+impl<S: SslClient> NetworkConnector for ClickSslConnector<S> {
+    type Stream = HttpsStream<S::Stream>;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> Result<Self::Stream> {
+        self.connect_fd(host, port, scheme)
+            .map(|(stream, _)| stream)
+    }
+}
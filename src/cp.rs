@@ -0,0 +1,534 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Copying files to and from containers. Like kubectl cp, this runs tar in the container over
+//! exec, and streams the archive over stdin or stdout. So tar needs to be installed in the
+//! container.
+//!
+//! When copying to a container, stdin is closed once the archive has been sent, as GNU tar keeps
+//! reading until EOF even after the end of archive marker. That needs v5.channel.k8s.io, so on
+//! servers that only speak v4 copies to a container can only finish if the container's tar stops
+//! at the marker (like busybox's does).
+
+use crate::error::KubeError;
+use crate::exec::{self, ERROR_CHANNEL, STDERR_CHANNEL, STDIN_CHANNEL, STDOUT_CHANNEL};
+use crate::kube::{Kluster, KlusterStream};
+use crate::output::ClickWriter;
+use crate::tar::{EntryKind, TarReader, TarWriter};
+use crate::websocket::{Message, WebSocket};
+
+use std::fs;
+use std::io::{self, stderr, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// Only show progress for files at least this big
+const PROGRESS_SIZE: u64 = 1024 * 1024;
+/// Send stdin in chunks of this size
+const STDIN_CHUNK: usize = 32 * 1024;
+
+fn human_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+/// Shows how much of a large file has been copied on stderr, so it doesn't end up in redirected
+/// output
+struct Progress<'a> {
+    name: &'a str,
+    total: u64,
+    done: u64,
+    shown: Option<Instant>,
+}
+
+impl<'a> Progress<'a> {
+    fn new(name: &'a str, total: u64) -> Progress<'a> {
+        Progress {
+            name,
+            total,
+            done: 0,
+            shown: None,
+        }
+    }
+
+    fn update(&mut self, amt: u64) {
+        self.done += amt;
+        let due = match self.shown {
+            Some(at) => at.elapsed() >= Duration::from_millis(250),
+            None => true,
+        };
+        if self.total >= PROGRESS_SIZE && due {
+            self.shown = Some(Instant::now());
+            eprint!(
+                "\r{}: {} / {} ({}%)",
+                self.name,
+                human_bytes(self.done),
+                human_bytes(self.total),
+                self.done * 100 / self.total
+            );
+            stderr().flush().unwrap_or(());
+        }
+    }
+
+    fn finish(self) {
+        if self.shown.is_some() {
+            eprintln!(
+                "\r{}: {} / {} (100%)",
+                self.name,
+                human_bytes(self.total),
+                human_bytes(self.total)
+            );
+        }
+    }
+}
+
+fn interrupted() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "Copy interrupted")
+}
+
+/// The output of a command running over exec. stdout can be read through the Read impl, and
+/// stderr and the exit status are collected as they arrive.
+struct ExecOutput<'a> {
+    ws: &'a mut WebSocket<KlusterStream>,
+    ctrlcbool: &'a AtomicBool,
+    buf: Vec<u8>,
+    pos: usize,
+    stderr: Vec<u8>,
+    status: Option<Result<i32, KubeError>>,
+    closed: bool,
+}
+
+impl<'a> ExecOutput<'a> {
+    fn new(ws: &'a mut WebSocket<KlusterStream>, ctrlcbool: &'a AtomicBool) -> ExecOutput<'a> {
+        ExecOutput {
+            ws,
+            ctrlcbool,
+            buf: vec![],
+            pos: 0,
+            stderr: vec![],
+            status: None,
+            closed: false,
+        }
+    }
+
+    /// Handle the next message, returning false once the command is done
+    fn read_next(&mut self) -> io::Result<bool> {
+        if self.closed || self.status.is_some() {
+            return Ok(false);
+        }
+        if self.ctrlcbool.load(Ordering::SeqCst) {
+            return Err(interrupted());
+        }
+        match self.ws.read_message()? {
+            Some(Message::Data(data)) => match data.split_first() {
+                Some((&STDOUT_CHANNEL, out)) => {
+                    self.buf = out.to_vec();
+                    self.pos = 0;
+                }
+                Some((&STDERR_CHANNEL, err)) => self.stderr.extend_from_slice(err),
+                Some((&ERROR_CHANNEL, err)) => {
+                    self.status = Some(match serde_json::from_slice(err) {
+                        Ok(s) => exec::exit_status(&s),
+                        Err(e) => Err(KubeError::from(e)),
+                    });
+                }
+                _ => {}
+            },
+            Some(Message::Close) => self.closed = true,
+            None => {} // nothing yet
+        }
+        Ok(true)
+    }
+
+    /// Wait for the command to finish, and check it succeeded
+    fn finish(mut self) -> Result<(), KubeError> {
+        while self.read_next()? {}
+        let err_msg = String::from_utf8_lossy(&self.stderr).trim().to_owned();
+        match self.status {
+            Some(Ok(0)) => Ok(()),
+            Some(Ok(code)) if !err_msg.is_empty() => Err(KubeError::KubeServerError(format!(
+                "tar exited with code {}: {}",
+                code, err_msg
+            ))),
+            Some(Ok(code)) => Err(KubeError::KubeServerError(format!(
+                "tar exited with code {}",
+                code
+            ))),
+            Some(Err(e)) => Err(e),
+            None => Err(KubeError::KubeServerError(
+                "Connection closed before tar finished".to_owned(),
+            )),
+        }
+    }
+}
+
+impl<'a> Read for ExecOutput<'a> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.buf.len() {
+            if !self.read_next()? {
+                return Ok(0);
+            }
+        }
+        let amt = std::cmp::min(out.len(), self.buf.len() - self.pos);
+        out[..amt].copy_from_slice(&self.buf[self.pos..(self.pos + amt)]);
+        self.pos += amt;
+        Ok(amt)
+    }
+}
+
+/// Sends what's written to it to the stdin of a command running over exec
+struct ExecInput<'a> {
+    ws: &'a mut WebSocket<KlusterStream>,
+    ctrlcbool: &'a AtomicBool,
+    buf: Vec<u8>,
+}
+
+impl<'a> Write for ExecInput<'a> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.ctrlcbool.load(Ordering::SeqCst) {
+            return Err(interrupted());
+        }
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= STDIN_CHUNK {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.len() > 1 {
+            self.ws.send_binary(&self.buf)?;
+            self.buf.truncate(1);
+        }
+        Ok(())
+    }
+}
+
+/// Turn a path from an archive into a relative path, or None if it's absolute or goes up a
+/// directory, which we refuse to write
+fn safe_path(path: &str) -> Option<PathBuf> {
+    let mut safe = PathBuf::new();
+    for comp in Path::new(path).components() {
+        match comp {
+            Component::Normal(part) => safe.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if safe.as_os_str().is_empty() {
+        None
+    } else {
+        Some(safe)
+    }
+}
+
+/// Split a path in a container into its parent directory and last component
+fn split_remote(path: &str) -> Result<(&str, &str), KubeError> {
+    let path = path.trim_end_matches('/');
+    let (parent, name) = match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(pos) => (&path[..pos], &path[(pos + 1)..]),
+        None => (".", path),
+    };
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(KubeError::ParseErr(format!("Can't copy {}", path)));
+    }
+    Ok((parent, name))
+}
+
+/// Where the entry at path in an archive of name should be written, given that name is being
+/// copied to dest. None if the entry isn't under name, or isn't safe to write.
+fn local_target(path: &str, name: &str, dest: &Path) -> Option<PathBuf> {
+    let rest = safe_path(path)?.strip_prefix(name).ok()?.to_path_buf();
+    if rest.as_os_str().is_empty() {
+        // name itself, which joining would turn into dest/
+        Some(dest.to_path_buf())
+    } else {
+        Some(dest.join(rest))
+    }
+}
+
+/// Copy remote, a file or directory in the container, to local. If local is an existing
+/// directory, the copy goes inside it.
+#[allow(clippy::too_many_arguments)]
+pub fn copy_from_pod(
+    kluster: &Kluster,
+    namespace: &str,
+    pod: &str,
+    container: Option<&str>,
+    remote: &str,
+    local: &Path,
+    ctrlcbool: &AtomicBool,
+    writer: &mut ClickWriter,
+) -> Result<(), KubeError> {
+    let (parent, name) = split_remote(remote)?;
+    let dest = if local.is_dir() {
+        local.join(name)
+    } else {
+        local.to_path_buf()
+    };
+    let cmd = ["tar", "cf", "-", "-C", parent, name];
+    let mut ws = exec::connect(
+        kluster,
+        namespace,
+        pod,
+        container,
+        &cmd,
+        false,
+        false,
+        Duration::from_millis(100),
+    )?;
+    ctrlcbool.store(false, Ordering::SeqCst);
+    let mut output = ExecOutput::new(&mut ws, ctrlcbool);
+
+    let (mut files, mut bytes) = (0, 0);
+    {
+        let mut archive = TarReader::new(&mut output);
+        while let Some(entry) = archive.next_entry()? {
+            // everything in the archive should be under name
+            let target = match local_target(&entry.path, name, &dest) {
+                Some(target) => target,
+                None => {
+                    clickwriteln!(
+                        writer,
+                        "Refusing to copy {}, it's outside {}",
+                        entry.path,
+                        name
+                    );
+                    continue;
+                }
+            };
+            match entry.kind {
+                EntryKind::Dir => fs::create_dir_all(&target)?,
+                EntryKind::File => {
+                    if let Some(parent) = target.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    let mut file = fs::File::create(&target)?;
+                    let mut progress = Progress::new(&entry.path, entry.size);
+                    archive.copy_data(&entry, &mut file, &mut |amt| progress.update(amt))?;
+                    progress.finish();
+                    file.set_permissions(fs::Permissions::from_mode(entry.mode & 0o777))?;
+                    files += 1;
+                    bytes += entry.size;
+                }
+                EntryKind::Other(_) => {
+                    clickwriteln!(
+                        writer,
+                        "Skipping {}, it's not a file or directory",
+                        entry.path
+                    );
+                }
+            }
+        }
+    }
+    output.finish()?;
+    ws.close();
+    clickwriteln!(
+        writer,
+        "Copied {} file(s), {}, to {}",
+        files,
+        human_bytes(bytes),
+        dest.display()
+    );
+    Ok(())
+}
+
+/// Add local to archive as path, recursing into directories. Returns the number of files and
+/// bytes added
+fn archive_local<W: Write>(
+    archive: &mut TarWriter<W>,
+    local: &Path,
+    path: &str,
+    writer: &mut ClickWriter,
+) -> io::Result<(u64, u64)> {
+    let meta = fs::symlink_metadata(local)?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mode = meta.permissions().mode() & 0o7777;
+    if meta.is_dir() {
+        archive.append_dir(path, mode, mtime)?;
+        let mut children: Vec<_> = fs::read_dir(local)?
+            .map(|entry| entry.map(|e| e.file_name()))
+            .collect::<io::Result<_>>()?;
+        children.sort();
+        let (mut files, mut bytes) = (0, 0);
+        for child in children.iter() {
+            let child_path = format!("{}/{}", path, child.to_string_lossy());
+            let (f, b) = archive_local(archive, &local.join(child), &child_path, writer)?;
+            files += f;
+            bytes += b;
+        }
+        Ok((files, bytes))
+    } else if meta.is_file() {
+        let file = fs::File::open(local)?;
+        let name = local.display().to_string();
+        let mut progress = Progress::new(&name, meta.len());
+        archive.append_file(path, mode, mtime, meta.len(), file, &mut |amt| {
+            progress.update(amt)
+        })?;
+        progress.finish();
+        Ok((1, meta.len()))
+    } else {
+        clickwriteln!(
+            writer,
+            "Skipping {}, it's not a file or directory",
+            local.display()
+        );
+        Ok((0, 0))
+    }
+}
+
+/// Copy local, a file or directory, to remote in the container. If remote ends with a /, the copy
+/// goes inside that directory. Missing parent directories are created.
+#[allow(clippy::too_many_arguments)]
+pub fn copy_to_pod(
+    kluster: &Kluster,
+    namespace: &str,
+    pod: &str,
+    container: Option<&str>,
+    local: &Path,
+    remote: &str,
+    ctrlcbool: &AtomicBool,
+    writer: &mut ClickWriter,
+) -> Result<(), KubeError> {
+    if !local.exists() {
+        return Err(KubeError::ParseErr(format!(
+            "{} doesn't exist",
+            local.display()
+        )));
+    }
+    let remote = if remote.ends_with('/') {
+        let local_name = local
+            .file_name()
+            .ok_or_else(|| KubeError::ParseErr(format!("Can't copy {}", local.display())))?;
+        format!("{}{}", remote, local_name.to_string_lossy())
+    } else {
+        remote.to_owned()
+    };
+    // make sure it's something we can write to. tar creates any missing parent directories of what
+    // it extracts, so the archive holds the full path, relative to / for absolute paths, or the
+    // working directory otherwise
+    split_remote(&remote)?;
+    let (cmd, path): (Vec<&str>, &str) = match remote.strip_prefix('/') {
+        Some(path) => (vec!["tar", "xmf", "-", "-C", "/"], path),
+        None => (vec!["tar", "xmf", "-"], remote.as_str()),
+    };
+    let path = path.trim_end_matches('/');
+
+    let mut ws = exec::connect(
+        kluster,
+        namespace,
+        pod,
+        container,
+        &cmd,
+        false,
+        true,
+        Duration::from_millis(100),
+    )?;
+    ctrlcbool.store(false, Ordering::SeqCst);
+    let (files, bytes) = {
+        let input = ExecInput {
+            ws: &mut ws,
+            ctrlcbool,
+            buf: vec![STDIN_CHANNEL],
+        };
+        let mut archive = TarWriter::new(input);
+        let counts = archive_local(&mut archive, local, path, writer)?;
+        archive.finish()?;
+        counts
+    };
+    exec::close_stdin(&mut ws)?;
+    ExecOutput::new(&mut ws, ctrlcbool).finish()?;
+    ws.close();
+    clickwriteln!(
+        writer,
+        "Copied {} file(s), {}, to {}:{}",
+        files,
+        human_bytes(bytes),
+        pod,
+        remote
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_path() {
+        assert_eq!(
+            safe_path("dump/heap.hprof"),
+            Some(PathBuf::from("dump/heap.hprof"))
+        );
+        assert_eq!(safe_path("./dump/"), Some(PathBuf::from("dump")));
+        assert_eq!(safe_path("/etc/passwd"), None);
+        assert_eq!(safe_path("dump/../../.bashrc"), None);
+        assert_eq!(safe_path("."), None);
+    }
+
+    #[test]
+    fn test_split_remote() {
+        assert_eq!(
+            split_remote("/tmp/heap.hprof").unwrap(),
+            ("/tmp", "heap.hprof")
+        );
+        assert_eq!(split_remote("/data/").unwrap(), ("/", "data"));
+        assert_eq!(split_remote("logs").unwrap(), (".", "logs"));
+        assert!(split_remote("/").is_err());
+        assert!(split_remote("/tmp/..").is_err());
+    }
+
+    #[test]
+    fn test_local_target() {
+        // a single file is the only entry in its archive
+        assert_eq!(
+            local_target("heap.hprof", "heap.hprof", Path::new("./heap.hprof")),
+            Some(PathBuf::from("./heap.hprof"))
+        );
+        assert_eq!(
+            local_target("logs/", "logs", Path::new("/tmp/out")),
+            Some(PathBuf::from("/tmp/out"))
+        );
+        assert_eq!(
+            local_target("logs/app/today.log", "logs", Path::new("/tmp/out")),
+            Some(PathBuf::from("/tmp/out/app/today.log"))
+        );
+        assert_eq!(local_target("other/x", "logs", Path::new("out")), None);
+        assert_eq!(local_target("logs/../../x", "logs", Path::new("out")), None);
+    }
+
+    #[test]
+    fn test_human_bytes() {
+        assert_eq!(human_bytes(512), "512 B");
+        assert_eq!(human_bytes(3 * 1024 * 1024 / 2), "1.5 MiB");
+    }
+}
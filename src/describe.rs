@@ -15,7 +15,7 @@
 //!  Utility functions for the Describe command, used to output
 //!  information for supported kubernetes object types
 
-use crate::values::{val_str, val_str_opt, val_u64};
+use crate::values::{val_str, val_str_opt, val_u64, Quantity};
 
 use ansi_term::Colour;
 use chrono::offset::Local;
@@ -24,6 +24,7 @@ use chrono::DateTime;
 use serde_json::Value;
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt::Write;
 use std::str::{self, FromStr};
 
@@ -207,6 +208,14 @@ pub fn describe_format_pod(v: Value) -> String {
                 secret_vals: false,
             },
         ),
+        (
+            "Resources:\n",
+            DescItem::CustomFunc {
+                path: Some("/spec/containers"),
+                func: &get_resources_str,
+                default: "<No Containers>",
+            },
+        ),
         (
             "Volumes:\n",
             DescItem::CustomFunc {
@@ -282,6 +291,70 @@ fn get_volume_str(v: &Value) -> Cow<str> {
     buf.into()
 }
 
+/// A resource list, like the capacity of a node, as resource=quantity, one per line
+fn resource_list_str(v: &Value) -> Cow<'_, str> {
+    let mut buf = String::new();
+    if let Some(resources) = v.as_object() {
+        for (idx, (resource, quantity)) in resources.iter().enumerate() {
+            if idx > 0 {
+                buf.push_str("\n\t\t");
+            }
+            let quantity = quantity.as_str().unwrap_or("");
+            match quantity.parse::<Quantity>() {
+                Ok(q) => write!(buf, "{}={}", resource, q.fmt_resource(resource)).unwrap(),
+                Err(_) => write!(buf, "{}={}", resource, quantity).unwrap(),
+            }
+        }
+    }
+    buf.into()
+}
+
+/// The requests and limits of each container, and of the whole pod if it has more than one
+fn get_resources_str(v: &Value) -> Cow<'_, str> {
+    let mut buf = String::new();
+    let mut totals: Vec<(&str, BTreeMap<&str, Quantity>)> =
+        vec![("Requests", BTreeMap::new()), ("Limits", BTreeMap::new())];
+    let containers = v.as_array().map(|c| c.as_slice()).unwrap_or(&[]);
+    for container in containers.iter() {
+        writeln!(buf, "  {}:", val_str("/name", container, "<No Name>")).unwrap();
+        for (title, total) in totals.iter_mut() {
+            let pointer = format!("/resources/{}", title.to_lowercase());
+            let resources = container.pointer(&pointer).and_then(Value::as_object);
+            let mut amounts = vec![];
+            for (resource, quantity) in resources.into_iter().flatten() {
+                let quantity = quantity.as_str().unwrap_or("");
+                match quantity.parse::<Quantity>() {
+                    Ok(q) => {
+                        *total.entry(resource).or_default() += q;
+                        amounts.push(format!("{}={}", resource, q.fmt_resource(resource)));
+                    }
+                    Err(_) => amounts.push(format!("{}={}", resource, quantity)),
+                }
+            }
+            if amounts.is_empty() {
+                writeln!(buf, "    {}:\t<none>", title).unwrap();
+            } else {
+                writeln!(buf, "    {}:\t{}", title, amounts.join(", ")).unwrap();
+            }
+        }
+    }
+    if containers.len() > 1 {
+        buf.push_str("  Total:\n");
+        for (title, total) in totals.iter() {
+            let amounts: Vec<String> = total
+                .iter()
+                .map(|(resource, q)| format!("{}={}", resource, q.fmt_resource(resource)))
+                .collect();
+            if amounts.is_empty() {
+                writeln!(buf, "    {}:\t<none>", title).unwrap();
+            } else {
+                writeln!(buf, "    {}:\t{}", title, amounts.join(", ")).unwrap();
+            }
+        }
+    }
+    buf.into()
+}
+
 fn pod_phase(v: &Value) -> Cow<str> {
     let phase_str = val_str("/status/phase", v, "<No Phase>");
     let colour = match &*phase_str {
@@ -318,6 +391,22 @@ pub fn describe_format_node(v: Value) -> String {
             },
         ),
         ("Created at:\t", DescItem::ObjectCreated),
+        (
+            "Capacity:\t",
+            DescItem::CustomFunc {
+                path: Some("/status/capacity"),
+                func: &resource_list_str,
+                default: "<none>",
+            },
+        ),
+        (
+            "Allocatable:\t",
+            DescItem::CustomFunc {
+                path: Some("/status/allocatable"),
+                func: &resource_list_str,
+                default: "<none>",
+            },
+        ),
         (
             "Provider Id:\t",
             DescItem::ValStr {
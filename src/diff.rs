@@ -0,0 +1,294 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Line based diffs, printed in unified diff format. The diff is found with the classic longest
+//! common subsequence table. That's quadratic, so it's only built for the lines between the common
+//! prefix and suffix, and very large changes fall back to replacing everything that changed.
+
+use crate::output::ClickWriter;
+
+use ansi_term::Colour::{Cyan, Green, Red};
+
+use std::cmp;
+use std::io::Write;
+
+#[derive(Debug, PartialEq)]
+pub enum Line<'a> {
+    Context(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// A group of changes, with the context around them
+#[derive(Debug, PartialEq)]
+pub struct Hunk<'a> {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<Line<'a>>,
+}
+
+/// The most entries we'll put in the lcs table, which is about 80MB. Past this, the changed part
+/// of the diff is shown as everything removed and then everything added.
+const MAX_LCS_SIZE: usize = 10_000_000;
+
+/// The full list of lines to get from old to new
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // most edits touch a few lines in the middle, so only the part between the common prefix and
+    // suffix needs the lcs table
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let mut lines: Vec<Line> = old[..prefix].iter().map(|l| Line::Context(l)).collect();
+    let old_mid = &old[prefix..(old.len() - suffix)];
+    let new_mid = &new[prefix..(new.len() - suffix)];
+    if (old_mid.len() + 1).saturating_mul(new_mid.len() + 1) > MAX_LCS_SIZE {
+        lines.extend(old_mid.iter().map(|l| Line::Removed(l)));
+        lines.extend(new_mid.iter().map(|l| Line::Added(l)));
+    } else {
+        lcs_lines(old_mid, new_mid, &mut lines);
+    }
+    lines.extend(old[(old.len() - suffix)..].iter().map(|l| Line::Context(l)));
+    lines
+}
+
+/// Add the lines to get from old to new to lines, using a longest common subsequence table
+fn lcs_lines<'a>(old: &[&'a str], new: &[&'a str], lines: &mut Vec<Line<'a>>) {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let width = new.len() + 1;
+    let mut lcs = vec![0usize; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i * width + j] = if old[i] == new[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                cmp::max(lcs[(i + 1) * width + j], lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(Line::Context(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|l| Line::Removed(l)));
+    lines.extend(new[j..].iter().map(|l| Line::Added(l)));
+}
+
+/// The hunks to get from old to new, with context lines of context around each change. Returns
+/// an empty list if they're the same.
+pub fn hunks<'a>(old: &'a str, new: &'a str, context: usize) -> Vec<Hunk<'a>> {
+    let lines = diff_lines(old, new);
+
+    // the ranges of lines that go in each hunk
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for (idx, line) in lines.iter().enumerate() {
+        if let Line::Context(_) = line {
+            continue;
+        }
+        let start = idx.saturating_sub(context);
+        let end = cmp::min(lines.len(), idx + context + 1);
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    // line numbers in old and new before each line
+    let mut positions = Vec::with_capacity(lines.len());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for line in lines.iter() {
+        positions.push((old_pos, new_pos));
+        match line {
+            Line::Context(_) => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Line::Removed(_) => old_pos += 1,
+            Line::Added(_) => new_pos += 1,
+        }
+    }
+
+    let mut lines: Vec<Option<Line>> = lines.into_iter().map(Some).collect();
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let hunk_lines: Vec<Line> = lines[start..end]
+                .iter_mut()
+                .filter_map(|l| l.take())
+                .collect();
+            let old_len = hunk_lines
+                .iter()
+                .filter(|l| !matches!(l, Line::Added(_)))
+                .count();
+            let new_len = hunk_lines
+                .iter()
+                .filter(|l| !matches!(l, Line::Removed(_)))
+                .count();
+            let (old_pos, new_pos) = positions[start];
+            // an empty range is numbered by the line before it
+            Hunk {
+                old_start: if old_len == 0 { old_pos } else { old_pos + 1 },
+                old_len,
+                new_start: if new_len == 0 { new_pos } else { new_pos + 1 },
+                new_len,
+                lines: hunk_lines,
+            }
+        })
+        .collect()
+}
+
+/// Print a unified diff from old to new, with old_name and new_name in the header. Colors are
+/// only used when writing to the terminal. Prints nothing and returns false if there are no
+/// differences.
+pub fn print_diff(
+    old: &str,
+    new: &str,
+    old_name: &str,
+    new_name: &str,
+    writer: &mut ClickWriter,
+) -> bool {
+    let hunks = hunks(old, new, 3);
+    if hunks.is_empty() {
+        return false;
+    }
+    let color = writer.is_stdout();
+    let paint = |colour: ansi_term::Colour, s: String| {
+        if color {
+            colour.paint(s).to_string()
+        } else {
+            s
+        }
+    };
+    clickwriteln!(writer, "{}", paint(Red, format!("--- {}", old_name)));
+    clickwriteln!(writer, "{}", paint(Green, format!("+++ {}", new_name)));
+    for hunk in hunks.iter() {
+        let header = format!(
+            "@@ -{},{} +{},{} @@",
+            hunk.old_start, hunk.old_len, hunk.new_start, hunk.new_len
+        );
+        clickwriteln!(writer, "{}", paint(Cyan, header));
+        for line in hunk.lines.iter() {
+            match line {
+                Line::Context(l) => clickwriteln!(writer, " {}", l),
+                Line::Removed(l) => clickwriteln!(writer, "{}", paint(Red, format!("-{}", l))),
+                Line::Added(l) => clickwriteln!(writer, "{}", paint(Green, format!("+{}", l))),
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(
+            diff_lines("a\nb\nc\n", "a\nx\nc\nd\n"),
+            vec![
+                Line::Context("a"),
+                Line::Removed("b"),
+                Line::Added("x"),
+                Line::Context("c"),
+                Line::Added("d"),
+            ]
+        );
+        assert!(hunks("same\n", "same\n", 3).is_empty());
+    }
+
+    #[test]
+    fn test_diff_large() {
+        // a small change in something big only diffs the part that changed
+        let old: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+        let new = old.replace("line 12345\n", "changed\n");
+        let lines = diff_lines(&old, &new);
+        assert_eq!(lines.len(), 20_001);
+        assert_eq!(lines[12345], Line::Removed("line 12345"));
+        assert_eq!(lines[12346], Line::Added("changed"));
+
+        // too big to compare line by line, so everything gets replaced
+        let new: String = (0..5_000).map(|i| format!("new {}\n", i)).collect();
+        let old = &old[..old.find("line 5000\n").unwrap()];
+        let lines = diff_lines(old, &new);
+        assert_eq!(lines.len(), 10_000);
+        assert_eq!(lines[4999], Line::Removed("line 4999"));
+        assert_eq!(lines[5000], Line::Added("new 0"));
+    }
+
+    #[test]
+    fn test_hunks() {
+        let old: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let new: String = (1..=20)
+            .filter(|i| *i != 18)
+            .map(|i| match i {
+                2 => "two\n".to_owned(),
+                _ => format!("{}\n", i),
+            })
+            .collect();
+        let hunks = hunks(&old, &new, 1);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(
+            hunks[0],
+            Hunk {
+                old_start: 1,
+                old_len: 3,
+                new_start: 1,
+                new_len: 3,
+                lines: vec![
+                    Line::Context("1"),
+                    Line::Removed("2"),
+                    Line::Added("two"),
+                    Line::Context("3"),
+                ],
+            }
+        );
+        assert_eq!(
+            (
+                hunks[1].old_start,
+                hunks[1].old_len,
+                hunks[1].new_start,
+                hunks[1].new_len
+            ),
+            (17, 3, 17, 2)
+        );
+
+        // everything added to nothing
+        let added = super::hunks("", "a\nb\n", 3);
+        assert_eq!((added[0].old_start, added[0].old_len), (0, 0));
+        assert_eq!((added[0].new_start, added[0].new_len), (1, 2));
+    }
+}
@@ -0,0 +1,328 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! API discovery: finding out which groups, versions and kinds of resources an api server serves.
+//!
+//! The core group lives under /api, and all the others under /apis. Each group version then has a
+//! list of the resources it serves at /api/v1 or /apis/<group>/<version>.
+
+use crate::error::KubeError;
+use crate::kube::KlusterConnector;
+
+use std::collections::VecDeque;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// How many group versions to fetch at once
+const FETCH_THREADS: usize = 8;
+
+/// A kind of resource served by the api server
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ApiResource {
+    /// the plural name, which is what's used in urls
+    pub name: String,
+    #[serde(rename = "singularName", default)]
+    pub singular_name: String,
+    pub namespaced: bool,
+    pub kind: String,
+    #[serde(default)]
+    pub verbs: Vec<String>,
+    #[serde(rename = "shortNames", default)]
+    pub short_names: Vec<String>,
+    /// the group version this is served from, like v1 or apps/v1. Filled in from the list this
+    /// came from
+    #[serde(skip)]
+    pub group_version: String,
+}
+
+impl ApiResource {
+    /// The group this is in, which is empty for the core group
+    pub fn group(&self) -> &str {
+        match self.group_version.find('/') {
+            Some(pos) => &self.group_version[..pos],
+            None => "",
+        }
+    }
+
+    /// The url for a list of these, in namespace if specified and this is namespaced
+    pub fn list_url(&self, namespace: Option<&str>) -> String {
+        let prefix = if self.group().is_empty() {
+            format!("/api/{}", self.group_version)
+        } else {
+            format!("/apis/{}", self.group_version)
+        };
+        match namespace {
+            Some(ns) if self.namespaced => {
+                format!("{}/namespaces/{}/{}", prefix, ns, self.name)
+            }
+            _ => format!("{}/{}", prefix, self.name),
+        }
+    }
+
+    /// The url for the object called name, in namespace if this is namespaced
+    pub fn url(&self, namespace: &str, name: &str) -> String {
+        format!("{}/{}", self.list_url(Some(namespace)), name)
+    }
+
+    /// Does this match name, which can be a kind, a plural, a singular or a short name, optionally
+    /// followed by .group. Matching is case insensitive.
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        let (name, group) = match name.find('.') {
+            Some(pos) => (&name[..pos], Some(&name[(pos + 1)..])),
+            None => (name.as_str(), None),
+        };
+        if let Some(group) = group {
+            if group != self.group() {
+                return false;
+            }
+        }
+        self.name == name
+            || self.singular_name == name
+            || self.kind.to_lowercase() == name
+            || self.short_names.iter().any(|sn| sn == name)
+    }
+
+    pub fn has_verb(&self, verb: &str) -> bool {
+        self.verbs.iter().any(|v| v == verb)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResourceList {
+    #[serde(rename = "groupVersion")]
+    group_version: String,
+    resources: Vec<ApiResource>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GroupVersion {
+    #[serde(rename = "groupVersion")]
+    pub group_version: String,
+    pub version: String,
+}
+
+/// A group, and the versions of it that are served
+#[derive(Clone, Debug, Deserialize)]
+pub struct ApiGroup {
+    pub name: String,
+    pub versions: Vec<GroupVersion>,
+    #[serde(rename = "preferredVersion")]
+    pub preferred_version: Option<GroupVersion>,
+}
+
+impl ApiGroup {
+    /// The group version to use for this group, the preferred one if there is one
+    pub fn preferred(&self) -> Option<&GroupVersion> {
+        self.preferred_version
+            .as_ref()
+            .or_else(|| self.versions.first())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiGroupList {
+    groups: Vec<ApiGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiVersions {
+    versions: Vec<String>,
+}
+
+/// Everything the api server told us it serves
+#[derive(Debug)]
+pub struct Discovery {
+    /// all the groups, with the core group (which has an empty name) first
+    pub groups: Vec<ApiGroup>,
+    /// the resources in the preferred version of each group, in the same order as groups
+    pub resources: Vec<ApiResource>,
+}
+
+impl Discovery {
+    /// Ask the api server what it serves. The resource lists for each group are fetched in
+    /// parallel by a few worker threads, as there can be a lot of them. Group versions that fail
+    /// (which happens if an aggregated api server is down) are skipped.
+    pub fn fetch(connector: &KlusterConnector) -> Result<Discovery, KubeError> {
+        let core: ApiVersions = connector.get("/api")?;
+        let mut groups = vec![ApiGroup {
+            name: "".to_owned(),
+            versions: core
+                .versions
+                .iter()
+                .map(|v| GroupVersion {
+                    group_version: v.clone(),
+                    version: v.clone(),
+                })
+                .collect(),
+            preferred_version: None,
+        }];
+        let group_list: ApiGroupList = connector.get("/apis")?;
+        groups.extend(group_list.groups);
+
+        // a queue of (index, group version) for the workers to take from. The index is so the
+        // results can be put back in the same order as groups
+        let queue: VecDeque<(usize, String)> = groups
+            .iter()
+            .filter_map(|group| group.preferred())
+            .map(|gv| gv.group_version.clone())
+            .enumerate()
+            .collect();
+        let workers = queue.len().min(FETCH_THREADS);
+        let queue = Arc::new(Mutex::new(queue));
+        let (tx, rx) = channel();
+        for _ in 0..workers {
+            let connector = connector.clone();
+            let queue = queue.clone();
+            let tx = tx.clone();
+            thread::spawn(move || loop {
+                let next = queue.lock().unwrap().pop_front();
+                let (index, group_version) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let list = Discovery::fetch_group_version(&connector, &group_version);
+                if tx.send((index, list)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        // the channel closes once all the workers have finished
+        let mut lists: Vec<(usize, Vec<ApiResource>)> = rx
+            .into_iter()
+            .filter_map(|(index, list)| list.ok().map(|list| (index, list)))
+            .collect();
+        lists.sort_by_key(|(index, _)| *index);
+        let resources = lists.into_iter().flat_map(|(_, list)| list).collect();
+        Ok(Discovery { groups, resources })
+    }
+
+    /// Ask the api server for the resources served in group_version (like v1 or apps/v1). Only
+    /// the preferred version of each group is fetched by fetch, so this is how to find resources
+    /// in the other versions.
+    pub fn fetch_group_version(
+        connector: &KlusterConnector,
+        group_version: &str,
+    ) -> Result<Vec<ApiResource>, KubeError> {
+        let url = if group_version.contains('/') {
+            format!("/apis/{}", group_version)
+        } else {
+            format!("/api/{}", group_version)
+        };
+        let list: ApiResourceList = connector.get(&url)?;
+        let group_version = list.group_version;
+        Ok(list
+            .resources
+            .into_iter()
+            .filter_map(|mut res| {
+                // things like pods/log are subresources, not something we can list
+                if res.name.contains('/') {
+                    None
+                } else {
+                    res.group_version = group_version.clone();
+                    Some(res)
+                }
+            })
+            .collect())
+    }
+
+    /// Find the resource called name (see ApiResource::matches). If more than one matches, the
+    /// first found is used, which will be the one from the core group if there is one.
+    pub fn find(&self, name: &str) -> Option<&ApiResource> {
+        self.resources.iter().find(|res| res.matches(name))
+    }
+
+    /// Find the resource for kind in group, which will be in the group's preferred version
+    pub fn find_kind(&self, group: &str, kind: &str) -> Option<&ApiResource> {
+        self.resources
+            .iter()
+            .find(|res| res.group() == group && res.kind == kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(group_version: &str, name: &str, kind: &str, namespaced: bool) -> ApiResource {
+        ApiResource {
+            name: name.to_owned(),
+            singular_name: kind.to_lowercase(),
+            namespaced,
+            kind: kind.to_owned(),
+            verbs: vec!["get".to_owned(), "list".to_owned()],
+            short_names: vec![],
+            group_version: group_version.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_resource_urls() {
+        let pods = resource("v1", "pods", "Pod", true);
+        assert_eq!(pods.group(), "");
+        assert_eq!(pods.list_url(None), "/api/v1/pods");
+        assert_eq!(pods.list_url(Some("ns")), "/api/v1/namespaces/ns/pods");
+        assert_eq!(pods.url("ns", "p1"), "/api/v1/namespaces/ns/pods/p1");
+
+        let issuers = resource(
+            "cert-manager.io/v1",
+            "clusterissuers",
+            "ClusterIssuer",
+            false,
+        );
+        assert_eq!(issuers.group(), "cert-manager.io");
+        assert_eq!(
+            issuers.list_url(Some("ns")),
+            "/apis/cert-manager.io/v1/clusterissuers"
+        );
+        assert_eq!(
+            issuers.url("ns", "le"),
+            "/apis/cert-manager.io/v1/clusterissuers/le"
+        );
+    }
+
+    #[test]
+    fn test_find() {
+        let mut certs = resource("cert-manager.io/v1", "certificates", "Certificate", true);
+        certs.short_names = vec!["cert".to_owned(), "certs".to_owned()];
+        let disc = Discovery {
+            groups: vec![],
+            resources: vec![
+                resource("v1", "events", "Event", true),
+                certs,
+                resource("events.k8s.io/v1", "events", "Event", true),
+            ],
+        };
+        let find_gv = |name| disc.find(name).map(|r| r.group_version.as_str());
+        assert_eq!(find_gv("Certificate"), Some("cert-manager.io/v1"));
+        assert_eq!(find_gv("certs"), Some("cert-manager.io/v1"));
+        assert_eq!(
+            find_gv("certificates.cert-manager.io"),
+            Some("cert-manager.io/v1")
+        );
+        assert_eq!(find_gv("events"), Some("v1"));
+        assert_eq!(find_gv("events.events.k8s.io"), Some("events.k8s.io/v1"));
+        assert_eq!(find_gv("certificates.example.com"), None);
+        assert_eq!(find_gv("widgets"), None);
+        assert_eq!(
+            disc.find_kind("events.k8s.io", "Event")
+                .map(|r| r.group_version.as_str()),
+            Some("events.k8s.io/v1")
+        );
+        assert!(disc.find_kind("apps", "Event").is_none());
+    }
+}
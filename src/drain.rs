@@ -0,0 +1,405 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Node maintenance: cordoning nodes so nothing new gets scheduled on them, and draining them by
+//! evicting all their pods. Evictions go through the Eviction API, so the api server refuses any
+//! that would violate a PodDisruptionBudget. Those are retried until the budget allows them.
+
+use crate::error::KubeError;
+use crate::kube::{Kluster, PatchType};
+use crate::output::ClickWriter;
+use crate::values::val_str;
+
+use serde_json::Value;
+
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait before trying an eviction that was refused again
+const EVICTION_RETRY: Duration = Duration::from_secs(5);
+
+pub struct DrainOptions {
+    pub ignore_daemonsets: bool,
+    pub delete_emptydir_data: bool,
+    pub force: bool,
+    /// give up after this long, or never if None
+    pub timeout: Option<Duration>,
+    /// only say what would be done
+    pub dry_run: bool,
+}
+
+/// What to do with a pod on a node being drained
+#[derive(Debug, PartialEq)]
+enum PodAction {
+    Evict,
+    /// leave it where it is, for the specified reason
+    Skip(&'static str),
+    /// the node can't be drained because of this pod, for the specified reason
+    Refuse(&'static str),
+}
+
+fn pod_action(pod: &Value, opts: &DrainOptions) -> PodAction {
+    if pod
+        .pointer("/metadata/annotations/kubernetes.io~1config.mirror")
+        .is_some()
+    {
+        // the kubelet manages these, deleting them through the api does nothing
+        return PodAction::Skip("it's a mirror pod");
+    }
+    let phase = val_str("/status/phase", pod, "");
+    if phase == "Succeeded" || phase == "Failed" {
+        // finished, so there's nothing to lose
+        return PodAction::Evict;
+    }
+    let controller = pod
+        .pointer("/metadata/ownerReferences")
+        .and_then(Value::as_array)
+        .and_then(|refs| {
+            refs.iter()
+                .find(|r| r.get("controller").and_then(Value::as_bool) == Some(true))
+        });
+    match controller {
+        Some(owner) if val_str("/kind", owner, "") == "DaemonSet" => {
+            return if opts.ignore_daemonsets {
+                PodAction::Skip("it's managed by a DaemonSet")
+            } else {
+                PodAction::Refuse("it's managed by a DaemonSet (use --ignore-daemonsets)")
+            };
+        }
+        Some(_) => {}
+        None if opts.force => {}
+        None => {
+            return PodAction::Refuse("it isn't managed by a controller (use --force)");
+        }
+    }
+    let uses_emptydir = pod
+        .pointer("/spec/volumes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .any(|vol| vol.get("emptyDir").is_some());
+    if uses_emptydir && !opts.delete_emptydir_data {
+        return PodAction::Refuse("it uses emptyDir storage (use --delete-emptydir-data)");
+    }
+    PodAction::Evict
+}
+
+/// Mark node as (un)schedulable
+pub fn set_unschedulable(
+    kluster: &Kluster,
+    node: &str,
+    unschedulable: bool,
+) -> Result<Value, KubeError> {
+    let patch = json!({"spec": {"unschedulable": unschedulable}}).to_string();
+    kluster.patch(
+        format!("/api/v1/nodes/{}", node).as_str(),
+        &patch,
+        PatchType::Merge,
+    )
+}
+
+/// A pod we're evicting
+struct EvictPod {
+    namespace: String,
+    name: String,
+    uid: String,
+    /// have we already said the eviction was refused
+    warned: bool,
+}
+
+impl EvictPod {
+    fn url(&self) -> String {
+        format!("/api/v1/namespaces/{}/pods/{}", self.namespace, self.name)
+    }
+
+    /// Ask for this pod to be evicted. Returns false if the eviction was refused for now, which
+    /// happens if it would violate a disruption budget
+    fn evict(&self, kluster: &Kluster, api_version: &str) -> Result<bool, KubeError> {
+        let body = json!({
+            "apiVersion": api_version,
+            "kind": "Eviction",
+            "metadata": {"name": self.name, "namespace": self.namespace},
+        });
+        let url = format!("{}/eviction", self.url());
+        match kluster.post(url.as_str(), &body.to_string()) {
+            Ok(_) => Ok(true),
+            // already gone
+            Err(ref e) if e.status_code() == Some(404) => Ok(true),
+            Err(ref e) if e.status_code() == Some(429) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Has this pod been deleted? A pod with the same name but a different uid is a new one, so
+    /// this one is gone
+    fn is_gone(&self, kluster: &Kluster) -> Result<bool, KubeError> {
+        match kluster.get_value(self.url().as_str()) {
+            Ok(pod) => Ok(val_str("/metadata/uid", &pod, "") != self.uid),
+            Err(ref e) if e.status_code() == Some(404) => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The group version of Eviction the server accepts, from the pods/eviction subresource in the
+/// core v1 resource list. Falls back to policy/v1 if that doesn't say.
+fn eviction_version(resources: &Value) -> String {
+    resources
+        .get("resources")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .find(|res| val_str("/name", res, "") == "pods/eviction")
+        .and_then(|res| {
+            match (
+                res.get("group").and_then(Value::as_str),
+                res.get("version").and_then(Value::as_str),
+            ) {
+                (Some(group), Some(version)) if !group.is_empty() && !version.is_empty() => {
+                    Some(format!("{}/{}", group, version))
+                }
+                _ => None,
+            }
+        })
+        .unwrap_or_else(|| "policy/v1".to_owned())
+}
+
+/// The group version of the Eviction API the server serves
+fn eviction_api_version(kluster: &Kluster) -> String {
+    match kluster.get_value("/api/v1") {
+        Ok(resources) => eviction_version(&resources),
+        Err(_) => "policy/v1".to_owned(),
+    }
+}
+
+/// Cordon node, then evict all the pods on it and wait for them to go away. Stops early if
+/// ctrlcbool gets set.
+pub fn drain(
+    kluster: &Kluster,
+    node: &str,
+    opts: &DrainOptions,
+    ctrlcbool: &AtomicBool,
+    writer: &mut ClickWriter,
+) -> Result<(), KubeError> {
+    let dry_run = if opts.dry_run { " (dry run)" } else { "" };
+    let pods = kluster
+        .get_value(format!("/api/v1/pods?fieldSelector=spec.nodeName%3D{}", node).as_str())?;
+    let pods = pods
+        .get("items")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+
+    let mut to_evict = vec![];
+    let mut refused = vec![];
+    for pod in pods.iter() {
+        let namespace = val_str("/metadata/namespace", pod, "");
+        let name = val_str("/metadata/name", pod, "");
+        match pod_action(pod, opts) {
+            PodAction::Evict => to_evict.push(EvictPod {
+                namespace: namespace.into_owned(),
+                name: name.into_owned(),
+                uid: val_str("/metadata/uid", pod, "").into_owned(),
+                warned: false,
+            }),
+            PodAction::Skip(reason) => {
+                clickwriteln!(writer, "Skipping pod {}/{}: {}", namespace, name, reason);
+            }
+            PodAction::Refuse(reason) => {
+                refused.push(format!("{}/{}: {}", namespace, name, reason))
+            }
+        }
+    }
+    if !refused.is_empty() {
+        clickwriteln!(writer, "Can't drain node {}, because of these pods:", node);
+        for pod in refused.iter() {
+            clickwriteln!(writer, "  {}", pod);
+        }
+        return Ok(());
+    }
+
+    if !opts.dry_run {
+        set_unschedulable(kluster, node, true)?;
+    }
+    clickwriteln!(writer, "Node {} cordoned{}", node, dry_run);
+    if opts.dry_run {
+        for pod in to_evict.iter() {
+            clickwriteln!(
+                writer,
+                "Evicting pod {}/{}{}",
+                pod.namespace,
+                pod.name,
+                dry_run
+            );
+        }
+        clickwriteln!(writer, "Node {} drained{}", node, dry_run);
+        return Ok(());
+    }
+
+    let api_version = eviction_api_version(kluster);
+    let deadline = opts.timeout.map(|t| Instant::now() + t);
+    let mut evicted: Vec<EvictPod> = vec![];
+    let mut last_attempt: Option<Instant> = None;
+    while !to_evict.is_empty() || !evicted.is_empty() {
+        if ctrlcbool.load(Ordering::SeqCst) {
+            clickwriteln!(writer, "Stopped draining node {}", node);
+            return Ok(());
+        }
+        if matches!(deadline, Some(d) if Instant::now() > d) {
+            clickwriteln!(
+                writer,
+                "Timed out draining node {}, {} pods are left",
+                node,
+                to_evict.len() + evicted.len()
+            );
+            return Ok(());
+        }
+
+        let retry = match last_attempt {
+            Some(at) => at.elapsed() >= EVICTION_RETRY,
+            None => true,
+        };
+        if retry {
+            last_attempt = Some(Instant::now());
+            let mut refused = vec![];
+            for mut pod in to_evict.drain(..) {
+                if pod.evict(kluster, &api_version)? {
+                    clickwriteln!(writer, "Evicting pod {}/{}", pod.namespace, pod.name);
+                    evicted.push(pod);
+                } else {
+                    if !pod.warned {
+                        clickwriteln!(
+                            writer,
+                            "Can't evict pod {}/{} yet, as it would violate its disruption \
+                             budget. Will retry.",
+                            pod.namespace,
+                            pod.name
+                        );
+                        pod.warned = true;
+                    }
+                    refused.push(pod);
+                }
+            }
+            to_evict = refused;
+        }
+
+        let mut remaining = vec![];
+        for pod in evicted.drain(..) {
+            if pod.is_gone(kluster)? {
+                clickwriteln!(writer, "Pod {}/{} evicted", pod.namespace, pod.name);
+            } else {
+                remaining.push(pod);
+            }
+        }
+        evicted = remaining;
+
+        if !to_evict.is_empty() || !evicted.is_empty() {
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+    clickwriteln!(writer, "Node {} drained", node);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts() -> DrainOptions {
+        DrainOptions {
+            ignore_daemonsets: false,
+            delete_emptydir_data: false,
+            force: false,
+            timeout: None,
+            dry_run: false,
+        }
+    }
+
+    fn pod(owner_kind: Option<&str>, volumes: Value) -> Value {
+        let owners = match owner_kind {
+            Some(kind) => json!([{"kind": kind, "name": "owner", "controller": true}]),
+            None => json!([]),
+        };
+        json!({
+            "metadata": {"name": "p", "namespace": "ns", "ownerReferences": owners},
+            "spec": {"volumes": volumes},
+            "status": {"phase": "Running"},
+        })
+    }
+
+    #[test]
+    fn test_pod_action() {
+        let rs_pod = pod(Some("ReplicaSet"), json!([]));
+        assert_eq!(pod_action(&rs_pod, &opts()), PodAction::Evict);
+
+        let ds_pod = pod(Some("DaemonSet"), json!([]));
+        assert!(matches!(pod_action(&ds_pod, &opts()), PodAction::Refuse(_)));
+        let ignore_ds = DrainOptions {
+            ignore_daemonsets: true,
+            ..opts()
+        };
+        assert!(matches!(
+            pod_action(&ds_pod, &ignore_ds),
+            PodAction::Skip(_)
+        ));
+
+        let bare_pod = pod(None, json!([]));
+        assert!(matches!(
+            pod_action(&bare_pod, &opts()),
+            PodAction::Refuse(_)
+        ));
+        let force = DrainOptions {
+            force: true,
+            ..opts()
+        };
+        assert_eq!(pod_action(&bare_pod, &force), PodAction::Evict);
+
+        let emptydir_pod = pod(Some("ReplicaSet"), json!([{"name": "tmp", "emptyDir": {}}]));
+        assert!(matches!(
+            pod_action(&emptydir_pod, &opts()),
+            PodAction::Refuse(_)
+        ));
+        let delete_data = DrainOptions {
+            delete_emptydir_data: true,
+            ..opts()
+        };
+        assert_eq!(pod_action(&emptydir_pod, &delete_data), PodAction::Evict);
+
+        let mut mirror = pod(None, json!([]));
+        mirror["metadata"]["annotations"] = json!({"kubernetes.io/config.mirror": "abc"});
+        assert!(matches!(pod_action(&mirror, &opts()), PodAction::Skip(_)));
+    }
+
+    #[test]
+    fn test_eviction_version() {
+        let list = |eviction: Value| {
+            json!({
+                "groupVersion": "v1",
+                "resources": [{"name": "pods", "kind": "Pod"}, eviction],
+            })
+        };
+        let beta = list(json!({
+            "name": "pods/eviction",
+            "group": "policy",
+            "version": "v1beta1",
+            "kind": "Eviction",
+        }));
+        assert_eq!(eviction_version(&beta), "policy/v1beta1");
+        let no_version = list(json!({"name": "pods/eviction", "kind": "Eviction"}));
+        assert_eq!(eviction_version(&no_version), "policy/v1");
+        assert_eq!(eviction_version(&json!({})), "policy/v1");
+    }
+}
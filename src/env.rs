@@ -3,6 +3,7 @@ use crate::error::KubeError;
 use crate::kobj::{KObj, ObjType};
 use crate::kube::Kluster;
 use crate::output::ClickWriter;
+use crate::portforward::PortForwardStats;
 
 use ansi_term::Colour::{Blue, Green, Red, Yellow};
 use rustyline::config as rustyconfig;
@@ -12,8 +13,7 @@ use tempdir::TempDir;
 use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::io::Write;
-use std::path::PathBuf;
-use std::process::Child;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
@@ -21,10 +21,11 @@ use std::sync::{Arc, Mutex};
 
 /// An ongoing port forward
 pub struct PortForward {
-    pub child: Child,
-    pub pod: String,
+    pub target: String,
     pub ports: Vec<String>,
     pub output: Arc<Mutex<String>>,
+    pub stats: Arc<PortForwardStats>,
+    pub stop: Arc<AtomicBool>,
 }
 
 #[derive(Debug)]
@@ -99,6 +100,13 @@ impl Env {
         env
     }
 
+    /// The directory click keeps its config (and caches) in
+    pub fn config_dir(&self) -> &Path {
+        self.click_config_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+    }
+
     pub fn current_selection(&self) -> &ObjectSelection {
         &self.current_selection
     }
@@ -350,18 +358,16 @@ impl Env {
         self.port_forwards.get_mut(i)
     }
 
-    pub fn stop_port_forward(&mut self, i: usize) -> Result<(), std::io::Error> {
+    pub fn stop_port_forward(&mut self, i: usize) {
         if i < self.port_forwards.len() {
-            let mut pf = self.port_forwards.remove(i);
-            pf.child.kill()
-        } else {
-            Ok(())
+            let pf = self.port_forwards.remove(i);
+            pf.stop.store(true, Ordering::SeqCst);
         }
     }
 
     pub fn stop_all_forwards(&mut self) {
-        for pf in self.port_forwards.iter_mut() {
-            pf.child.kill().unwrap();
+        for pf in self.port_forwards.iter() {
+            pf.stop.store(true, Ordering::SeqCst);
         }
         self.port_forwards = Vec::new();
     }
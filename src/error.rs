@@ -63,6 +63,8 @@ pub enum KubeError {
     ParseErr(String),
     Kube(KubeErrNo),
     KubeServerError(String),
+    /// An error response from the api server, with its status code and message
+    Status(u16, String),
     ConfigFileError(String),
     DecodeError(base64::DecodeError),
     Io(io::Error),
@@ -71,6 +73,7 @@ pub enum KubeError {
     SerdeJson(serde_json::Error),
     SerdeYaml(serde_yaml::Error),
     JoinPathsError(env::JoinPathsError),
+    NotServed(String),
 }
 
 impl fmt::Display for KubeError {
@@ -79,6 +82,7 @@ impl fmt::Display for KubeError {
             KubeError::ParseErr(ref s) => write!(f, "Parse Error: {}", s),
             KubeError::Kube(ref err) => write!(f, "Kube Error: {}", err),
             KubeError::KubeServerError(ref s) => write!(f, "Server Error: {}", s),
+            KubeError::Status(_, ref s) => write!(f, "Server Error: {}", s),
             KubeError::ConfigFileError(ref s) => write!(f, "Failed to get config: {}", s),
             KubeError::DecodeError(ref err) => write!(f, "Base64 decode error: {}", err),
             KubeError::Io(ref err) => write!(f, "IO error: {}", err),
@@ -87,6 +91,9 @@ impl fmt::Display for KubeError {
             KubeError::SerdeJson(ref err) => write!(f, "Serde json error: {}", err),
             KubeError::SerdeYaml(ref err) => write!(f, "Serde yaml error: {}", err),
             KubeError::JoinPathsError(ref err) => write!(f, "Join paths error: {}", err),
+            KubeError::NotServed(ref kind) => {
+                write!(f, "The server doesn't serve any version of {}", kind)
+            }
         }
     }
 }
@@ -97,6 +104,7 @@ impl error::Error for KubeError {
             KubeError::ParseErr(_) => None,
             KubeError::Kube(ref err) => Some(err),
             KubeError::KubeServerError(_) => None,
+            KubeError::Status(..) => None,
             KubeError::ConfigFileError(_) => None,
             KubeError::DecodeError(ref err) => Some(err),
             KubeError::Io(ref err) => Some(err),
@@ -105,6 +113,17 @@ impl error::Error for KubeError {
             KubeError::SerdeJson(ref err) => Some(err),
             KubeError::SerdeYaml(ref err) => Some(err),
             KubeError::JoinPathsError(ref err) => Some(err),
+            KubeError::NotServed(_) => None,
+        }
+    }
+}
+
+impl KubeError {
+    /// The status code of the api server response this came from, if it came from one
+    pub fn status_code(&self) -> Option<u16> {
+        match *self {
+            KubeError::Status(code, _) => Some(code),
+            _ => None,
         }
     }
 }
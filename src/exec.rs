@@ -0,0 +1,350 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Running commands in containers, using the kubernetes channel streaming protocol over a
+//! websocket. Every message on the websocket starts with a byte saying which channel it's for.
+//!
+//! We ask for v5.channel.k8s.io, and fall back to v4 on servers that don't support it. v5 adds a
+//! way to close a channel, which is the only way to tell the command there's no more input.
+
+use crate::error::KubeError;
+use crate::kube::{Kluster, KlusterStream};
+use crate::output::ClickWriter;
+use crate::values::val_str;
+use crate::websocket::{Message, WebSocket};
+
+use serde_json::Value;
+
+use std::io::{self, Write};
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub const STDIN_CHANNEL: u8 = 0;
+pub const STDOUT_CHANNEL: u8 = 1;
+pub const STDERR_CHANNEL: u8 = 2;
+pub const ERROR_CHANNEL: u8 = 3;
+const RESIZE_CHANNEL: u8 = 4;
+/// v5 only: a message on this channel closes the channel in its second byte
+const CLOSE_CHANNEL: u8 = 255;
+
+const V5_PROTOCOL: &str = "v5.channel.k8s.io";
+const V4_PROTOCOL: &str = "v4.channel.k8s.io";
+
+/// Tell the command on the other end of ws that there's no more input. Returns false if the
+/// server only speaks v4, which has no way to do that.
+pub fn close_stdin(ws: &mut WebSocket<KlusterStream>) -> io::Result<bool> {
+    if ws.protocol() == Some(V5_PROTOCOL) {
+        ws.send_binary(&[CLOSE_CHANNEL, STDIN_CHANNEL])?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// While this exists, the terminal on fd is in raw mode. The original settings are restored when
+/// it's dropped
+struct RawMode {
+    fd: libc::c_int,
+    orig: libc::termios,
+}
+
+impl RawMode {
+    fn enter(fd: libc::c_int) -> io::Result<RawMode> {
+        let mut termios = MaybeUninit::<libc::termios>::uninit();
+        // safe as we check the return value before using termios
+        let orig = unsafe {
+            if libc::tcgetattr(fd, termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            termios.assume_init()
+        };
+        let mut raw = orig;
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(RawMode { fd, orig })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.orig);
+        }
+    }
+}
+
+fn is_tty(fd: libc::c_int) -> bool {
+    unsafe { libc::isatty(fd) == 1 }
+}
+
+/// Get the size of the terminal as (width, height)
+fn terminal_size() -> Option<(u16, u16)> {
+    let mut size = MaybeUninit::<libc::winsize>::uninit();
+    // safe as we check the return value before using size
+    unsafe {
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) == 0 {
+            let size = size.assume_init();
+            Some((size.ws_col, size.ws_row))
+        } else {
+            None
+        }
+    }
+}
+
+/// Read stdin in a thread, sending what's read back over the returned channel. The fd is polled so
+/// the thread notices when stop is set, rather than sitting in a read and stealing the input
+/// meant for the next prompt.
+fn read_stdin(stop: Arc<AtomicBool>) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        while !stop.load(Ordering::SeqCst) {
+            let mut pollfd = libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ready = unsafe { libc::poll(&mut pollfd, 1, 100) };
+            if ready < 0 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                break;
+            }
+            if ready == 0 {
+                continue;
+            }
+            let amt = unsafe {
+                libc::read(
+                    libc::STDIN_FILENO,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if amt <= 0 || sender.send(buf[..(amt as usize)].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Figure out how the command exited from the Status sent on the error channel
+pub fn exit_status(status: &Value) -> Result<i32, KubeError> {
+    if val_str("/status", status, "") == "Success" {
+        return Ok(0);
+    }
+    if val_str("/reason", status, "") == "NonZeroExitCode" {
+        let code = status
+            .pointer("/details/causes")
+            .and_then(Value::as_array)
+            .and_then(|causes| {
+                causes
+                    .iter()
+                    .find(|cause| val_str("/reason", cause, "") == "ExitCode")
+            })
+            .and_then(|cause| val_str("/message", cause, "").parse().ok());
+        if let Some(code) = code {
+            return Ok(code);
+        }
+    }
+    Err(KubeError::KubeServerError(
+        val_str("/message", status, "Unknown error").into_owned(),
+    ))
+}
+
+/// Start cmd in the specified pod/container, returning the websocket to talk to it over. Reads on
+/// the websocket time out after read_timeout. Use close_stdin to send EOF, if the server allows.
+#[allow(clippy::too_many_arguments)]
+pub fn connect(
+    kluster: &Kluster,
+    namespace: &str,
+    pod: &str,
+    container: Option<&str>,
+    cmd: &[&str],
+    tty: bool,
+    stdin: bool,
+    read_timeout: Duration,
+) -> Result<WebSocket<KlusterStream>, KubeError> {
+    let path = format!("/api/v1/namespaces/{}/pods/{}/exec", namespace, pod);
+    let bool_str = |b: bool| if b { "true" } else { "false" };
+    let mut query = vec![
+        ("stdout", "true"),
+        ("stderr", bool_str(!tty)), // stderr is merged into stdout with a tty
+        ("stdin", bool_str(stdin)),
+        ("tty", bool_str(tty)),
+    ];
+    if let Some(cont) = container {
+        query.push(("container", cont));
+    }
+    for arg in cmd.iter() {
+        query.push(("command", arg));
+    }
+    // the server picks the first of these it supports
+    kluster.connect_websocket(&path, &query, &[V5_PROTOCOL, V4_PROTOCOL], read_timeout)
+}
+
+/// Run cmd in the specified pod/container. stdout goes to writer, stderr to stderr. If stdin is
+/// true stdin is sent to the command, and if tty is true the remote command gets a terminal, and
+/// the local one is put into raw mode. Stops early if ctrlcbool gets set (i.e. if the user hits
+/// Ctrl-C when not in raw mode). Returns the exit code of the command if we got one.
+#[allow(clippy::too_many_arguments)]
+pub fn exec(
+    kluster: &Kluster,
+    namespace: &str,
+    pod: &str,
+    container: Option<&str>,
+    cmd: &[&str],
+    tty: bool,
+    stdin: bool,
+    ctrlcbool: &AtomicBool,
+    writer: &mut ClickWriter,
+) -> Result<Option<i32>, KubeError> {
+    let mut ws = connect(
+        kluster,
+        namespace,
+        pod,
+        container,
+        cmd,
+        tty,
+        stdin,
+        Duration::from_millis(20),
+    )?;
+
+    let raw_mode = if tty && stdin && is_tty(libc::STDIN_FILENO) {
+        Some(RawMode::enter(libc::STDIN_FILENO)?)
+    } else {
+        None
+    };
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut input = if stdin {
+        Some(read_stdin(stop.clone()))
+    } else {
+        None
+    };
+
+    let mut size = None;
+    let mut status = None;
+    let mut res = Ok(());
+    ctrlcbool.store(false, Ordering::SeqCst);
+    while !ctrlcbool.load(Ordering::SeqCst) {
+        if tty {
+            let new_size = terminal_size();
+            if new_size != size {
+                if let Some((width, height)) = new_size {
+                    let mut msg = vec![RESIZE_CHANNEL];
+                    msg.extend(
+                        json!({"Width": width, "Height": height})
+                            .to_string()
+                            .bytes(),
+                    );
+                    res = ws.send_binary(&msg);
+                }
+                size = new_size;
+            }
+            if res.is_err() {
+                break;
+            }
+        }
+
+        match ws.read_message() {
+            Ok(Some(Message::Data(data))) => match data.split_first() {
+                Some((&STDOUT_CHANNEL, out)) => {
+                    res = writer.write_all(out).and_then(|_| writer.flush());
+                }
+                Some((&STDERR_CHANNEL, err)) => {
+                    let mut stderr = io::stderr();
+                    res = stderr.write_all(err).and_then(|_| stderr.flush());
+                }
+                Some((&ERROR_CHANNEL, err)) => match serde_json::from_slice(err) {
+                    Ok(s) => status = Some(exit_status(&s)),
+                    Err(e) => status = Some(Err(KubeError::from(e))),
+                },
+                _ => {} // empty, or a channel we don't care about
+            },
+            Ok(Some(Message::Close)) => break,
+            Ok(None) => {} // nothing to read yet
+            Err(e) => res = Err(e),
+        }
+
+        let mut stdin_closed = false;
+        if let Some(ref receiver) = input {
+            loop {
+                match receiver.try_recv() {
+                    Ok(data) => {
+                        let mut msg = Vec::with_capacity(data.len() + 1);
+                        msg.push(STDIN_CHANNEL);
+                        msg.extend_from_slice(&data);
+                        if let Err(e) = ws.send_binary(&msg) {
+                            res = Err(e);
+                            break;
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        stdin_closed = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if stdin_closed {
+            // nothing more to send, so let the command see EOF
+            input = None;
+            if let Err(e) = close_stdin(&mut ws) {
+                res = Err(e);
+            }
+        }
+
+        if res.is_err() {
+            break;
+        }
+    }
+
+    stop.store(true, Ordering::SeqCst);
+    ws.close();
+    drop(raw_mode);
+    res?;
+    status.transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_status() {
+        assert_eq!(
+            exit_status(&json!({"metadata": {}, "status": "Success"})).unwrap(),
+            0
+        );
+        let failed = json!({
+            "status": "Failure",
+            "message": "command terminated with non-zero exit code",
+            "reason": "NonZeroExitCode",
+            "details": {"causes": [{"reason": "ExitCode", "message": "42"}]}
+        });
+        assert_eq!(exit_status(&failed).unwrap(), 42);
+        let err = json!({"status": "Failure", "message": "container not found"});
+        assert!(exit_status(&err).is_err());
+    }
+}
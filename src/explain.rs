@@ -0,0 +1,340 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Explaining the fields of resources, like kubectl explain. This uses the definitions in the api
+//! server's OpenAPI (v2) schema, so it knows about custom resources that publish a schema too.
+
+use crate::discovery::{ApiResource, Discovery};
+use crate::error::KubeError;
+use crate::output::ClickWriter;
+use crate::values::val_str;
+
+use serde_json::Value;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const WRAP_WIDTH: usize = 80;
+
+/// Where the definitions for context are cached
+pub fn cache_path(config_dir: &Path, context: &str) -> PathBuf {
+    // context names can be things like arns, which aren't good file names
+    let file_name: String = context
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    config_dir
+        .join("openapi")
+        .join(format!("{}.json", file_name))
+}
+
+/// Split what, like deployments.apps.spec.replicas, into the resource and the path of the field in
+/// it. As groups have dots in them too, the longest prefix that's a resource is used.
+pub fn split_path<'a, 'b>(
+    discovery: &'a Discovery,
+    what: &'b str,
+) -> Option<(&'a ApiResource, Vec<&'b str>)> {
+    let parts: Vec<&str> = what.split('.').collect();
+    (1..=parts.len()).rev().find_map(|idx| {
+        discovery
+            .find(&parts[..idx].join("."))
+            .map(|res| (res, parts[idx..].to_vec()))
+    })
+}
+
+/// Find the definition of resource
+fn find_definition<'a>(defs: &'a Value, resource: &ApiResource) -> Option<&'a Value> {
+    let version = match resource.group_version.find('/') {
+        Some(pos) => &resource.group_version[(pos + 1)..],
+        None => resource.group_version.as_str(),
+    };
+    defs.as_object()?.values().find(|def| {
+        def.get("x-kubernetes-group-version-kind")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .any(|gvk| {
+                val_str("/group", gvk, "") == resource.group()
+                    && val_str("/version", gvk, "") == version
+                    && val_str("/kind", gvk, "") == resource.kind
+            })
+    })
+}
+
+/// The definition schema refers to, if it's a reference
+fn resolve<'a>(defs: &'a Value, schema: &'a Value) -> &'a Value {
+    let reference = schema
+        .get("$ref")
+        .or_else(|| schema.pointer("/allOf/0/$ref"))
+        .and_then(Value::as_str)
+        .and_then(|r| r.strip_prefix("#/definitions/"));
+    match reference.and_then(|r| defs.get(r)) {
+        Some(def) => def,
+        None => schema,
+    }
+}
+
+/// The schema that holds the fields of things of type schema, which is the type of the items for
+/// lists and the type of the values for maps
+fn fields_schema<'a>(defs: &'a Value, schema: &'a Value) -> &'a Value {
+    let mut schema = resolve(defs, schema);
+    loop {
+        let inner = match schema.get("items") {
+            Some(items) => items,
+            None if schema.get("properties").is_none() => {
+                match schema.get("additionalProperties") {
+                    Some(values) if values.is_object() => values,
+                    _ => return schema,
+                }
+            }
+            None => return schema,
+        };
+        schema = resolve(defs, inner);
+    }
+}
+
+/// Find the schema of the field at path in schema
+fn field_schema<'a>(
+    defs: &'a Value,
+    schema: &'a Value,
+    path: &[&str],
+) -> Result<&'a Value, KubeError> {
+    let mut schema = schema;
+    for (idx, field) in path.iter().enumerate() {
+        schema = fields_schema(defs, schema)
+            .pointer(&format!("/properties/{}", field))
+            .ok_or_else(|| {
+                KubeError::ParseErr(format!("No field {} in {}", field, path[..idx].join(".")))
+            })?;
+    }
+    Ok(schema)
+}
+
+/// The type of things described by schema, like kubectl shows it: string, []Container or
+/// map[string]string
+fn type_str(schema: &Value) -> String {
+    let reference = schema
+        .get("$ref")
+        .or_else(|| schema.pointer("/allOf/0/$ref"))
+        .and_then(Value::as_str);
+    if let Some(reference) = reference {
+        // like #/definitions/io.k8s.api.core.v1.Container
+        return reference.rsplit('.').next().unwrap_or(reference).to_owned();
+    }
+    match schema.get("type").and_then(Value::as_str) {
+        Some("array") => match schema.get("items") {
+            Some(items) => format!("[]{}", type_str(items)),
+            None => "[]Object".to_owned(),
+        },
+        Some("object") => match schema.get("additionalProperties") {
+            Some(values) if values.is_object() => format!("map[string]{}", type_str(values)),
+            _ => "Object".to_owned(),
+        },
+        Some(typ) => typ.to_owned(),
+        None => "Object".to_owned(),
+    }
+}
+
+/// The names of the fields of things of type schema
+fn field_names<'a>(defs: &'a Value, schema: &'a Value) -> Vec<&'a str> {
+    fields_schema(defs, schema)
+        .get("properties")
+        .and_then(Value::as_object)
+        .map(|props| props.keys().map(|k| k.as_str()).collect())
+        .unwrap_or_default()
+}
+
+/// The fields that can be under the field at path in resource, for completion. Returns nothing if
+/// the resource has no definition or there's no such field.
+pub fn complete_fields<'a>(defs: &'a Value, resource: &ApiResource, path: &[&str]) -> Vec<&'a str> {
+    find_definition(defs, resource)
+        .and_then(|def| field_schema(defs, def, path).ok())
+        .map(|schema| field_names(defs, schema))
+        .unwrap_or_default()
+}
+
+/// Write text word wrapped, with each line indented by indent spaces
+fn write_wrapped(text: &str, indent: usize, writer: &mut ClickWriter) {
+    for para in text.split('\n') {
+        let mut line = String::new();
+        for word in para.split_whitespace() {
+            if !line.is_empty() && indent + line.len() + word.len() + 1 > WRAP_WIDTH {
+                clickwriteln!(writer, "{:indent$}{}", "", line, indent = indent);
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        clickwriteln!(writer, "{:indent$}{}", "", line, indent = indent);
+    }
+}
+
+/// Print the type and description of the field at path in resource, and the fields under it
+pub fn print_explanation(
+    defs: &Value,
+    resource: &ApiResource,
+    path: &[&str],
+    writer: &mut ClickWriter,
+) -> Result<(), KubeError> {
+    let def = find_definition(defs, resource).ok_or_else(|| {
+        KubeError::ParseErr(format!(
+            "The server has no schema for {} ({})",
+            resource.kind, resource.group_version
+        ))
+    })?;
+    let schema = field_schema(defs, def, path)?;
+    clickwriteln!(writer, "KIND:     {}", resource.kind);
+    clickwriteln!(writer, "VERSION:  {}", resource.group_version);
+    clickwriteln!(writer, "");
+    if let Some(field) = path.last() {
+        clickwriteln!(writer, "FIELD:    {} <{}>", field, type_str(schema));
+        clickwriteln!(writer, "");
+    }
+    // references have their description on the definition
+    let description = schema
+        .get("description")
+        .or_else(|| resolve(defs, schema).get("description"))
+        .and_then(Value::as_str)
+        .unwrap_or("<empty>");
+    clickwriteln!(writer, "DESCRIPTION:");
+    write_wrapped(description, 5, writer);
+
+    let fields = fields_schema(defs, schema);
+    if let Some(props) = fields.get("properties").and_then(Value::as_object) {
+        let required: Vec<&str> = fields
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        clickwriteln!(writer, "");
+        clickwriteln!(writer, "FIELDS:");
+        for (name, prop) in props.iter() {
+            let required = if required.contains(&name.as_str()) {
+                " -required-"
+            } else {
+                ""
+            };
+            clickwriteln!(writer, "   {}\t<{}>{}", name, type_str(prop), required);
+            let description = val_str("/description", prop, "<empty>");
+            write_wrapped(&description, 5, writer);
+            clickwriteln!(writer, "");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defs() -> Value {
+        json!({
+            "io.k8s.api.core.v1.Pod": {
+                "description": "Pod is a collection of containers.",
+                "properties": {
+                    "spec": {
+                        "$ref": "#/definitions/io.k8s.api.core.v1.PodSpec",
+                        "description": "Specification of the desired behavior of the pod.",
+                    },
+                },
+                "x-kubernetes-group-version-kind": [
+                    {"group": "", "kind": "Pod", "version": "v1"},
+                ],
+            },
+            "io.k8s.api.core.v1.PodSpec": {
+                "properties": {
+                    "containers": {
+                        "type": "array",
+                        "items": {"$ref": "#/definitions/io.k8s.api.core.v1.Container"},
+                    },
+                    "nodeSelector": {
+                        "type": "object",
+                        "additionalProperties": {"type": "string"},
+                    },
+                },
+                "required": ["containers"],
+            },
+            "io.k8s.api.core.v1.Container": {
+                "properties": {
+                    "name": {"type": "string"},
+                    "args": {"type": "array", "items": {"type": "string"}},
+                },
+            },
+        })
+    }
+
+    fn pods() -> ApiResource {
+        ApiResource {
+            name: "pods".to_owned(),
+            singular_name: "pod".to_owned(),
+            namespaced: true,
+            kind: "Pod".to_owned(),
+            verbs: vec![],
+            short_names: vec!["po".to_owned()],
+            group_version: "v1".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_split_path() {
+        let mut deployments = pods();
+        deployments.name = "deployments".to_owned();
+        deployments.kind = "Deployment".to_owned();
+        deployments.group_version = "apps/v1".to_owned();
+        let discovery = Discovery {
+            groups: vec![],
+            resources: vec![pods(), deployments],
+        };
+        let (res, path) = split_path(&discovery, "po.spec.containers").unwrap();
+        assert_eq!(
+            (res.kind.as_str(), path),
+            ("Pod", vec!["spec", "containers"])
+        );
+        let (res, path) = split_path(&discovery, "deployments.apps.spec").unwrap();
+        assert_eq!((res.kind.as_str(), path), ("Deployment", vec!["spec"]));
+        assert!(split_path(&discovery, "widgets.spec").is_none());
+    }
+
+    #[test]
+    fn test_fields() {
+        let defs = defs();
+        let pod = find_definition(&defs, &pods()).unwrap();
+        let containers = field_schema(&defs, pod, &["spec", "containers"]).unwrap();
+        assert_eq!(type_str(containers), "[]Container");
+        assert_eq!(field_names(&defs, containers), vec!["args", "name"]);
+        let args = field_schema(&defs, pod, &["spec", "containers", "args"]).unwrap();
+        assert_eq!(type_str(args), "[]string");
+        let selector = field_schema(&defs, pod, &["spec", "nodeSelector"]).unwrap();
+        assert_eq!(type_str(selector), "map[string]string");
+        assert!(field_schema(&defs, pod, &["spec", "bogus"]).is_err());
+        assert_eq!(
+            complete_fields(&defs, &pods(), &["spec"]),
+            vec!["containers", "nodeSelector"]
+        );
+        assert_eq!(
+            cache_path(Path::new("/k"), "arn:aws:eks:us-west-2/prod"),
+            PathBuf::from("/k/openapi/arn_aws_eks_us-west-2_prod.json")
+        );
+    }
+}
@@ -1,11 +1,13 @@
 use crate::describe;
-use crate::kube::Metadata;
+use crate::discovery::ApiResource;
+use crate::error::KubeError;
+use crate::kube::{Kluster, Metadata};
 use crate::output::ClickWriter;
 use crate::values::val_str_opt;
 use crate::Env;
 
 use ansi_term::ANSIString;
-use ansi_term::Colour::{Blue, Cyan, Green, Purple, Red, Yellow};
+use ansi_term::Colour::{Blue, Cyan, Green, Purple, Red, White, Yellow};
 use clap::ArgMatches;
 use serde::ser::Serialize;
 use serde_json::Value;
@@ -14,7 +16,9 @@ use std::io::Write;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ObjType {
-    Pod { containers: Vec<String> },
+    Pod {
+        containers: Vec<String>,
+    },
     Node,
     Deployment,
     Service,
@@ -23,6 +27,52 @@ pub enum ObjType {
     ConfigMap,
     Secret,
     Job,
+    /// Any other kind of resource, found through API discovery
+    Dynamic(ApiResource),
+}
+
+impl ObjType {
+    /// The kind of object this is
+    pub fn kind(&self) -> &str {
+        match self {
+            ObjType::Pod { .. } => "Pod",
+            ObjType::Node => "Node",
+            ObjType::Deployment => "Deployment",
+            ObjType::Service => "Service",
+            ObjType::ReplicaSet => "ReplicaSet",
+            ObjType::StatefulSet => "StatefulSet",
+            ObjType::ConfigMap => "ConfigMap",
+            ObjType::Secret => "Secret",
+            ObjType::Job => "Job",
+            ObjType::Dynamic(ref res) => &res.kind,
+        }
+    }
+
+    /// The groups that objects of this type can be served from, in order of preference. Core
+    /// (v1) types aren't included, as they don't change, so we don't need discovery to find them.
+    /// Older clusters serve some kinds from groups that have since been removed.
+    fn groups(&self) -> &'static [&'static str] {
+        match self {
+            ObjType::Deployment | ObjType::ReplicaSet => &["apps", "extensions"],
+            ObjType::StatefulSet => &["apps"],
+            ObjType::Job => &["batch"],
+            _ => &[],
+        }
+    }
+
+    /// Find out how kluster serves this type of object, using discovery. The preferred version of
+    /// the first group in groups() that serves it is used, so apps/v1 on any recent cluster.
+    pub fn api_resource(&self, kluster: &Kluster) -> Result<ApiResource, KubeError> {
+        if let ObjType::Dynamic(ref res) = self {
+            return Ok(res.clone());
+        }
+        let discovery = kluster.discovery()?;
+        self.groups()
+            .iter()
+            .find_map(|group| discovery.find_kind(group, self.kind()))
+            .cloned()
+            .ok_or_else(|| KubeError::NotServed(self.kind().to_string()))
+    }
 }
 
 /// An object we can have as a "current" thing
@@ -150,17 +200,7 @@ impl KObj {
     }
 
     pub fn type_str(&self) -> &str {
-        match self.typ {
-            ObjType::Pod { .. } => "Pod",
-            ObjType::Node => "Node",
-            ObjType::Deployment => "Deployment",
-            ObjType::Service => "Service",
-            ObjType::ReplicaSet => "ReplicaSet",
-            ObjType::StatefulSet => "StatefulSet",
-            ObjType::ConfigMap => "ConfigMap",
-            ObjType::Secret => "Secret",
-            ObjType::Job => "Job",
-        }
+        self.typ.kind()
     }
 
     pub fn prompt_str(&self) -> ANSIString {
@@ -174,6 +214,7 @@ impl KObj {
             ObjType::ConfigMap => Purple.bold().paint(self.name.as_str()),
             ObjType::Secret => Red.bold().paint(self.name.as_str()),
             ObjType::Job => Purple.bold().paint(self.name.as_str()),
+            ObjType::Dynamic(_) => White.bold().paint(self.name.as_str()),
         }
     }
 
@@ -186,34 +227,25 @@ impl KObj {
         matches!(self.typ, ObjType::Pod { .. })
     }
 
-    pub fn url(&self, namespace: &str) -> String {
-        match self.typ {
+    /// The url for this object. Which group version to use for kinds not in the core group is
+    /// found using discovery, which is why this needs the kluster
+    pub fn url(&self, kluster: &Kluster, namespace: &str) -> Result<String, KubeError> {
+        Ok(match self.typ {
             ObjType::Pod { .. } => format!("/api/v1/namespaces/{}/pods/{}", namespace, self.name),
             ObjType::Node => format!("/api/v1/nodes/{}", self.name),
-            ObjType::Deployment => format!(
-                "/apis/extensions/v1beta1/namespaces/{}/deployments/{}",
-                namespace, self.name
-            ),
             ObjType::Service => format!("/api/v1/namespaces/{}/services/{}", namespace, self.name),
-            ObjType::ReplicaSet => format!(
-                "/apis/extensions/v1beta1/namespaces/{}/replicasets/{}",
-                namespace, self.name
-            ),
-            ObjType::StatefulSet => format!(
-                "/apis/apps/v1beta1/namespaces/{}/statefulsets/{}",
-                namespace, self.name
-            ),
             ObjType::ConfigMap => {
                 format!("/api/v1/namespaces/{}/configmaps/{}", namespace, self.name)
             }
             ObjType::Secret => format!("/api/v1/namespaces/{}/secrets/{}", namespace, self.name),
-            ObjType::Job => format!("/apis/batch/v1/namespaces/{}/jobs/{}", namespace, self.name),
-        }
+            _ => self.typ.api_resource(kluster)?.url(namespace, &self.name),
+        })
     }
 
     pub fn describe(&self, matches: &ArgMatches, env: &Env, writer: &mut ClickWriter) {
         let namespace = match self.typ {
             ObjType::Node => "",
+            ObjType::Dynamic(ref res) if !res.namespaced => "",
             _ => match self.namespace {
                 Some(ref ns) => ns,
                 None => {
@@ -223,8 +255,7 @@ impl KObj {
             },
         };
 
-        let url = self.url(namespace);
-        match env.run_on_kluster(|k| k.get_value(url.as_str())) {
+        match env.run_on_kluster(|k| k.get_value(self.url(k, namespace)?.as_str())) {
             Some(val) => {
                 if !maybe_full_describe_output(matches, &val, writer) {
                     match self.typ {
@@ -250,6 +281,8 @@ impl KObj {
                                 describe::describe_format_service(val, endpoint_val)
                             )
                         }
+                        // there's no specific format for these, so show the whole thing
+                        ObjType::Dynamic(_) => writer.print_yaml(&val).unwrap_or(()),
                         _ => clickwriteln!(writer, "{} {}", self.type_str(), NOTSUPPORTED),
                     }
                 }
@@ -258,3 +291,123 @@ impl KObj {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::Discovery;
+
+    fn resource(group_version: &str, name: &str, kind: &str, namespaced: bool) -> ApiResource {
+        ApiResource {
+            name: name.to_owned(),
+            singular_name: kind.to_lowercase(),
+            namespaced,
+            kind: kind.to_owned(),
+            verbs: vec![],
+            short_names: vec![],
+            group_version: group_version.to_owned(),
+        }
+    }
+
+    fn kluster(resources: Vec<ApiResource>) -> Kluster {
+        let kluster = Kluster::new(
+            "test",
+            None,
+            "https://kube.test:443",
+            None,
+            None,
+            true,
+            10,
+            20,
+        )
+        .unwrap();
+        kluster.set_discovery(Discovery {
+            groups: vec![],
+            resources,
+        });
+        kluster
+    }
+
+    fn kobj(name: &str, typ: ObjType) -> KObj {
+        KObj {
+            name: name.to_owned(),
+            namespace: Some("ns".to_owned()),
+            typ,
+        }
+    }
+
+    #[test]
+    fn test_api_resource() {
+        let old = kluster(vec![
+            resource("extensions/v1beta1", "deployments", "Deployment", true),
+            resource("extensions/v1beta1", "replicasets", "ReplicaSet", true),
+        ]);
+        let gv = |k: &Kluster, typ: ObjType| typ.api_resource(k).map(|r| r.group_version);
+        assert_eq!(gv(&old, ObjType::Deployment).unwrap(), "extensions/v1beta1");
+        assert!(gv(&old, ObjType::StatefulSet).is_err());
+
+        let both = kluster(vec![
+            resource("extensions/v1beta1", "deployments", "Deployment", true),
+            resource("apps/v1", "deployments", "Deployment", true),
+            resource("apps/v1", "statefulsets", "StatefulSet", true),
+            resource("batch/v1", "jobs", "Job", true),
+        ]);
+        assert_eq!(gv(&both, ObjType::Deployment).unwrap(), "apps/v1");
+        assert_eq!(gv(&both, ObjType::StatefulSet).unwrap(), "apps/v1");
+        assert_eq!(gv(&both, ObjType::Job).unwrap(), "batch/v1");
+        assert!(matches!(
+            gv(&both, ObjType::ReplicaSet),
+            Err(KubeError::NotServed(ref kind)) if kind == "ReplicaSet"
+        ));
+
+        // dynamic types already know their resource, so don't need discovery
+        let issuers = resource("cert-manager.io/v1", "issuers", "Issuer", true);
+        assert_eq!(
+            ObjType::Dynamic(issuers.clone())
+                .api_resource(&kluster(vec![]))
+                .unwrap(),
+            issuers
+        );
+    }
+
+    #[test]
+    fn test_url() {
+        let k = kluster(vec![resource("apps/v1", "deployments", "Deployment", true)]);
+        let url = |obj: KObj, ns| obj.url(&k, ns).unwrap();
+        assert_eq!(
+            url(kobj("p1", ObjType::Pod { containers: vec![] }), "ns"),
+            "/api/v1/namespaces/ns/pods/p1"
+        );
+        assert_eq!(url(kobj("n1", ObjType::Node), ""), "/api/v1/nodes/n1");
+        assert_eq!(
+            url(kobj("s1", ObjType::Secret), "ns"),
+            "/api/v1/namespaces/ns/secrets/s1"
+        );
+        assert_eq!(
+            url(kobj("web", ObjType::Deployment), "ns"),
+            "/apis/apps/v1/namespaces/ns/deployments/web"
+        );
+        assert!(kobj("db", ObjType::StatefulSet).url(&k, "ns").is_err());
+
+        let certs = resource("cert-manager.io/v1", "certificates", "Certificate", true);
+        assert_eq!(
+            url(kobj("tls", ObjType::Dynamic(certs)), "ns"),
+            "/apis/cert-manager.io/v1/namespaces/ns/certificates/tls"
+        );
+        let issuers = resource(
+            "cert-manager.io/v1",
+            "clusterissuers",
+            "ClusterIssuer",
+            false,
+        );
+        assert_eq!(
+            url(kobj("le", ObjType::Dynamic(issuers)), "ns"),
+            "/apis/cert-manager.io/v1/clusterissuers/le"
+        );
+        let pvs = resource("v1", "persistentvolumes", "PersistentVolume", false);
+        assert_eq!(
+            url(kobj("pv1", ObjType::Dynamic(pvs)), ""),
+            "/api/v1/persistentvolumes/pv1"
+        );
+    }
+}
@@ -21,26 +21,35 @@ use hyper::client::request::Request;
 use hyper::client::response::Response;
 use hyper::client::{Body, RequestBuilder};
 use hyper::error::Error as HyperError;
-use hyper::header::{Authorization, Basic, Bearer};
+use hyper::header::{qitem, Accept, Authorization, Basic, Bearer, ContentType, Headers};
 use hyper::method::Method;
+use hyper::mime::Mime;
+use hyper::net::{HttpsStream, NetworkStream};
 use hyper::status::StatusCode;
 use hyper::{Client, Url};
-use hyper_sync_rustls::TlsClient;
-use rustls::{self, Certificate, PrivateKey};
+use hyper_sync_rustls::{TlsClient, WrappedStream};
+use rustls::{self, Certificate, ClientSession, PrivateKey};
 use serde::Deserialize;
 use serde_json::{Map, Value};
 
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
+use std::fs;
 use std::io::BufReader;
 use std::net::IpAddr;
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::rc::Rc;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use crate::config::{AuthProvider, ExecAuth, ExecProvider};
 use crate::connector::ClickSslConnector;
+use crate::discovery::Discovery;
 use crate::error::{KubeErrNo, KubeError};
+use crate::websocket::WebSocket;
 
 // Various things we can return from the kubernetes api
 
@@ -180,11 +189,19 @@ pub struct VolumeMount {
     pub sub_path: Option<String>,
 }
 
+/// Resource requests and limits, like cpu: 250m, as the quantity strings the api returns
+#[derive(Debug, Default, Deserialize)]
+pub struct ResourceRequirements {
+    pub requests: Option<HashMap<String, String>>,
+    pub limits: Option<HashMap<String, String>>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ContainerSpec {
     pub name: String,
     pub args: Option<Vec<String>>,
     pub command: Option<Vec<String>>,
+    pub resources: Option<ResourceRequirements>,
     #[serde(rename = "volumeMounts")]
     pub volume_mounts: Option<Vec<VolumeMount>>,
 }
@@ -235,6 +252,7 @@ pub struct NodeCondition {
 #[derive(Debug, Deserialize)]
 pub struct NodeStatus {
     pub conditions: Vec<NodeCondition>,
+    pub allocatable: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -346,6 +364,28 @@ pub struct NamespaceList {
     pub items: Vec<Namespace>,
 }
 
+/// A list that can be fetched from the server in pages, which then need to be joined up
+pub trait PagedList {
+    fn len(&self) -> usize;
+    fn append(&mut self, other: Self);
+}
+
+macro_rules! paged_list_imp {
+    ($($struct: ident),*) => {
+        $(
+            impl PagedList for $struct {
+                fn len(&self) -> usize {
+                    self.items.len()
+                }
+
+                fn append(&mut self, other: Self) {
+                    self.items.extend(other.items);
+                }
+            }
+        )*
+    };
+}
+
 pub trait ValueList {
     fn values(&self) -> &Vec<Value>;
     fn typ(&self) -> crate::kobj::ObjType;
@@ -400,6 +440,120 @@ pub struct JobList {
 }
 value_list_imp!(JobList, crate::kobj::ObjType::Job);
 
+// A list of any kind of resource, for kinds found through discovery
+#[derive(Debug, Deserialize)]
+pub struct ResourceList {
+    pub items: Vec<Value>,
+}
+
+// Tables, as rendered by the server
+#[derive(Debug, Deserialize)]
+pub struct TableColumn {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: String,
+    /// columns with a priority above 0 are only shown in wide output
+    #[serde(default)]
+    pub priority: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TableRow {
+    pub cells: Vec<Value>,
+    /// the metadata of the object this row is for
+    #[serde(default)]
+    pub object: Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ServerTable {
+    #[serde(rename = "columnDefinitions")]
+    pub columns: Vec<TableColumn>,
+    #[serde(default)]
+    pub rows: Vec<TableRow>,
+}
+
+impl ServerTable {
+    /// Find the index of the column called name, ignoring case
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|col| col.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Sort the rows by the column at index col. Numbers are compared as numbers, dates as dates
+    /// and everything else as strings. The Age column is sorted by creation time, so the oldest
+    /// objects are first.
+    pub fn sort_by_column(&mut self, col: usize) {
+        let column = &self.columns[col];
+        let typ = column.typ.as_str();
+        if column.name == "Age" {
+            self.rows.sort_by(|r1, r2| {
+                let created = |row: &TableRow| {
+                    crate::values::val_str_opt("/metadata/creationTimestamp", &row.object)
+                        .and_then(|ts| ts.parse::<DateTime<Utc>>().ok())
+                };
+                crate::table::opt_sort(created(r1), created(r2), |c1, c2| c1.cmp(c2))
+            });
+            return;
+        }
+        self.rows.sort_by(|r1, r2| {
+            let (c1, c2) = (r1.cells.get(col), r2.cells.get(col));
+            match typ {
+                "integer" | "number" => crate::table::opt_sort(
+                    c1.and_then(Value::as_f64),
+                    c2.and_then(Value::as_f64),
+                    |n1, n2| n1.partial_cmp(n2).unwrap_or(std::cmp::Ordering::Equal),
+                ),
+                "date" => crate::table::opt_sort(
+                    c1.and_then(Value::as_str)
+                        .and_then(|s| s.parse::<DateTime<Utc>>().ok()),
+                    c2.and_then(Value::as_str)
+                        .and_then(|s| s.parse::<DateTime<Utc>>().ok()),
+                    |d1, d2| d1.cmp(d2),
+                ),
+                _ => crate::table::opt_sort(
+                    c1.map(table_cell_string),
+                    c2.map(table_cell_string),
+                    |s1, s2| s1.cmp(s2),
+                ),
+            }
+        });
+    }
+}
+
+/// The text of a server side table cell
+pub fn table_cell_string(cell: &Value) -> String {
+    match cell {
+        Value::String(s) => s.clone(),
+        Value::Null => "<none>".to_owned(),
+        other => other.to_string(),
+    }
+}
+
+paged_list_imp!(
+    PodList,
+    NodeList,
+    DeploymentList,
+    ServiceList,
+    ReplicaSetList,
+    StatefulSetList,
+    ConfigMapList,
+    SecretList,
+    JobList,
+    ResourceList
+);
+
+impl PagedList for ServerTable {
+    fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn append(&mut self, other: Self) {
+        self.rows.extend(other.rows);
+    }
+}
+
 // Kubernetes authentication data
 
 // Auth is either a token, a username/password, or an auth provider
@@ -443,21 +597,205 @@ impl ClientCertKey {
     }
 }
 
+/// The different kinds of PATCH requests the api server understands
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PatchType {
+    /// A JSON merge patch (RFC 7386)
+    Merge,
+    /// A kubernetes strategic merge patch, which knows how to merge lists by key
+    StrategicMerge,
+    /// A JSON patch (RFC 6902), a list of operations to apply
+    Json,
+    /// A server-side apply, where body is the full configuration the caller wants. The request
+    /// needs a fieldManager parameter
+    Apply,
+}
+
+impl PatchType {
+    fn content_type(self) -> &'static str {
+        match self {
+            PatchType::Merge => "application/merge-patch+json",
+            PatchType::StrategicMerge => "application/strategic-merge-patch+json",
+            PatchType::Json => "application/json-patch+json",
+            PatchType::Apply => "application/apply-patch+yaml",
+        }
+    }
+}
+
 // Hold either a Bearer or Basic auth header
+#[derive(Clone)]
 enum AuthHeader {
     Basic(Basic),
     Bearer(Bearer),
 }
 
+impl AuthHeader {
+    /// The header for auth. Tokens from auth and exec providers are refreshed first if they've
+    /// expired.
+    fn for_auth(auth: &KlusterAuth) -> Option<AuthHeader> {
+        match auth {
+            KlusterAuth::Token(ref token) => Some(AuthHeader::Bearer(Bearer {
+                token: token.clone(),
+            })),
+            KlusterAuth::AuthProvider(ref auth_provider) => match auth_provider.ensure_token() {
+                Some(token) => Some(AuthHeader::Bearer(Bearer { token })),
+                None => {
+                    print_token_err();
+                    None
+                }
+            },
+            KlusterAuth::UserPass(ref user, ref pass) => Some(AuthHeader::Basic(Basic {
+                username: user.clone(),
+                password: Some(pass.clone()),
+            })),
+            KlusterAuth::ExecProvider(ref exec_provider) => {
+                let (auth, _) = exec_provider.get_auth();
+                match auth {
+                    ExecAuth::Token(token) => Some(AuthHeader::Bearer(Bearer { token })),
+                    ExecAuth::ClientCertKey { .. } => None, // handled by handle_exec_provider
+                }
+            }
+        }
+    }
+
+    fn set_on(self, headers: &mut Headers) {
+        match self {
+            AuthHeader::Basic(header) => headers.set(Authorization(header)),
+            AuthHeader::Bearer(header) => headers.set(Authorization(header)),
+        }
+    }
+}
+
+/// Turn an unsuccessful response into an error, using the message the server sent if possible
+fn check_resp(resp: Response) -> Result<Response, KubeError> {
+    if resp.status.is_success() {
+        Ok(resp)
+    } else if resp.status == StatusCode::Unauthorized {
+        Err(KubeError::Kube(KubeErrNo::Unauthorized))
+    } else {
+        // try and read an error message out
+        let code = resp.status.to_u16();
+        let val: Value = serde_json::from_reader(resp)?;
+        match crate::values::val_str_opt("/message", &val) {
+            Some(msg) => Err(KubeError::Status(code, msg)),
+            None => Err(KubeError::Kube(KubeErrNo::Unknown)),
+        }
+    }
+}
+
+/// The kind of stream a Kluster's websockets run over
+pub type KlusterStream = HttpsStream<WrappedStream<ClientSession>>;
+
 pub struct Kluster {
     pub name: String,
     endpoint: Url,
-    auth: Option<KlusterAuth>,
+    // shared with our KlusterConnectors, so they see refreshed tokens
+    auth: Option<Arc<Mutex<KlusterAuth>>>,
     root_cert: Option<String>,
     client_cert_key: Option<ClientCertKey>,
     insecure: bool,
     client: RefCell<Client>,
     connector: RefCell<ClickSslConnector<TlsClient>>,
+    discovery: RefCell<Option<Rc<Discovery>>>,
+    openapi: RefCell<Option<Rc<Value>>>,
+}
+
+/// Everything needed to open websockets to, and make simple GET requests of, a Kluster. Unlike a
+/// Kluster this can be sent to other threads. The auth header is worked out for each request, so
+/// expired tokens get refreshed just like they do for the Kluster. Client certificates are fixed
+/// when this is created though.
+#[derive(Clone)]
+pub struct KlusterConnector {
+    endpoint: Url,
+    connector: ClickSslConnector<TlsClient>,
+    auth: Option<Arc<Mutex<KlusterAuth>>>,
+}
+
+impl KlusterConnector {
+    fn headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        let auth = self
+            .auth
+            .as_ref()
+            .and_then(|auth| AuthHeader::for_auth(&auth.lock().unwrap()));
+        if let Some(auth) = auth {
+            auth.set_on(&mut headers);
+        }
+        headers
+    }
+
+    /// Get a resource and deserialize it as a T
+    pub fn get<T>(&self, path: &str) -> Result<T, KubeError>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let resp = self.get_read(path, Some(Duration::new(20, 0)))?;
+        serde_json::from_reader(resp).map_err(KubeError::from)
+    }
+
+    /// Get a Response to read from. A timeout of None means reads never time out, which is what
+    /// following logs needs
+    pub fn get_read(&self, path: &str, timeout: Option<Duration>) -> Result<Response, KubeError> {
+        let url = self.endpoint.join(path)?;
+        let mut req = Request::with_connector(Method::Get, url, &self.connector)?;
+        *req.headers_mut() = self.headers();
+        req.set_read_timeout(timeout)?;
+        check_resp(req.start()?.send()?)
+    }
+
+    /// Open a websocket to path, with the specified query parameters, asking for one of
+    /// protocols. Once connected, reads on the websocket will time out after read_timeout (see
+    /// websocket.rs for why this is needed)
+    pub fn connect_websocket(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        protocols: &[&str],
+        read_timeout: Duration,
+    ) -> Result<WebSocket<KlusterStream>, KubeError> {
+        self.connect_websocket_fd(path, query, protocols, read_timeout)
+            .map(|(ws, _)| ws)
+    }
+
+    /// Like connect_websocket, but also returns the fd of the socket under the websocket, so it
+    /// can be polled (see ClickSslConnector::connect_fd for what that can and can't tell you)
+    pub fn connect_websocket_fd(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        protocols: &[&str],
+        read_timeout: Duration,
+    ) -> Result<(WebSocket<KlusterStream>, RawFd), KubeError> {
+        let mut url = self.endpoint.join(path)?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query.iter());
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| KubeError::ParseErr(format!("No host in url: {}", url)))?;
+        let port = url.port_or_known_default().unwrap_or(443);
+        let (stream, fd) = self.connector.connect_fd(host, port, url.scheme())?;
+        stream.set_read_timeout(Some(Duration::new(20, 0)))?;
+        stream.set_write_timeout(Some(Duration::new(20, 0)))?;
+
+        let host_header = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        let path_and_query = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let ws = WebSocket::handshake(
+            stream,
+            &host_header,
+            &path_and_query,
+            &self.headers().to_string(),
+            protocols,
+        )?;
+        ws.get_ref().set_read_timeout(Some(read_timeout))?;
+        Ok((ws, fd))
+    }
 }
 
 // NoCertificateVerification struct/impl taken from the rustls example code
@@ -565,10 +903,20 @@ impl Kluster {
         *self.connector.borrow_mut() = new_connector;
     }
 
-    fn handle_exec_provider(&self, exec_provider: &ExecProvider) {
+    /// If auth is an exec provider that gives out client certificates, make sure we're using a
+    /// current one
+    fn handle_exec_provider(&self) {
+        let kluster_auth = match self.auth {
+            Some(ref auth) => auth.lock().unwrap(),
+            None => return,
+        };
+        let exec_provider = match *kluster_auth {
+            KlusterAuth::ExecProvider(ref exec_provider) => exec_provider,
+            _ => return,
+        };
         let (auth, was_expired) = exec_provider.get_auth();
         match auth {
-            ExecAuth::Token(_) => {} // handled below
+            ExecAuth::Token(_) => {} // handled in AuthHeader::for_auth
             ExecAuth::ClientCertKey { cert, key } => {
                 if was_expired {
                     let client_cert_key = Some(ClientCertKey::with_cert_and_key(cert, key));
@@ -579,32 +927,9 @@ impl Kluster {
     }
 
     fn get_auth_header(&self) -> Option<AuthHeader> {
-        match self.auth {
-            Some(KlusterAuth::Token(ref token)) => Some(AuthHeader::Bearer(Bearer {
-                token: token.clone(),
-            })),
-            Some(KlusterAuth::AuthProvider(ref auth_provider)) => {
-                match auth_provider.ensure_token() {
-                    Some(token) => Some(AuthHeader::Bearer(Bearer { token })),
-                    None => {
-                        print_token_err();
-                        None
-                    }
-                }
-            }
-            Some(KlusterAuth::UserPass(ref user, ref pass)) => Some(AuthHeader::Basic(Basic {
-                username: user.clone(),
-                password: Some(pass.clone()),
-            })),
-            Some(KlusterAuth::ExecProvider(ref exec_provider)) => {
-                let (auth, _) = exec_provider.get_auth();
-                match auth {
-                    ExecAuth::Token(token) => Some(AuthHeader::Bearer(Bearer { token })),
-                    ExecAuth::ClientCertKey { .. } => None, // handled above
-                }
-            }
-            None => None,
-        }
+        self.auth
+            .as_ref()
+            .and_then(|auth| AuthHeader::for_auth(&auth.lock().unwrap()))
     }
 
     fn add_auth_header<'a>(&self, req: RequestBuilder<'a>) -> RequestBuilder<'a> {
@@ -640,7 +965,7 @@ impl Kluster {
         Ok(Kluster {
             name: name.to_owned(),
             endpoint,
-            auth,
+            auth: auth.map(|auth| Arc::new(Mutex::new(auth))),
             root_cert: cert_opt,
             client_cert_key,
             insecure,
@@ -651,28 +976,59 @@ impl Kluster {
                 ip,
                 Duration::new(connect_timeout_secs.into(), 0),
             )),
+            discovery: RefCell::new(None),
+            openapi: RefCell::new(None),
         })
     }
 
-    fn send_req(&self, path: &str) -> Result<Response, HyperError> {
+    fn send_req(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&str>,
+        content_type: Option<&str>,
+        accept: Option<&str>,
+    ) -> Result<Response, HyperError> {
         let url = self.endpoint.join(path)?;
-        if let Some(KlusterAuth::ExecProvider(ref exec_provider)) = self.auth {
-            self.handle_exec_provider(exec_provider);
-        }
+        self.handle_exec_provider();
         let client = self.client.borrow();
-        let req = client.get(url);
+        let req = client.request(method, url);
+        let req = match body {
+            Some(b) => {
+                let hyper_body = Body::BufBody(b.as_bytes(), b.len());
+                req.body(hyper_body)
+            }
+            None => req,
+        };
+        let req = match content_type.and_then(|ct| ct.parse::<Mime>().ok()) {
+            Some(mime) => req.header(ContentType(mime)),
+            None => req,
+        };
+        let req = match accept.and_then(|a| a.parse::<Mime>().ok()) {
+            Some(mime) => req.header(Accept(vec![qitem(mime)])),
+            None => req,
+        };
         let req = self.add_auth_header(req);
         req.send()
     }
 
-    fn send(&self, path: &str) -> Result<Response, KubeError> {
-        match self.send_req(path) {
+    /// Send a request, creating a new client and retrying once if the connection was reset
+    fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&str>,
+        content_type: Option<&str>,
+        accept: Option<&str>,
+    ) -> Result<Response, KubeError> {
+        match self.send_req(method.clone(), path, body, content_type, accept) {
             Ok(resp) => Ok(resp),
             Err(e) => match &e {
                 HyperError::Io(ref io_err) => {
                     if io_err.kind() == std::io::ErrorKind::ConnectionReset {
                         self.create_new_client(&self.client_cert_key);
-                        self.send_req(path).map_err(KubeError::from)
+                        self.send_req(method, path, body, content_type, accept)
+                            .map_err(KubeError::from)
                     } else {
                         Err(KubeError::from(e))
                     }
@@ -682,28 +1038,13 @@ impl Kluster {
         }
     }
 
-    fn check_resp(&self, resp: Response) -> Result<Response, KubeError> {
-        if resp.status == StatusCode::Ok {
-            Ok(resp)
-        } else if resp.status == StatusCode::Unauthorized {
-            Err(KubeError::Kube(KubeErrNo::Unauthorized))
-        } else {
-            // try and read an error message out
-            let val: Value = serde_json::from_reader(resp)?;
-            match crate::values::val_str_opt("/message", &val) {
-                Some(msg) => Err(KubeError::KubeServerError(msg)),
-                None => Err(KubeError::Kube(KubeErrNo::Unknown)),
-            }
-        }
-    }
-
     /// Get a resource and deserialize it as a T
     pub fn get<T>(&self, path: &str) -> Result<T, KubeError>
     where
         for<'de> T: Deserialize<'de>,
     {
-        let resp = self.send(path)?;
-        let resp = self.check_resp(resp)?;
+        let resp = self.send(Method::Get, path, None, None, None)?;
+        let resp = check_resp(resp)?;
         serde_json::from_reader(resp).map_err(KubeError::from)
     }
 
@@ -731,7 +1072,7 @@ impl Kluster {
         // None here means don't timeout, which we set for logs follow
         req.set_read_timeout(timeout)?;
         match req.start()?.send() {
-            Ok(resp) => self.check_resp(resp),
+            Ok(resp) => check_resp(resp),
             Err(e) => match &e {
                 HyperError::Io(ref io_err) => {
                     if retry && io_err.kind() == std::io::ErrorKind::ConnectionReset {
@@ -746,13 +1087,154 @@ impl Kluster {
         }
     }
 
+    /// Get a KlusterConnector for this cluster, for talking to it from other threads
+    pub fn connector(&self) -> KlusterConnector {
+        self.handle_exec_provider();
+        KlusterConnector {
+            endpoint: self.endpoint.clone(),
+            connector: self.connector.borrow().clone(),
+            auth: self.auth.clone(),
+        }
+    }
+
+    /// Open a websocket to path, with the specified query parameters, asking for one of
+    /// protocols. Once connected, reads on the websocket will time out after read_timeout (see
+    /// websocket.rs for why this is needed)
+    pub fn connect_websocket(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        protocols: &[&str],
+        read_timeout: Duration,
+    ) -> Result<WebSocket<KlusterStream>, KubeError> {
+        self.connector()
+            .connect_websocket(path, query, protocols, read_timeout)
+    }
+
+    /// Get the groups and resources this cluster serves. This is fetched the first time it's
+    /// needed, and then cached
+    pub fn discovery(&self) -> Result<Rc<Discovery>, KubeError> {
+        if let Some(ref disc) = *self.discovery.borrow() {
+            return Ok(disc.clone());
+        }
+        let disc = Rc::new(Discovery::fetch(&self.connector())?);
+        *self.discovery.borrow_mut() = Some(disc.clone());
+        Ok(disc)
+    }
+
+    /// Throw away what discovery found, and ask the server again. This is needed to see new kinds,
+    /// like after custom resources are installed.
+    pub fn refresh_discovery(&self) -> Result<Rc<Discovery>, KubeError> {
+        *self.discovery.borrow_mut() = None;
+        self.discovery()
+    }
+
+    /// Get the result of discovery, but only if it's already been fetched
+    pub fn cached_discovery(&self) -> Option<Rc<Discovery>> {
+        self.discovery.borrow().clone()
+    }
+
+    // Code to use a known set of resources in a test, rather than asking a server
+    #[cfg(test)]
+    pub fn set_discovery(&self, discovery: Discovery) {
+        *self.discovery.borrow_mut() = Some(Rc::new(discovery));
+    }
+
+    /// Get the definitions of all the types in the server's OpenAPI (v2) schema. The schema is big
+    /// and slow to fetch, so the definitions are kept in memory, and on disk at cache_path. With
+    /// refresh they're fetched from the server again.
+    pub fn openapi_definitions(
+        &self,
+        cache_path: &Path,
+        refresh: bool,
+    ) -> Result<Rc<Value>, KubeError> {
+        if !refresh {
+            if let Some(defs) = self.cached_openapi_definitions(cache_path) {
+                return Ok(defs);
+            }
+        }
+        let mut schema = self.get_value("/openapi/v2")?;
+        let defs = match schema.get_mut("definitions") {
+            Some(defs) => defs.take(),
+            None => {
+                return Err(KubeError::ParseErr(
+                    "OpenAPI schema has no definitions".to_owned(),
+                ))
+            }
+        };
+        if let Some(dir) = cache_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(cache_path, defs.to_string())?;
+        let defs = Rc::new(defs);
+        *self.openapi.borrow_mut() = Some(defs.clone());
+        Ok(defs)
+    }
+
+    /// Get the OpenAPI definitions, but only if they're in memory or cached on disk at cache_path
+    pub fn cached_openapi_definitions(&self, cache_path: &Path) -> Option<Rc<Value>> {
+        if let Some(ref defs) = *self.openapi.borrow() {
+            return Some(defs.clone());
+        }
+        let defs: Value = serde_json::from_slice(&fs::read(cache_path).ok()?).ok()?;
+        let defs = Rc::new(defs);
+        *self.openapi.borrow_mut() = Some(defs.clone());
+        Some(defs)
+    }
+
     /// Get a serde_json::Value
     pub fn get_value(&self, path: &str) -> Result<Value, KubeError> {
-        let resp = self.send(path)?;
-        let resp = self.check_resp(resp)?;
+        let resp = self.send(Method::Get, path, None, None, None)?;
+        let resp = check_resp(resp)?;
+        serde_json::from_reader(resp).map_err(KubeError::from)
+    }
+
+    /// Get a list of resources rendered as a Table by the server (see ServerTable), which has the
+    /// same columns kubectl would show (including the additionalPrinterColumns of custom resources)
+    pub fn get_table(&self, path: &str) -> Result<Value, KubeError> {
+        let resp = self.send(
+            Method::Get,
+            path,
+            None,
+            None,
+            Some("application/json;as=Table;g=meta.k8s.io;v=v1"),
+        )?;
+        let resp = check_resp(resp)?;
+        serde_json::from_reader(resp).map_err(KubeError::from)
+    }
+
+    /// Send body as the body of a request with the specified method, and return the object the
+    /// server sends back
+    fn send_value(
+        &self,
+        method: Method,
+        path: &str,
+        body: &str,
+        content_type: &str,
+    ) -> Result<Value, KubeError> {
+        let resp = self.send(method, path, Some(body), Some(content_type), None)?;
+        let resp = check_resp(resp)?;
         serde_json::from_reader(resp).map_err(KubeError::from)
     }
 
+    /// Issue an HTTP PUT request to the specified path, replacing the object there with body.
+    /// Returns the updated object
+    pub fn put(&self, path: &str, body: &str) -> Result<Value, KubeError> {
+        self.send_value(Method::Put, path, body, "application/json")
+    }
+
+    /// Issue an HTTP POST request to the specified path, creating the object in body. Returns
+    /// the created object
+    pub fn post(&self, path: &str, body: &str) -> Result<Value, KubeError> {
+        self.send_value(Method::Post, path, body, "application/json")
+    }
+
+    /// Issue an HTTP PATCH request to the specified path.  patch_type specifies how the api
+    /// server should interpret body.  Returns the patched object
+    pub fn patch(&self, path: &str, body: &str, patch_type: PatchType) -> Result<Value, KubeError> {
+        self.send_value(Method::Patch, path, body, patch_type.content_type())
+    }
+
     /// Issue an HTTP DELETE request to the specified path
     pub fn delete(
         &self,
@@ -760,40 +1242,14 @@ impl Kluster {
         body: Option<&str>,
         retry: bool,
     ) -> Result<Response, KubeError> {
-        match self.inner_delete(path, body) {
-            Ok(resp) => Ok(resp),
-            Err(e) => match &e {
-                HyperError::Io(ref io_err) => {
-                    if retry && io_err.kind() == std::io::ErrorKind::ConnectionReset {
-                        self.create_new_client(&self.client_cert_key);
-                        self.inner_delete(path, body).map_err(KubeError::from)
-                    } else {
-                        Err(KubeError::from(e))
-                    }
-                }
-                _ => Err(KubeError::from(e)),
-            },
+        if retry {
+            self.send(Method::Delete, path, body, None, None)
+        } else {
+            self.send_req(Method::Delete, path, body, None, None)
+                .map_err(KubeError::from)
         }
     }
 
-    fn inner_delete(&self, path: &str, body: Option<&str>) -> Result<Response, HyperError> {
-        let url = self.endpoint.join(path)?;
-        if let Some(KlusterAuth::ExecProvider(ref exec_provider)) = self.auth {
-            self.handle_exec_provider(exec_provider);
-        }
-        let client = self.client.borrow();
-        let req = client.delete(url);
-        let req = match body {
-            Some(b) => {
-                let hyper_body = Body::BufBody(b.as_bytes(), b.len());
-                req.body(hyper_body)
-            }
-            None => req,
-        };
-        let req = self.add_auth_header(req);
-        req.send()
-    }
-
     /// Get all namespaces in this cluster
     pub fn namespaces_for_context(&self) -> Result<Vec<String>, KubeError> {
         let mut vec = Vec::new();
@@ -817,6 +1273,90 @@ fn print_token_err() {
 mod tests {
     use super::*;
 
+    #[test]
+    fn connector_refreshes_token() {
+        let config = serde_json::from_value(json!({"command": "aws"})).unwrap();
+        let provider = ExecProvider::new(config);
+        *provider.auth.borrow_mut() = Some(ExecAuth::Token("old-token".to_owned()));
+        *provider.expiry.borrow_mut() = Some(chrono::Local::now() + chrono::Duration::hours(1));
+        let kluster = Kluster::new(
+            "test",
+            None,
+            "https://kube.test:443",
+            Some(KlusterAuth::with_exec_provider(provider)),
+            None,
+            true,
+            10,
+            20,
+        )
+        .unwrap();
+        let connector = kluster.connector();
+        let token = |connector: &KlusterConnector| {
+            connector
+                .headers()
+                .get::<Authorization<Bearer>>()
+                .map(|auth| auth.token.clone())
+        };
+        assert_eq!(token(&connector), Some("old-token".to_owned()));
+
+        // once the token expires, connectors made before then get a new one
+        if let KlusterAuth::ExecProvider(ref provider) =
+            *kluster.auth.as_ref().unwrap().lock().unwrap()
+        {
+            *provider.expiry.borrow_mut() = Some(chrono::Local::now() - chrono::Duration::hours(1));
+        }
+        assert_eq!(token(&connector), Some("testtoken".to_owned()));
+    }
+
+    #[test]
+    fn sort_server_table() {
+        let table_json = r#"
+{
+  "kind": "Table",
+  "apiVersion": "meta.k8s.io/v1",
+  "columnDefinitions": [
+    {"name": "Name", "type": "string", "format": "name", "priority": 0},
+    {"name": "Replicas", "type": "integer", "priority": 0},
+    {"name": "Issued", "type": "date", "priority": 1},
+    {"name": "Age", "type": "string", "priority": 0}
+  ],
+  "rows": [
+    {
+      "cells": ["b", 10, "2021-01-02T00:00:00Z", "2d"],
+      "object": {"metadata": {"name": "b", "creationTimestamp": "2021-01-02T00:00:00Z"}}
+    },
+    {
+      "cells": ["c", 9, null, "3d"],
+      "object": {"metadata": {"name": "c", "creationTimestamp": "2021-01-01T00:00:00Z"}}
+    },
+    {
+      "cells": ["a", 100, "2021-01-03T00:00:00Z", "1d"],
+      "object": {"metadata": {"name": "a", "creationTimestamp": "2021-01-03T00:00:00Z"}}
+    }
+  ]
+}"#;
+        let mut table: ServerTable = serde_json::from_str(table_json).unwrap();
+        let names = |t: &ServerTable| -> Vec<String> {
+            t.rows
+                .iter()
+                .map(|r| table_cell_string(&r.cells[0]))
+                .collect()
+        };
+        assert_eq!(table.column_index("replicas"), Some(1));
+        assert_eq!(table.column_index("Status"), None);
+
+        table.sort_by_column(0);
+        assert_eq!(names(&table), vec!["a", "b", "c"]);
+        table.sort_by_column(1);
+        assert_eq!(names(&table), vec!["c", "b", "a"]);
+        table.sort_by_column(2);
+        assert_eq!(names(&table), vec!["c", "b", "a"]);
+        table.sort_by_column(0);
+        table.sort_by_column(3);
+        assert_eq!(names(&table), vec!["c", "b", "a"]);
+        assert_eq!(table_cell_string(&table.rows[0].cells[2]), "<none>");
+    }
+
     #[test]
     fn null_last_timestamp() {
         let event_list_json = r#"
@@ -0,0 +1,190 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Streaming the logs of a number of containers at once. Each stream is read in its own thread,
+//! and lines are printed as they arrive, prefixed with the pod/container they came from.
+
+use crate::kube::KlusterConnector;
+use crate::output::ClickWriter;
+
+use ansi_term::Colour::{self, Blue, Cyan, Green, Purple, Red, Yellow};
+use regex::Regex;
+
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// Colours used for the prefixes, in the order sources get them
+const PREFIX_COLOURS: [Colour; 6] = [Cyan, Green, Yellow, Blue, Purple, Red];
+
+/// A container to get logs from
+pub struct LogSource {
+    pub namespace: String,
+    pub pod: String,
+    pub container: String,
+}
+
+impl LogSource {
+    /// The url to get logs from, url_args are extra query parameters, each starting with &
+    fn url(&self, url_args: &str) -> String {
+        format!(
+            "/api/v1/namespaces/{}/pods/{}/log?container={}{}",
+            self.namespace, self.pod, self.container, url_args
+        )
+    }
+
+    fn prefix(&self) -> String {
+        format!("{}/{}", self.pod, self.container)
+    }
+}
+
+/// Filters applied to each line of logs as it's read. A line is kept if it matches grep (when
+/// there is one), and doesn't match exclude (when there is one)
+#[derive(Clone, Default)]
+pub struct LogFilter {
+    pub grep: Option<Regex>,
+    pub exclude: Option<Regex>,
+}
+
+impl LogFilter {
+    pub fn keep(&self, line: &str) -> bool {
+        let grepped = match self.grep {
+            Some(ref re) => re.is_match(line),
+            None => true,
+        };
+        let excluded = match self.exclude {
+            Some(ref re) => re.is_match(line),
+            None => false,
+        };
+        grepped && !excluded
+    }
+}
+
+/// What the reading threads send back. The usize is the index of the source
+enum LogEvent {
+    Line(usize, String),
+    Error(usize, String),
+}
+
+/// Get logs from all of sources at once, printing lines that filter keeps as they come in. Stops
+/// when all the streams have ended, or when ctrlcbool gets set. Threads that are blocked waiting
+/// for a line notice they've been stopped when they next get one.
+pub fn stream_logs(
+    connector: &KlusterConnector,
+    sources: &[LogSource],
+    url_args: &str,
+    timeout: Option<Duration>,
+    filter: &LogFilter,
+    ctrlcbool: &AtomicBool,
+    writer: &mut ClickWriter,
+) {
+    let (sender, receiver) = channel();
+    for (i, source) in sources.iter().enumerate() {
+        let sender = sender.clone();
+        let connector = connector.clone();
+        let url = source.url(url_args);
+        let filter = filter.clone();
+        thread::spawn(move || {
+            let mut reader = match connector.get_read(&url, timeout) {
+                Ok(resp) => BufReader::new(resp),
+                Err(e) => {
+                    sender.send(LogEvent::Error(i, e.to_string())).unwrap_or(());
+                    return;
+                }
+            };
+            loop {
+                let mut line = String::new();
+                match reader.read_line(&mut line) {
+                    Ok(0) => break,
+                    Ok(_) if !filter.keep(&line) => {}
+                    Ok(_) => {
+                        if sender.send(LogEvent::Line(i, line)).is_err() {
+                            // receiver is gone, so we've been stopped
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        sender.send(LogEvent::Error(i, e.to_string())).unwrap_or(());
+                        break;
+                    }
+                }
+            }
+        });
+    }
+    // only the threads hold senders now, so we see a disconnect once they've all finished
+    drop(sender);
+
+    let prefixes: Vec<String> = sources
+        .iter()
+        .enumerate()
+        .map(|(i, source)| {
+            PREFIX_COLOURS[i % PREFIX_COLOURS.len()]
+                .paint(source.prefix())
+                .to_string()
+        })
+        .collect();
+
+    ctrlcbool.store(false, Ordering::SeqCst);
+    while !ctrlcbool.load(Ordering::SeqCst) {
+        match receiver.recv_timeout(Duration::new(1, 0)) {
+            Ok(LogEvent::Line(i, line)) => {
+                clickwriteln!(writer, "{} {}", prefixes[i], line.trim_end_matches('\n'));
+            }
+            Ok(LogEvent::Error(i, e)) => {
+                clickwriteln!(writer, "{} Error getting logs: {}", prefixes[i], e);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_source() {
+        let source = LogSource {
+            namespace: "ns".to_owned(),
+            pod: "web-1".to_owned(),
+            container: "nginx".to_owned(),
+        };
+        assert_eq!(
+            source.url("&follow=true"),
+            "/api/v1/namespaces/ns/pods/web-1/log?container=nginx&follow=true"
+        );
+        assert_eq!(source.prefix(), "web-1/nginx");
+    }
+
+    #[test]
+    fn test_log_filter() {
+        let line = "GET /healthz 200";
+        assert!(LogFilter::default().keep(line));
+        let grep = LogFilter {
+            grep: Some(Regex::new("GET|POST").unwrap()),
+            exclude: None,
+        };
+        assert!(grep.keep(line));
+        assert!(!grep.keep("DELETE /things/1 404"));
+        let both = LogFilter {
+            exclude: Some(Regex::new("healthz").unwrap()),
+            ..grep
+        };
+        assert!(!both.keep(line));
+        assert!(both.keep("GET /things 200"));
+    }
+}
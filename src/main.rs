@@ -40,6 +40,7 @@ extern crate duct_sh;
 extern crate humantime;
 extern crate hyper;
 extern crate hyper_sync_rustls;
+extern crate libc;
 extern crate log;
 extern crate os_pipe;
 extern crate regex;
@@ -54,21 +55,36 @@ extern crate term;
 extern crate untrusted;
 extern crate webpki;
 
+mod apply;
+mod capacity;
 mod certs;
 mod cmd;
 mod command_processor;
+mod compare;
 mod completer;
 mod config;
 mod connector;
+mod cp;
 mod describe;
+mod diff;
+mod discovery;
+mod drain;
 mod env;
 mod error;
+mod exec;
+mod explain;
 mod kobj;
 mod kube;
+mod logs;
 mod parser;
+mod portforward;
 mod subjaltnames;
 mod table;
+mod tar;
+mod top;
 mod values;
+mod watch;
+mod websocket;
 
 #[cfg(test)]
 mod duct_mock;
@@ -135,11 +135,16 @@ impl ClickWriter {
         }
     }
 
+    /// Is this writing to stdout, rather than a file or pipe. Color is only used if so
+    pub fn is_stdout(&self) -> bool {
+        matches!(self.output, WriterOutput::Stdout(_))
+    }
+
     pub fn pretty_color_json<T: ?Sized>(&mut self, value: &T) -> Result<(), JsonError>
     where
         T: Serialize,
     {
-        if let WriterOutput::Stdout(_) = self.output {
+        if self.is_stdout() {
             let mut ser = Serializer::with_formatter(self, PrettyColorFormatter::new());
             value.serialize(&mut ser)
         } else {
@@ -148,6 +153,14 @@ impl ClickWriter {
         }
     }
 
+    /// Clear the terminal, so output can be redrawn in place. Does nothing if we're piping or
+    /// redirecting
+    pub fn clear_screen(&mut self) {
+        if let WriterOutput::Stdout(ref mut stdout) = self.output {
+            stdout.write_all(b"\x1b[2J\x1b[H").unwrap_or(());
+        }
+    }
+
     pub fn print_yaml<T: ?Sized>(&mut self, value: &T) -> Result<(), serde_yaml::Error>
     where
         T: Serialize,
@@ -0,0 +1,671 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Forwarding local ports to pods, using the kubernetes portforward subresource over a websocket.
+//!
+//! Each forwarded port gets a listener thread, and each connection to it gets a thread that opens
+//! a websocket to the pod and pumps data between it and the local socket. The websocket carries
+//! two channels per port: data, and errors. The first message from the server on each channel
+//! starts with the (little endian) port number, which we skip.
+//!
+//! Forwards can also be to a Service or Deployment, in which case a ready pod backing it is found
+//! when a connection comes in. If that pod goes away, the next connection finds a new one.
+
+use crate::env::PortForward;
+use crate::error::KubeError;
+use crate::kobj::{KObj, ObjType};
+use crate::kube::{Kluster, KlusterConnector, KlusterStream, Service, ServicePort};
+use crate::values::{label_selector, val_str, val_str_opt, val_u64};
+use crate::websocket::{Message, WebSocket};
+
+use serde_json::Value;
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const DATA_CHANNEL: u8 = 0;
+const ERROR_CHANNEL: u8 = 1;
+/// How often forwarding threads wake up to check if they should stop
+const STOP_CHECK_MS: i32 = 100;
+/// Reads on the websocket only happen once polling says there's data, or to pick up what might be
+/// left over from the last read, so they only wait this long
+const WS_DRAIN_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// A portforward websocket, and the fd of the socket under it
+type ForwardSocket = (WebSocket<KlusterStream>, RawFd);
+
+/// Stats for a port forward, kept up to date by the forwarding threads
+#[derive(Default)]
+pub struct PortForwardStats {
+    /// total number of connections handled
+    pub connections: AtomicUsize,
+    /// number of connections currently open
+    pub active: AtomicUsize,
+    /// bytes sent from the pod to local connections
+    pub bytes_in: AtomicU64,
+    /// bytes sent from local connections to the pod
+    pub bytes_out: AtomicU64,
+    /// the pod the most recent connection was forwarded to
+    pub pod: Mutex<Option<String>>,
+    /// the most recent error, if any
+    pub error: Mutex<Option<String>>,
+}
+
+/// The port to forward to on the remote side, either a number or the name of a port
+#[derive(Clone, Debug, PartialEq)]
+pub enum RemotePort {
+    Number(u16),
+    Name(String),
+}
+
+impl fmt::Display for RemotePort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RemotePort::Number(port) => write!(f, "{}", port),
+            RemotePort::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Parse a port specification like 5000, 8080:9090, 0:3456, :3456 or 8080:http into (local,
+/// remote) ports. A local port of 0 means pick a random one, as does only giving a port name
+pub fn parse_port_spec(spec: &str) -> Result<(u16, RemotePort), KubeError> {
+    let parse_local = |s: &str| {
+        s.parse::<u16>()
+            .map_err(|e| KubeError::ParseErr(format!("Invalid port '{}': {}", s, e)))
+    };
+    let (local, remote) = match spec.find(':') {
+        Some(pos) => {
+            let local = &spec[..pos];
+            let local = if local.is_empty() {
+                0
+            } else {
+                parse_local(local)?
+            };
+            (Some(local), &spec[(pos + 1)..])
+        }
+        None => (None, spec),
+    };
+    let remote = if remote.is_empty() || remote.chars().all(|c| c.is_ascii_digit()) {
+        match remote.parse::<u16>() {
+            Ok(0) => {
+                return Err(KubeError::ParseErr(format!(
+                    "Invalid port specification '{}': remote port can't be 0",
+                    spec
+                )))
+            }
+            Ok(port) => RemotePort::Number(port),
+            Err(e) => {
+                return Err(KubeError::ParseErr(format!(
+                    "Invalid port '{}': {}",
+                    remote, e
+                )))
+            }
+        }
+    } else {
+        RemotePort::Name(remote.to_string())
+    };
+    let local = match (local, &remote) {
+        (Some(local), _) => local,
+        (None, RemotePort::Number(port)) => *port,
+        (None, RemotePort::Name(_)) => 0,
+    };
+    Ok((local, remote))
+}
+
+/// Find the number of the port called name in any of the pod's containers
+fn named_pod_port(pod: &Value, name: &str) -> Option<u16> {
+    pod.pointer("/spec/containers")
+        .and_then(Value::as_array)?
+        .iter()
+        .filter_map(|cont| cont.get("ports").and_then(Value::as_array))
+        .flatten()
+        .find(|port| val_str("/name", port, "") == name)
+        .map(|port| val_u64("/containerPort", port, 0) as u16)
+}
+
+/// Is the pod running, ready, and not being deleted
+fn pod_is_ready(pod: &Value) -> bool {
+    pod.pointer("/metadata/deletionTimestamp").is_none()
+        && val_str("/status/phase", pod, "") == "Running"
+        && pod
+            .pointer("/status/conditions")
+            .and_then(Value::as_array)
+            .map(|conds| {
+                conds.iter().any(|cond| {
+                    val_str("/type", cond, "") == "Ready" && val_str("/status", cond, "") == "True"
+                })
+            })
+            .unwrap_or(false)
+}
+
+/// Find the service port that remote refers to, by number or by name
+fn find_service_port<'a>(ports: &'a [ServicePort], remote: &RemotePort) -> Option<&'a ServicePort> {
+    ports.iter().find(|sp| match remote {
+        RemotePort::Number(port) => sp.port == u32::from(*port),
+        RemotePort::Name(name) => sp.name.as_ref() == Some(name),
+    })
+}
+
+/// Find a ready pod in endpoints that serves the service port called port_name (which is None for
+/// services with a single unnamed port)
+fn endpoints_pod(endpoints: &Value, port_name: Option<&str>) -> Option<String> {
+    endpoints
+        .get("subsets")
+        .and_then(Value::as_array)?
+        .iter()
+        .filter(|subset| {
+            subset
+                .get("ports")
+                .and_then(Value::as_array)
+                .map(|ports| {
+                    ports
+                        .iter()
+                        .any(|port| val_str_opt("/name", port).as_deref() == port_name)
+                })
+                .unwrap_or(false)
+        })
+        .filter_map(|subset| subset.get("addresses").and_then(Value::as_array))
+        .flatten()
+        .find(|addr| val_str("/targetRef/kind", addr, "") == "Pod")
+        .and_then(|addr| val_str_opt("/targetRef/name", addr))
+}
+
+/// Finds the pod and port to forward connections for one remote port to. The last pod used is
+/// remembered, so we only need to look again when that pod goes away.
+struct Resolver {
+    connector: KlusterConnector,
+    namespace: String,
+    target: KObj,
+    url: String,
+    remote: RemotePort,
+    current: Mutex<Option<(String, u16)>>,
+}
+
+impl Resolver {
+    /// The port to use on pod, which is named by self.remote, or by target_port if that's set
+    fn pod_port(&self, pod: &str, target_port: Option<&RemotePort>) -> Result<u16, KubeError> {
+        match target_port.unwrap_or(&self.remote) {
+            RemotePort::Number(port) => Ok(*port),
+            RemotePort::Name(name) => {
+                let url = format!("/api/v1/namespaces/{}/pods/{}", self.namespace, pod);
+                let pod_val: Value = self.connector.get(&url)?;
+                named_pod_port(&pod_val, name).ok_or_else(|| {
+                    KubeError::ParseErr(format!("Pod {} has no port named {}", pod, name))
+                })
+            }
+        }
+    }
+
+    fn resolve_service(&self) -> Result<(String, u16), KubeError> {
+        let service: Service = self.connector.get(&self.url)?;
+        let ports = service.spec.ports.as_deref().unwrap_or(&[]);
+        let sport = find_service_port(ports, &self.remote).ok_or_else(|| {
+            KubeError::ParseErr(format!(
+                "Service {} has no port {}",
+                self.target.name, self.remote
+            ))
+        })?;
+        let url = format!(
+            "/api/v1/namespaces/{}/endpoints/{}",
+            self.namespace, self.target.name
+        );
+        let endpoints: Value = self.connector.get(&url)?;
+        let pod = endpoints_pod(&endpoints, sport.name.as_deref()).ok_or_else(|| {
+            KubeError::ParseErr(format!(
+                "No ready pods backing service {}",
+                self.target.name
+            ))
+        })?;
+        let target_port = match sport.target_pod {
+            Some(Value::Number(ref num)) => RemotePort::Number(num.as_u64().unwrap_or(0) as u16),
+            Some(Value::String(ref name)) => match name.parse() {
+                Ok(num) => RemotePort::Number(num),
+                Err(_) => RemotePort::Name(name.clone()),
+            },
+            // targetPort defaults to the same as port
+            _ => RemotePort::Number(sport.port as u16),
+        };
+        let port = self.pod_port(&pod, Some(&target_port))?;
+        Ok((pod, port))
+    }
+
+    fn resolve_deployment(&self) -> Result<(String, u16), KubeError> {
+        let dep: Value = self.connector.get(&self.url)?;
+        let selector = dep.pointer("/spec/selector").ok_or_else(|| {
+            KubeError::ParseErr(format!("Deployment {} has no selector", self.target.name))
+        })?;
+        let selector = label_selector(selector)?;
+        let url = format!(
+            "/api/v1/namespaces/{}/pods?labelSelector={}",
+            self.namespace, selector
+        );
+        let pods: Value = self.connector.get(&url)?;
+        let pod = pods
+            .get("items")
+            .and_then(Value::as_array)
+            .and_then(|items| items.iter().find(|pod| pod_is_ready(pod)))
+            .and_then(|pod| val_str_opt("/metadata/name", pod))
+            .ok_or_else(|| {
+                KubeError::ParseErr(format!("No ready pods for deployment {}", self.target.name))
+            })?;
+        let port = self.pod_port(&pod, None)?;
+        Ok((pod, port))
+    }
+
+    /// Look up which pod and port to use, ignoring any remembered one
+    fn resolve(&self) -> Result<(String, u16), KubeError> {
+        match self.target.typ {
+            ObjType::Service => self.resolve_service(),
+            ObjType::Deployment => self.resolve_deployment(),
+            _ => {
+                let port = self.pod_port(&self.target.name, None)?;
+                Ok((self.target.name.clone(), port))
+            }
+        }
+    }
+
+    /// Get the pod and port to use, looking them up if we don't have them already
+    fn get(&self) -> Result<(String, u16), KubeError> {
+        let mut current = self.current.lock().unwrap();
+        if current.is_none() {
+            *current = Some(self.resolve()?);
+        }
+        Ok(current.clone().unwrap())
+    }
+
+    /// Forget the remembered pod, if it's pod
+    fn forget(&self, pod: &str) {
+        let mut current = self.current.lock().unwrap();
+        if current.as_ref().map(|(cur, _)| cur == pod).unwrap_or(false) {
+            *current = None;
+        }
+    }
+
+    /// Open a portforward websocket to a pod. If the pod we were using can't be connected to,
+    /// and the target isn't a specific pod, look for a new one and try that instead
+    fn connect(&self, output: &Mutex<String>) -> Result<(String, ForwardSocket), KubeError> {
+        let (pod, port) = self.get()?;
+        match self.connect_to(&pod, port) {
+            Ok(ws) => Ok((pod, ws)),
+            Err(e) => {
+                self.forget(&pod);
+                if self.target.is_pod() {
+                    return Err(e);
+                }
+                let (new_pod, port) = self.get()?;
+                log_output(
+                    output,
+                    &format!(
+                        "Couldn't connect to pod {} ({}), now forwarding to pod {}",
+                        pod, e, new_pod
+                    ),
+                );
+                let ws = self.connect_to(&new_pod, port)?;
+                Ok((new_pod, ws))
+            }
+        }
+    }
+
+    fn connect_to(&self, pod: &str, port: u16) -> Result<ForwardSocket, KubeError> {
+        let path = format!(
+            "/api/v1/namespaces/{}/pods/{}/portforward",
+            self.namespace, pod
+        );
+        let port_str = port.to_string();
+        self.connector.connect_websocket_fd(
+            &path,
+            &[("ports", &port_str)],
+            &["v4.channel.k8s.io"],
+            WS_DRAIN_TIMEOUT,
+        )
+    }
+}
+
+fn log_output(output: &Mutex<String>, msg: &str) {
+    let mut output = output.lock().unwrap();
+    output.push_str(msg);
+    output.push('\n');
+}
+
+/// Wait for any of fds to be readable (or closed), for at most timeout_ms. Returns whether each
+/// one is.
+fn poll_readable<const N: usize>(fds: [RawFd; N], timeout_ms: i32) -> io::Result<[bool; N]> {
+    let mut pollfds = fds.map(|fd| libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    });
+    let ready = unsafe { libc::poll(pollfds.as_mut_ptr(), N as libc::nfds_t, timeout_ms) };
+    if ready < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::Interrupted {
+            return Ok([false; N]);
+        }
+        return Err(err);
+    }
+    Ok(pollfds.map(|pfd| pfd.revents != 0))
+}
+
+/// Pump data between stream and a portforward websocket, until one side closes. Both sockets are
+/// polled, and each is only read once there's something to read, so neither direction waits on
+/// the other.
+fn forward_connection(
+    mut stream: TcpStream,
+    (mut ws, ws_fd): ForwardSocket,
+    stats: &PortForwardStats,
+    stop: &AtomicBool,
+) -> Result<(), KubeError> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(None)?;
+
+    let mut seen_port = [false; 2];
+    let mut error = String::new();
+    let mut buf = [0; 16384];
+    // TLS can have data buffered that polling the socket won't tell us about, so after reading a
+    // message we keep reading until a read times out, before going back to polling
+    let mut ws_pending = true;
+    let res = loop {
+        if stop.load(Ordering::SeqCst) {
+            break Ok(());
+        }
+        let ready = if ws_pending {
+            poll_readable([stream.as_raw_fd()], 0).map(|[local]| (true, local))
+        } else {
+            poll_readable([ws_fd, stream.as_raw_fd()], STOP_CHECK_MS)
+                .map(|[remote, local]| (remote, local))
+        };
+        let (remote_ready, local_ready) = match ready {
+            Ok(ready) => ready,
+            Err(e) => break Err(e),
+        };
+        if remote_ready {
+            match ws.read_message() {
+                Ok(Some(Message::Data(data))) => {
+                    ws_pending = true;
+                    if let Some((&channel, mut rest)) = data.split_first() {
+                        if channel == DATA_CHANNEL || channel == ERROR_CHANNEL {
+                            if !seen_port[channel as usize] {
+                                seen_port[channel as usize] = true;
+                                rest = rest.get(2..).unwrap_or(&[]);
+                            }
+                            if channel == DATA_CHANNEL {
+                                if let Err(e) = stream.write_all(rest) {
+                                    break Err(e);
+                                }
+                                stats
+                                    .bytes_in
+                                    .fetch_add(rest.len() as u64, Ordering::SeqCst);
+                            } else {
+                                error.push_str(&String::from_utf8_lossy(rest));
+                            }
+                        }
+                    }
+                }
+                Ok(Some(Message::Close)) => break Ok(()),
+                Ok(None) => ws_pending = false,
+                Err(e) => break Err(e),
+            }
+        }
+        if local_ready {
+            match stream.read(&mut buf) {
+                Ok(0) => break Ok(()), // local side closed the connection
+                Ok(amt) => {
+                    let mut msg = Vec::with_capacity(amt + 1);
+                    msg.push(DATA_CHANNEL);
+                    msg.extend_from_slice(&buf[..amt]);
+                    if let Err(e) = ws.send_binary(&msg) {
+                        break Err(e);
+                    }
+                    stats.bytes_out.fetch_add(amt as u64, Ordering::SeqCst);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        }
+    };
+    ws.close();
+    if !error.is_empty() {
+        Err(KubeError::KubeServerError(error))
+    } else {
+        res.map_err(KubeError::from)
+    }
+}
+
+/// Handle one local connection, forwarding it to whatever pod resolver says
+fn handle_connection(
+    stream: TcpStream,
+    resolver: &Resolver,
+    output: &Mutex<String>,
+    stats: &PortForwardStats,
+    stop: &AtomicBool,
+) -> Result<(), KubeError> {
+    let (pod, ws) = resolver.connect(output)?;
+    *stats.pod.lock().unwrap() = Some(pod.clone());
+    let res = forward_connection(stream, ws, stats, stop);
+    if res.is_err() {
+        // the pod may have gone away, so check again for the next connection
+        resolver.forget(&pod);
+    }
+    res
+}
+
+/// Accept connections on listener, and forward each of them using resolver
+fn listen(
+    listener: TcpListener,
+    resolver: Arc<Resolver>,
+    output: Arc<Mutex<String>>,
+    stats: Arc<PortForwardStats>,
+    stop: Arc<AtomicBool>,
+) {
+    let local_port = listener.local_addr().map(|a| a.port()).unwrap_or(0);
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let resolver = resolver.clone();
+                let output = output.clone();
+                let stats = stats.clone();
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    log_output(&output, &format!("Handling connection for {}", local_port));
+                    stats.connections.fetch_add(1, Ordering::SeqCst);
+                    stats.active.fetch_add(1, Ordering::SeqCst);
+                    if let Err(e) = handle_connection(stream, &resolver, &output, &stats, &stop) {
+                        let msg = format!("error forwarding port {}: {}", resolver.remote, e);
+                        log_output(&output, &msg);
+                        *stats.error.lock().unwrap() = Some(msg);
+                    }
+                    stats.active.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(e) => {
+                let msg = format!("error listening on port {}: {}", local_port, e);
+                log_output(&output, &msg);
+                *stats.error.lock().unwrap() = Some(msg);
+                break;
+            }
+        }
+    }
+}
+
+/// Start forwarding the ports specified by port_specs (see parse_port_spec) to obj, which must be
+/// a Pod, Service or Deployment. All the local ports are bound before this returns, so errors like
+/// a port being in use are reported here
+pub fn start(
+    kluster: &Kluster,
+    namespace: &str,
+    obj: &KObj,
+    port_specs: &[&str],
+) -> Result<PortForward, KubeError> {
+    let target = match obj.typ {
+        ObjType::Pod { .. } => obj.name.clone(),
+        ObjType::Service | ObjType::Deployment => {
+            format!("{}/{}", obj.type_str().to_lowercase(), obj.name)
+        }
+        _ => {
+            return Err(KubeError::ParseErr(format!(
+                "Can't port-forward to a {}",
+                obj.type_str()
+            )))
+        }
+    };
+
+    let mut listeners = Vec::new();
+    let mut ports = Vec::new();
+    let output = Arc::new(Mutex::new(String::new()));
+    for spec in port_specs.iter() {
+        let (local, remote) = parse_port_spec(spec)?;
+        let listener = TcpListener::bind(("127.0.0.1", local)).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Unable to listen on port {}: {}", local, e),
+            )
+        })?;
+        listener.set_nonblocking(true)?;
+        let local = listener.local_addr()?.port();
+        log_output(
+            &output,
+            &format!("Forwarding from 127.0.0.1:{} -> {}", local, remote),
+        );
+        ports.push(format!("{}:{}", local, remote));
+        listeners.push((listener, remote));
+    }
+
+    // only start threads once everything is bound, so an error doesn't leave some running
+    let connector = kluster.connector();
+    let url = obj.url(kluster, namespace)?;
+    let stats = Arc::new(PortForwardStats::default());
+    let stop = Arc::new(AtomicBool::new(false));
+    for (listener, remote) in listeners.into_iter() {
+        let resolver = Arc::new(Resolver {
+            connector: connector.clone(),
+            namespace: namespace.to_string(),
+            target: obj.clone(),
+            url: url.clone(),
+            remote,
+            current: Mutex::new(None),
+        });
+        let output = output.clone();
+        let stats = stats.clone();
+        let stop = stop.clone();
+        thread::spawn(move || listen(listener, resolver, output, stats, stop));
+    }
+
+    Ok(PortForward {
+        target,
+        ports,
+        output,
+        stats,
+        stop,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port_spec() {
+        use super::RemotePort::{Name, Number};
+        assert_eq!(parse_port_spec("5000").unwrap(), (5000, Number(5000)));
+        assert_eq!(parse_port_spec("8080:9090").unwrap(), (8080, Number(9090)));
+        assert_eq!(parse_port_spec("0:3456").unwrap(), (0, Number(3456)));
+        assert_eq!(parse_port_spec(":3456").unwrap(), (0, Number(3456)));
+        assert_eq!(
+            parse_port_spec("5432:postgres").unwrap(),
+            (5432, Name("postgres".to_string()))
+        );
+        assert_eq!(
+            parse_port_spec("http").unwrap(),
+            (0, Name("http".to_string()))
+        );
+        assert!(parse_port_spec("70000").is_err());
+        assert!(parse_port_spec("5000:").is_err());
+        assert!(parse_port_spec("5000:0").is_err());
+        assert!(parse_port_spec("http:80").is_err());
+    }
+
+    #[test]
+    fn test_endpoints_pod() {
+        let endpoints = json!({
+            "subsets": [
+                {
+                    "addresses": [{"ip": "10.0.0.1", "targetRef": {"kind": "Pod", "name": "web-1"}}],
+                    "ports": [{"name": "http", "port": 8080}]
+                },
+                {
+                    "notReadyAddresses": [{"ip": "10.0.0.2", "targetRef": {"kind": "Pod", "name": "db-2"}}],
+                    "ports": [{"name": "db", "port": 5432}]
+                },
+                {
+                    "addresses": [{"ip": "10.0.0.3", "targetRef": {"kind": "Pod", "name": "db-3"}}],
+                    "ports": [{"name": "db", "port": 5432}]
+                }
+            ]
+        });
+        assert_eq!(
+            endpoints_pod(&endpoints, Some("http")),
+            Some("web-1".to_string())
+        );
+        assert_eq!(
+            endpoints_pod(&endpoints, Some("db")),
+            Some("db-3".to_string())
+        );
+        assert_eq!(endpoints_pod(&endpoints, None), None);
+        let unnamed = json!({
+            "subsets": [{
+                "addresses": [{"ip": "10.0.0.4", "targetRef": {"kind": "Pod", "name": "only"}}],
+                "ports": [{"port": 80}]
+            }]
+        });
+        assert_eq!(endpoints_pod(&unnamed, None), Some("only".to_string()));
+    }
+
+    #[test]
+    fn test_named_pod_port() {
+        let pod = json!({
+            "spec": {"containers": [
+                {"name": "sidecar"},
+                {"name": "main", "ports": [
+                    {"name": "http", "containerPort": 8080},
+                    {"name": "metrics", "containerPort": 9090}
+                ]}
+            ]}
+        });
+        assert_eq!(named_pod_port(&pod, "metrics"), Some(9090));
+        assert_eq!(named_pod_port(&pod, "grpc"), None);
+    }
+
+    #[test]
+    fn test_poll_readable() {
+        use std::os::unix::net::UnixStream;
+        let (mut a, b) = UnixStream::pair().unwrap();
+        let (c, d) = UnixStream::pair().unwrap();
+        let fds = [b.as_raw_fd(), c.as_raw_fd()];
+        assert_eq!(poll_readable(fds, 0).unwrap(), [false, false]);
+        a.write_all(b"x").unwrap();
+        assert_eq!(poll_readable(fds, 1000).unwrap(), [true, false]);
+        drop(d);
+        assert_eq!(poll_readable(fds, 1000).unwrap(), [true, true]);
+    }
+}
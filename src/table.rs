@@ -193,10 +193,32 @@ pub fn print_table<'a, T>(
     specs: &Vec<(T, Vec<CellSpec<'a>>)>,
     writer: &mut ClickWriter,
 ) {
+    print_table_from(table, specs, 0, writer);
+}
+
+/// Add a row to table for each of specs, with the first row getting index start
+#[allow(clippy::ptr_arg)]
+pub fn add_rows_from<'a, T>(table: &mut Table, specs: &Vec<(T, Vec<CellSpec<'a>>)>, start: usize) {
     for (index, t_spec) in specs.iter().enumerate() {
-        let row_vec: Vec<Cell> = t_spec.1.iter().map(|spec| spec.to_cell(index)).collect();
+        let row_vec: Vec<Cell> = t_spec
+            .1
+            .iter()
+            .map(|spec| spec.to_cell(start + index))
+            .collect();
         table.add_row(Row::new(row_vec));
     }
+}
+
+/// Like print_table, but the first row gets index start. This is used when printing a list page
+/// by page, so the numbering carries on from the previous page
+#[allow(clippy::ptr_arg)]
+pub fn print_table_from<'a, T>(
+    table: &mut Table,
+    specs: &Vec<(T, Vec<CellSpec<'a>>)>,
+    start: usize,
+    writer: &mut ClickWriter,
+) {
+    add_rows_from(table, specs, start);
     table.set_format(*TBLFMT);
     if !term_print_table(table, writer) {
         table.print(writer).unwrap_or(0);
@@ -0,0 +1,441 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Just enough of the tar format to copy files to and from containers. We write ustar archives,
+//! and read ustar, plus the GNU long name and pax path extensions that tar in a container will use
+//! for long paths.
+
+use std::io::{self, Read, Write};
+
+const BLOCK_SIZE: usize = 512;
+/// tar reads and writes in records of this many bytes, so archives get padded to a multiple
+const RECORD_SIZE: u64 = 10240;
+/// Extended headers (long names and pax attributes) are read into memory, so refuse ones bigger
+/// than this rather than trusting whatever size a corrupt archive claims
+const MAX_EXTENSION_SIZE: u64 = 1024 * 1024;
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Write n into field as a NUL terminated octal number, or in the base-256 encoding GNU tar uses
+/// if it doesn't fit
+fn write_number(field: &mut [u8], n: u64) {
+    let digits = field.len() - 1;
+    if digits < 22 && n >= 1 << (3 * digits) {
+        let mut n = n;
+        for byte in field.iter_mut().rev() {
+            *byte = (n & 0xff) as u8;
+            n >>= 8;
+        }
+        field[0] |= 0x80;
+    } else {
+        let octal = format!("{:0width$o}\0", n, width = digits);
+        field.copy_from_slice(octal.as_bytes());
+    }
+}
+
+fn read_number(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        // base-256
+        return Ok(field[1..]
+            .iter()
+            .fold(u64::from(field[0] & 0x7f), |n, b| (n << 8) | u64::from(*b)));
+    }
+    let s: String = field
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|b| *b as char)
+        .collect();
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(s, 8)
+        .map_err(|_| invalid_data(format!("Invalid number in tar header: {}", s)))
+}
+
+fn read_string(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn checksum(header: &[u8; BLOCK_SIZE]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            // the checksum field itself counts as spaces
+            if (148..156).contains(&i) {
+                u64::from(b' ')
+            } else {
+                u64::from(*b)
+            }
+        })
+        .sum()
+}
+
+/// Split path into the prefix and name fields of a ustar header
+fn split_path(path: &str) -> io::Result<(&str, &str)> {
+    if path.len() <= 100 {
+        return Ok(("", path));
+    }
+    for (pos, _) in path.match_indices('/') {
+        let (prefix, name) = (&path[..pos], &path[(pos + 1)..]);
+        if prefix.len() <= 155 && name.len() <= 100 && !name.is_empty() {
+            return Ok((prefix, name));
+        }
+    }
+    Err(invalid_data(format!("Path too long for tar: {}", path)))
+}
+
+fn header(path: &str, typeflag: u8, mode: u32, size: u64, mtime: u64) -> io::Result<[u8; 512]> {
+    let (prefix, name) = split_path(path)?;
+    let mut header = [0u8; BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_number(&mut header[100..108], u64::from(mode));
+    write_number(&mut header[108..116], 0); // uid
+    write_number(&mut header[116..124], 0); // gid
+    write_number(&mut header[124..136], size);
+    write_number(&mut header[136..148], mtime);
+    header[156] = typeflag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..(345 + prefix.len())].copy_from_slice(prefix.as_bytes());
+    let sum = format!("{:06o}\0 ", checksum(&header));
+    header[148..156].copy_from_slice(sum.as_bytes());
+    Ok(header)
+}
+
+fn padding(size: u64) -> usize {
+    (BLOCK_SIZE - (size % BLOCK_SIZE as u64) as usize) % BLOCK_SIZE
+}
+
+/// Writes a tar archive to an underlying writer
+pub struct TarWriter<W: Write> {
+    out: W,
+    written: u64,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(out: W) -> TarWriter<W> {
+        TarWriter { out, written: 0 }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.out.write_all(buf)?;
+        self.written += buf.len() as u64;
+        Ok(())
+    }
+
+    pub fn append_dir(&mut self, path: &str, mode: u32, mtime: u64) -> io::Result<()> {
+        let header = header(path, b'5', mode, 0, mtime)?;
+        self.write(&header)
+    }
+
+    /// Add a file with size bytes read from data. progress is called with the number of bytes
+    /// written after each chunk
+    pub fn append_file<R: Read>(
+        &mut self,
+        path: &str,
+        mode: u32,
+        mtime: u64,
+        size: u64,
+        data: R,
+        progress: &mut dyn FnMut(u64),
+    ) -> io::Result<()> {
+        let header = header(path, b'0', mode, size, mtime)?;
+        self.write(&header)?;
+        let mut data = data.take(size);
+        let mut buf = vec![0; 65536];
+        let mut copied = 0;
+        loop {
+            let amt = data.read(&mut buf)?;
+            if amt == 0 {
+                break;
+            }
+            self.write(&buf[..amt])?;
+            copied += amt as u64;
+            progress(amt as u64);
+        }
+        if copied != size {
+            return Err(invalid_data(format!(
+                "{} changed size while being read",
+                path
+            )));
+        }
+        self.write(&[0; BLOCK_SIZE][..padding(size)])
+    }
+
+    /// Write the end of archive marker and pad out the last record, returning the underlying
+    /// writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write(&[0; BLOCK_SIZE * 2])?;
+        let pad = (RECORD_SIZE - self.written % RECORD_SIZE) % RECORD_SIZE;
+        self.write(&vec![0; pad as usize])?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    /// anything else, like a link, with its type flag
+    Other(u8),
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    pub path: String,
+    pub kind: EntryKind,
+    pub mode: u32,
+    pub size: u64,
+}
+
+/// Reads the entries of a tar archive from an underlying reader
+pub struct TarReader<R: Read> {
+    inp: R,
+    /// bytes of the current entry's data (and padding) not read yet
+    pending: u64,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(inp: R) -> TarReader<R> {
+        TarReader { inp, pending: 0 }
+    }
+
+    fn skip_pending(&mut self) -> io::Result<()> {
+        let pending = self.pending;
+        self.pending = 0;
+        let skipped = io::copy(&mut (&mut self.inp).take(pending), &mut io::sink())?;
+        if skipped != pending {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    /// Read the data of an extended header entry of size bytes
+    fn read_extension(&mut self, size: u64) -> io::Result<Vec<u8>> {
+        if size > MAX_EXTENSION_SIZE {
+            return Err(invalid_data(format!(
+                "Extended header of {} bytes is too big",
+                size
+            )));
+        }
+        let mut data = vec![0; size as usize];
+        self.inp.read_exact(&mut data)?;
+        self.pending = padding(size) as u64;
+        self.skip_pending()?;
+        Ok(data)
+    }
+
+    /// The next entry, or None at the end of the archive. Any data of the previous entry that
+    /// wasn't read is skipped.
+    pub fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        self.skip_pending()?;
+        let mut long_path = None;
+        loop {
+            let mut header = [0u8; BLOCK_SIZE];
+            match self.inp.read_exact(&mut header) {
+                Ok(()) => {}
+                // some tars don't write the end of archive marker
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            if header.iter().all(|b| *b == 0) {
+                return Ok(None);
+            }
+            if read_number(&header[148..156])? != checksum(&header) {
+                return Err(invalid_data("Bad checksum in tar header".to_owned()));
+            }
+            let size = read_number(&header[124..136])?;
+            match header[156] {
+                b'L' => {
+                    // GNU long name, which is the path of the next entry
+                    let data = self.read_extension(size)?;
+                    long_path = Some(read_string(&data));
+                    continue;
+                }
+                b'x' => {
+                    // pax extended header, lines of "<len> <key>=<value>\n"
+                    let data = self.read_extension(size)?;
+                    for record in String::from_utf8_lossy(&data).lines() {
+                        if let Some(path) = record
+                            .split_once(' ')
+                            .and_then(|(_, kv)| kv.strip_prefix("path="))
+                        {
+                            long_path = Some(path.to_owned());
+                        }
+                    }
+                    continue;
+                }
+                b'g' => {
+                    // pax global header, nothing we care about
+                    self.read_extension(size)?;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let path = match long_path {
+                Some(path) => path,
+                None => {
+                    let name = read_string(&header[..100]);
+                    let prefix = read_string(&header[345..500]);
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{}/{}", prefix, name)
+                    } else {
+                        name
+                    }
+                }
+            };
+            let kind = match header[156] {
+                b'0' | b'\0' | b'7' => EntryKind::File,
+                b'5' => EntryKind::Dir,
+                flag => EntryKind::Other(flag),
+            };
+            // only files have data, whatever the size field says
+            self.pending = if kind == EntryKind::File {
+                size + padding(size) as u64
+            } else {
+                0
+            };
+            return Ok(Some(Entry {
+                path,
+                kind,
+                mode: read_number(&header[100..108])? as u32,
+                size: if kind == EntryKind::File { size } else { 0 },
+            }));
+        }
+    }
+
+    /// Copy the data of entry, the last one returned by next_entry, to out. progress is called
+    /// with the number of bytes copied after each chunk
+    pub fn copy_data(
+        &mut self,
+        entry: &Entry,
+        out: &mut dyn Write,
+        progress: &mut dyn FnMut(u64),
+    ) -> io::Result<()> {
+        let mut data = (&mut self.inp).take(entry.size);
+        let mut buf = vec![0; 65536];
+        let mut copied = 0;
+        loop {
+            let amt = data.read(&mut buf)?;
+            if amt == 0 {
+                break;
+            }
+            out.write_all(&buf[..amt])?;
+            copied += amt as u64;
+            progress(amt as u64);
+        }
+        if copied != entry.size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.pending = padding(entry.size) as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbers() {
+        let mut field = [0u8; 12];
+        write_number(&mut field, 1234);
+        assert_eq!(&field, b"00000002322\0");
+        assert_eq!(read_number(&field).unwrap(), 1234);
+
+        // too big for 11 octal digits
+        let big = 20 * 1024 * 1024 * 1024;
+        write_number(&mut field, big);
+        assert_eq!(field[0] & 0x80, 0x80);
+        assert_eq!(read_number(&field).unwrap(), big);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let long_dir = "d".repeat(120);
+        let mut writer = TarWriter::new(vec![]);
+        writer.append_dir("top", 0o755, 0).unwrap();
+        writer
+            .append_file("top/a.txt", 0o644, 0, 5, &b"hello"[..], &mut |_| {})
+            .unwrap();
+        writer
+            .append_file(
+                &format!("top/{}/b.txt", long_dir),
+                0o600,
+                0,
+                3,
+                &b"abc"[..],
+                &mut |_| {},
+            )
+            .unwrap();
+        let archive = writer.finish().unwrap();
+        assert_eq!(archive.len() as u64 % RECORD_SIZE, 0);
+
+        let mut reader = TarReader::new(&archive[..]);
+        let dir = reader.next_entry().unwrap().unwrap();
+        assert_eq!((dir.path.as_str(), dir.kind), ("top", EntryKind::Dir));
+        assert_eq!(dir.mode, 0o755);
+
+        let a = reader.next_entry().unwrap().unwrap();
+        assert_eq!(a.path, "top/a.txt");
+        let mut data = vec![];
+        reader.copy_data(&a, &mut data, &mut |_| {}).unwrap();
+        assert_eq!(data, b"hello");
+
+        // data of b isn't read, so gets skipped
+        let b = reader.next_entry().unwrap().unwrap();
+        assert_eq!(b.path, format!("top/{}/b.txt", long_dir));
+        assert_eq!((b.size, b.mode), (3, 0o600));
+        assert!(reader.next_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_gnu_long_name() {
+        let long_name = format!("{}.txt", "x".repeat(150));
+        let mut archive = vec![];
+        let mut long_header =
+            header("././@LongLink", b'L', 0, long_name.len() as u64 + 1, 0).unwrap();
+        // GNU tar doesn't set the ustar magic for these
+        long_header[257..265].copy_from_slice(b"ustar  \0");
+        let sum = format!("{:06o}\0 ", checksum(&long_header));
+        long_header[148..156].copy_from_slice(sum.as_bytes());
+        archive.extend_from_slice(&long_header);
+        let mut name_block = [0u8; BLOCK_SIZE];
+        name_block[..long_name.len()].copy_from_slice(long_name.as_bytes());
+        archive.extend_from_slice(&name_block);
+        archive.extend_from_slice(&header("truncated", b'0', 0o644, 0, 0).unwrap());
+        archive.extend_from_slice(&[0; BLOCK_SIZE * 2]);
+
+        let mut reader = TarReader::new(&archive[..]);
+        let entry = reader.next_entry().unwrap().unwrap();
+        assert_eq!(entry.path, long_name);
+        assert_eq!(entry.kind, EntryKind::File);
+        assert!(reader.next_entry().unwrap().is_none());
+
+        let mut huge = header("././@LongLink", b'L', 0, 1 << 62, 0)
+            .unwrap()
+            .to_vec();
+        huge.extend_from_slice(&[0; BLOCK_SIZE]);
+        let err = TarReader::new(&huge[..]).next_entry().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("too big"));
+    }
+}
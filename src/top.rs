@@ -0,0 +1,438 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Resource usage of pods and nodes. Usage comes from the metrics api (metrics.k8s.io), which is
+//! served by metrics-server, so this only works in clusters that run it.
+
+use crate::error::KubeError;
+use crate::kube::{Kluster, Metadata, Node, NodeList, Pod, PodList};
+use crate::output::ClickWriter;
+use crate::table::{opt_sort, CellSpec};
+use crate::values::Quantity;
+
+use prettytable::{format, Table};
+use regex::Regex;
+
+use std::collections::HashMap;
+
+const METRICS_API: &str = "/apis/metrics.k8s.io/v1beta1";
+
+#[derive(Debug, Deserialize)]
+struct ContainerMetrics {
+    usage: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PodMetrics {
+    metadata: Metadata,
+    containers: Vec<ContainerMetrics>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PodMetricsList {
+    items: Vec<PodMetrics>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeMetrics {
+    metadata: Metadata,
+    usage: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeMetricsList {
+    items: Vec<NodeMetrics>,
+}
+
+/// Amounts of cpu and memory. Either can be missing, like when no container in a pod sets a
+/// memory limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Amounts {
+    pub cpu: Option<Quantity>,
+    pub memory: Option<Quantity>,
+}
+
+impl Amounts {
+    /// The cpu and memory in a map of resource name to quantity
+    pub fn from_map(map: &HashMap<String, String>) -> Amounts {
+        Amounts {
+            cpu: map.get("cpu").and_then(|q| q.parse().ok()),
+            memory: map.get("memory").and_then(|q| q.parse().ok()),
+        }
+    }
+
+    /// Add other to these amounts. Missing amounts count as zero, unless they're missing from both
+    pub fn add(&mut self, other: &Amounts) {
+        let add = |a: Option<Quantity>, b: Option<Quantity>| match (a, b) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, None) => a,
+            (None, b) => b,
+        };
+        self.cpu = add(self.cpu, other.cpu);
+        self.memory = add(self.memory, other.memory);
+    }
+}
+
+/// Sum of the requests and limits of the containers in pod
+pub fn pod_requests_limits(pod: &Pod) -> (Amounts, Amounts) {
+    let mut requests = Amounts::default();
+    let mut limits = Amounts::default();
+    for cont in pod.spec.containers.iter() {
+        if let Some(ref resources) = cont.resources {
+            if let Some(ref reqs) = resources.requests {
+                requests.add(&Amounts::from_map(reqs));
+            }
+            if let Some(ref lims) = resources.limits {
+                limits.add(&Amounts::from_map(lims));
+            }
+        }
+    }
+    (requests, limits)
+}
+
+fn allocatable(node: &Node) -> Amounts {
+    match node.status.allocatable {
+        Some(ref alloc) => Amounts::from_map(alloc),
+        None => Amounts::default(),
+    }
+}
+
+/// used as a percent of total, if we know both
+fn percent(used: Option<Quantity>, total: Option<Quantity>) -> Option<i128> {
+    match (used, total) {
+        (Some(used), Some(total)) => used.percent_of(total),
+        _ => None,
+    }
+}
+
+fn percent_str(percent: Option<i128>) -> String {
+    match percent {
+        Some(p) => format!("{}%", p),
+        None => "-".to_owned(),
+    }
+}
+
+fn cpu_str(amount: Option<Quantity>) -> String {
+    match amount {
+        Some(a) => a.fmt_cpu(),
+        None => "-".to_owned(),
+    }
+}
+
+fn memory_str(amount: Option<Quantity>) -> String {
+    match amount {
+        Some(a) => a.fmt_memory(),
+        None => "-".to_owned(),
+    }
+}
+
+fn right(txt: String) -> CellSpec<'static> {
+    CellSpec::with_align_owned(txt, format::Alignment::RIGHT)
+}
+
+fn metrics_err(e: KubeError) -> KubeError {
+    match e.status_code() {
+        Some(404) => KubeError::NotServed("metrics.k8s.io (is metrics-server installed?)".into()),
+        _ => e,
+    }
+}
+
+/// A pod with its usage, and the allocatable resources of the node it's on
+pub struct PodUsage {
+    pub pod: Pod,
+    pub usage: Amounts,
+    pub requests: Amounts,
+    pub limits: Amounts,
+    pub node_allocatable: Amounts,
+}
+
+/// Get the usage of the pods in namespace (or all namespaces if None), limited to the ones on node
+/// and matching label_selector if they're set. Pods that have no metrics yet, like ones that just
+/// started, are left out.
+pub fn pod_usage(
+    kluster: &Kluster,
+    namespace: Option<&str>,
+    node: Option<&str>,
+    label_selector: Option<&str>,
+) -> Result<Vec<PodUsage>, KubeError> {
+    // the metrics api has the same paths as the core api, and takes label selectors, but not field
+    // selectors, so pods on other nodes are dropped when joining with the pod list
+    let mut path = match namespace {
+        Some(ns) => format!("/namespaces/{}/pods", ns),
+        None => "/pods".to_owned(),
+    };
+    if let Some(selector) = label_selector {
+        path.push_str("?labelSelector=");
+        path.push_str(selector);
+    }
+    let mut pods_url = format!("/api/v1{}", path);
+    if let Some(node) = node {
+        pods_url.push(if label_selector.is_some() { '&' } else { '?' });
+        pods_url.push_str("fieldSelector=spec.nodeName=");
+        pods_url.push_str(node);
+    }
+    let pods: PodList = kluster.get(&pods_url)?;
+    let metrics: PodMetricsList = kluster
+        .get(&format!("{}{}", METRICS_API, path))
+        .map_err(metrics_err)?;
+    let mut usages: HashMap<(Option<String>, String), Amounts> = HashMap::new();
+    for pm in metrics.items.into_iter() {
+        let mut usage = Amounts::default();
+        for cont in pm.containers.iter() {
+            usage.add(&Amounts::from_map(&cont.usage));
+        }
+        usages.insert((pm.metadata.namespace, pm.metadata.name), usage);
+    }
+    // reading nodes needs more permissions than reading pods, so we just leave out node percents
+    // if we can't
+    let nodes: HashMap<String, Amounts> = match kluster.get::<NodeList>("/api/v1/nodes") {
+        Ok(nodes) => nodes
+            .items
+            .iter()
+            .map(|node| (node.metadata.name.clone(), allocatable(node)))
+            .collect(),
+        Err(_) => HashMap::new(),
+    };
+
+    let mut result = vec![];
+    for pod in pods.items.into_iter() {
+        let key = (pod.metadata.namespace.clone(), pod.metadata.name.clone());
+        if let Some(usage) = usages.remove(&key) {
+            let (requests, limits) = pod_requests_limits(&pod);
+            let node_allocatable = pod
+                .spec
+                .node_name
+                .as_ref()
+                .and_then(|node| nodes.get(node))
+                .cloned()
+                .unwrap_or_default();
+            result.push(PodUsage {
+                pod,
+                usage,
+                requests,
+                limits,
+                node_allocatable,
+            });
+        }
+    }
+    Ok(result)
+}
+
+/// Print the usage of pods, sorted by sort, and filtered by regex. Returns the list of printed
+/// pods, so they can be selected by number.
+pub fn print_pod_usage(
+    mut usages: Vec<PodUsage>,
+    show_namespace: bool,
+    regex: Option<Regex>,
+    sort: Option<&str>,
+    reverse: bool,
+    writer: &mut ClickWriter,
+) -> PodList {
+    let mut table = Table::new();
+    let mut title_row = row![
+        "####",
+        "Name",
+        "CPU",
+        "CPU Req",
+        "CPU Lim",
+        "CPU %Req",
+        "CPU %Node",
+        "Memory",
+        "Mem Req",
+        "Mem Lim",
+        "Mem %Req",
+        "Mem %Node"
+    ];
+    let show_namespace = show_namespace || matches!(sort, Some("namespace") | Some("Namespace"));
+    if show_namespace {
+        title_row.add_cell(prettytable::Cell::new("Namespace"));
+    }
+    table.set_titles(title_row);
+
+    match sort {
+        Some("Name") | Some("name") => {
+            usages.sort_by(|u1, u2| u1.pod.metadata.name.cmp(&u2.pod.metadata.name))
+        }
+        Some("CPU") | Some("cpu") => {
+            usages.sort_by(|u1, u2| opt_sort(u1.usage.cpu, u2.usage.cpu, |c1, c2| c1.cmp(c2)))
+        }
+        Some("Memory") | Some("memory") => {
+            usages.sort_by(|u1, u2| opt_sort(u1.usage.memory, u2.usage.memory, |m1, m2| m1.cmp(m2)))
+        }
+        Some("Namespace") | Some("namespace") => {
+            usages.sort_by(|u1, u2| u1.pod.metadata.namespace.cmp(&u2.pod.metadata.namespace))
+        }
+        _ => {}
+    }
+    if reverse {
+        usages.reverse();
+    }
+
+    let usage_specs = usages.into_iter().map(|u| {
+        let mut specs = vec![
+            CellSpec::new_index(),
+            CellSpec::new_owned(u.pod.metadata.name.clone()),
+            right(cpu_str(u.usage.cpu)),
+            right(cpu_str(u.requests.cpu)),
+            right(cpu_str(u.limits.cpu)),
+            right(percent_str(percent(u.usage.cpu, u.requests.cpu))),
+            right(percent_str(percent(u.usage.cpu, u.node_allocatable.cpu))),
+            right(memory_str(u.usage.memory)),
+            right(memory_str(u.requests.memory)),
+            right(memory_str(u.limits.memory)),
+            right(percent_str(percent(u.usage.memory, u.requests.memory))),
+            right(percent_str(percent(
+                u.usage.memory,
+                u.node_allocatable.memory,
+            ))),
+        ];
+        if show_namespace {
+            specs.push(CellSpec::new_owned(
+                u.pod.metadata.namespace.clone().unwrap_or_default(),
+            ));
+        }
+        (u.pod, specs)
+    });
+
+    let filtered = match regex {
+        Some(r) => crate::table::filter(usage_specs, r),
+        None => usage_specs.collect(),
+    };
+    crate::table::print_table(&mut table, &filtered, writer);
+    PodList {
+        items: filtered.into_iter().map(|(pod, _)| pod).collect(),
+    }
+}
+
+/// Get and print the usage of nodes, sorted by sort and filtered by regex. If node is set, only
+/// that node is shown. Returns the list of printed nodes.
+pub fn print_node_usage(
+    kluster: &Kluster,
+    node: Option<&str>,
+    regex: Option<Regex>,
+    sort: Option<&str>,
+    reverse: bool,
+    writer: &mut ClickWriter,
+) -> Result<NodeList, KubeError> {
+    let (nodes, metrics) = match node {
+        Some(node) => {
+            let path = format!("/nodes/{}", node);
+            let node: Node = kluster.get(&format!("/api/v1{}", path))?;
+            let metrics: NodeMetrics = kluster
+                .get(&format!("{}{}", METRICS_API, path))
+                .map_err(metrics_err)?;
+            (vec![node], vec![metrics])
+        }
+        None => {
+            let nodes: NodeList = kluster.get("/api/v1/nodes")?;
+            let metrics: NodeMetricsList = kluster
+                .get(&format!("{}/nodes", METRICS_API))
+                .map_err(metrics_err)?;
+            (nodes.items, metrics.items)
+        }
+    };
+    let metrics: HashMap<String, Amounts> = metrics
+        .into_iter()
+        .map(|nm| (nm.metadata.name, Amounts::from_map(&nm.usage)))
+        .collect();
+    let mut usages: Vec<(Node, Amounts, Amounts)> = nodes
+        .into_iter()
+        .map(|node| {
+            let usage = metrics
+                .get(&node.metadata.name)
+                .cloned()
+                .unwrap_or_default();
+            let alloc = allocatable(&node);
+            (node, usage, alloc)
+        })
+        .collect();
+
+    match sort {
+        Some("Name") | Some("name") => {
+            usages.sort_by(|u1, u2| u1.0.metadata.name.cmp(&u2.0.metadata.name))
+        }
+        Some("CPU") | Some("cpu") => {
+            usages.sort_by(|u1, u2| opt_sort(u1.1.cpu, u2.1.cpu, |c1, c2| c1.cmp(c2)))
+        }
+        Some("Memory") | Some("memory") => {
+            usages.sort_by(|u1, u2| opt_sort(u1.1.memory, u2.1.memory, |m1, m2| m1.cmp(m2)))
+        }
+        _ => {}
+    }
+    if reverse {
+        usages.reverse();
+    }
+
+    let mut table = Table::new();
+    table.set_titles(row![
+        "####",
+        "Name",
+        "CPU",
+        "CPU Alloc",
+        "CPU %",
+        "Memory",
+        "Mem Alloc",
+        "Memory %"
+    ]);
+    let usage_specs = usages.into_iter().map(|(node, usage, alloc)| {
+        let specs = vec![
+            CellSpec::new_index(),
+            CellSpec::new_owned(node.metadata.name.clone()),
+            right(cpu_str(usage.cpu)),
+            right(cpu_str(alloc.cpu)),
+            right(percent_str(percent(usage.cpu, alloc.cpu))),
+            right(memory_str(usage.memory)),
+            right(memory_str(alloc.memory)),
+            right(percent_str(percent(usage.memory, alloc.memory))),
+        ];
+        (node, specs)
+    });
+    let filtered = match regex {
+        Some(r) => crate::table::filter(usage_specs, r),
+        None => usage_specs.collect(),
+    };
+    crate::table::print_table(&mut table, &filtered, writer);
+    Ok(NodeList {
+        items: filtered.into_iter().map(|(node, _)| node).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q(s: &str) -> Option<Quantity> {
+        s.parse().ok()
+    }
+
+    #[test]
+    fn test_amounts() {
+        let mut amounts = Amounts::default();
+        let mut map = HashMap::new();
+        map.insert("cpu".to_owned(), "100m".to_owned());
+        amounts.add(&Amounts::from_map(&map));
+        map.insert("memory".to_owned(), "1Mi".to_owned());
+        amounts.add(&Amounts::from_map(&map));
+        assert_eq!(
+            amounts,
+            Amounts {
+                cpu: q("200m"),
+                memory: q("1Mi"),
+            }
+        );
+        assert_eq!(percent(q("50m"), q("200m")), Some(25));
+        assert_eq!(percent(q("50m"), None), None);
+        assert_eq!(percent(q("50m"), q("0")), None);
+    }
+}
@@ -18,6 +18,9 @@ use serde_json::value::Value;
 use crate::error::KubeError;
 
 use std::borrow::Cow;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub};
+use std::str::FromStr;
 
 pub fn val_str<'a>(pointer: &str, value: &'a Value, default: &'a str) -> Cow<'a, str> {
     match value.pointer(pointer) {
@@ -73,3 +76,319 @@ where
         None => Err(KubeError::ParseErr("Can't deserialize".to_owned())),
     }
 }
+
+/// Turn a label selector object (like a deployment's spec.selector) into the form used by the
+/// labelSelector query parameter. Operators we don't know about are an error, as leaving them out
+/// would select more than was asked for.
+pub fn label_selector(selector: &Value) -> Result<String, KubeError> {
+    let mut reqs: Vec<String> = match selector.get("matchLabels").and_then(Value::as_object) {
+        Some(labels) => labels
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v.as_str().unwrap_or("")))
+            .collect(),
+        None => vec![],
+    };
+    let exprs = selector
+        .get("matchExpressions")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or(&[]);
+    for expr in exprs.iter() {
+        let key = val_str("/key", expr, "");
+        let values = || -> Vec<&str> {
+            expr.get("values")
+                .and_then(Value::as_array)
+                .map(|vals| vals.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default()
+        };
+        reqs.push(match val_str("/operator", expr, "").as_ref() {
+            "In" => format!("{} in ({})", key, values().join(",")),
+            "NotIn" => format!("{} notin ({})", key, values().join(",")),
+            "Exists" => key.into_owned(),
+            "DoesNotExist" => format!("!{}", key),
+            op => {
+                return Err(KubeError::ParseErr(format!(
+                    "Unknown label selector operator: {}",
+                    op
+                )))
+            }
+        });
+    }
+    Ok(reqs.join(","))
+}
+
+/// A Kubernetes resource quantity, like 250m, 1.5Gi or 2. It's stored as thousandths of the base
+/// unit (so millicores for cpu), which is exact for everything but the n and u suffixes. Those are
+/// rounded up, like the api server does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Quantity {
+    milli: i128,
+}
+
+const BINARY_SUFFIXES: [&str; 6] = ["Ki", "Mi", "Gi", "Ti", "Pi", "Ei"];
+const DECIMAL_SUFFIXES: [&str; 6] = ["k", "M", "G", "T", "P", "E"];
+
+/// The whole number and up to three decimal places of milli thousandths, without trailing zeros
+fn decimal_str(milli: i128) -> String {
+    let units = format!("{}.{:03}", milli / 1000, milli % 1000);
+    units.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
+
+impl Quantity {
+    /// What percent of total this is, or None if total is zero
+    pub fn percent_of(&self, total: Quantity) -> Option<i128> {
+        (self.milli * 100).checked_div(total.milli)
+    }
+
+    fn sign(&self) -> &'static str {
+        if self.milli < 0 {
+            "-"
+        } else {
+            ""
+        }
+    }
+
+    /// Show an amount of cpu, or any other resource that's counted, like pods: in milli units if
+    /// it's less than one (250m), and as a decimal otherwise (1.5, 2048)
+    pub fn fmt_cpu(&self) -> String {
+        let milli = self.milli.abs();
+        if milli < 1000 {
+            format!("{}{}m", self.sign(), milli)
+        } else {
+            format!("{}{}", self.sign(), decimal_str(milli))
+        }
+    }
+
+    /// Show an amount of bytes, like memory or storage. Amounts that are a whole number of the
+    /// largest binary or decimal unit that fits are shown exactly (64Mi, 1G), and others in the
+    /// largest binary unit, to one decimal place (3.8Gi)
+    pub fn fmt_memory(&self) -> String {
+        let milli = self.milli.abs();
+        if milli < 1024 * 1000 {
+            return format!("{}{}", self.sign(), decimal_str(milli));
+        }
+        // the largest unit of each kind that's no bigger than milli
+        let largest = |base: i128, suffixes: &[&'static str; 6]| {
+            let mut unit = (1000, "");
+            for (idx, suffix) in suffixes.iter().enumerate() {
+                let size = 1000 * base.pow(idx as u32 + 1);
+                if size > milli {
+                    break;
+                }
+                unit = (size, *suffix);
+            }
+            unit
+        };
+        let (binary_size, binary_suffix) = largest(1024, &BINARY_SUFFIXES);
+        let (decimal_size, decimal_suffix) = largest(1000, &DECIMAL_SUFFIXES);
+        if milli % binary_size == 0 {
+            format!("{}{}{}", self.sign(), milli / binary_size, binary_suffix)
+        } else if milli % decimal_size == 0 {
+            format!("{}{}{}", self.sign(), milli / decimal_size, decimal_suffix)
+        } else {
+            let amount = format!("{:.1}", milli as f64 / binary_size as f64);
+            format!(
+                "{}{}{}",
+                self.sign(),
+                amount.trim_end_matches(".0"),
+                binary_suffix
+            )
+        }
+    }
+
+    /// Show an amount of resource, using fmt_memory for things measured in bytes, and fmt_cpu
+    /// for everything else
+    pub fn fmt_resource(&self, resource: &str) -> String {
+        match resource {
+            "memory" | "storage" | "ephemeral-storage" => self.fmt_memory(),
+            _ if resource.starts_with("hugepages-") => self.fmt_memory(),
+            _ => self.fmt_cpu(),
+        }
+    }
+}
+
+impl FromStr for Quantity {
+    type Err = KubeError;
+
+    /// Parse a quantity: a number with an optional binary (Ki, Mi, ...) or decimal (n, u, m, k, M,
+    /// ...) suffix, or a decimal exponent (1e3)
+    fn from_str(s: &str) -> Result<Quantity, KubeError> {
+        let err = || KubeError::ParseErr(format!("Invalid quantity: {}", s));
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '+' || c == '-'))
+            .unwrap_or(s.len());
+        let (num, suffix) = s.split_at(split);
+        let (negative, num) = match num.strip_prefix('-') {
+            Some(num) => (true, num),
+            None => (false, num.strip_prefix('+').unwrap_or(num)),
+        };
+        let (whole, frac) = num.split_once('.').unwrap_or((num, ""));
+        if (whole.is_empty() && frac.is_empty())
+            || !whole
+                .chars()
+                .chain(frac.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(err());
+        }
+        // the number is mantissa / 10^frac.len()
+        let mantissa: i128 = format!("{}{}", whole, frac).parse().map_err(|_| err())?;
+
+        let (binary_exp, decimal_exp): (u32, i32) = match suffix {
+            "" => (0, 0),
+            "n" => (0, -9),
+            "u" => (0, -6),
+            "m" => (0, -3),
+            _ => match BINARY_SUFFIXES.iter().position(|b| *b == suffix) {
+                Some(pos) => (10 * (pos as u32 + 1), 0),
+                None => match DECIMAL_SUFFIXES.iter().position(|d| *d == suffix) {
+                    Some(pos) => (0, 3 * (pos as i32 + 1)),
+                    None => match suffix.strip_prefix(|c| c == 'e' || c == 'E') {
+                        Some(exp) => (0, exp.parse().map_err(|_| err())?),
+                        None => return Err(err()),
+                    },
+                },
+            },
+        };
+        let mut milli = mantissa.checked_mul(1 << binary_exp).ok_or_else(err)?;
+        let scale = decimal_exp + 3 - frac.len() as i32;
+        if scale >= 0 {
+            let mult = 10i128.checked_pow(scale as u32).ok_or_else(err)?;
+            milli = milli.checked_mul(mult).ok_or_else(err)?;
+        } else {
+            let div = 10i128.checked_pow(-scale as u32).ok_or_else(err)?;
+            milli = (milli + div - 1) / div; // round up
+        }
+        Ok(Quantity {
+            milli: if negative { -milli } else { milli },
+        })
+    }
+}
+
+impl Add for Quantity {
+    type Output = Quantity;
+
+    fn add(self, other: Quantity) -> Quantity {
+        Quantity {
+            milli: self.milli + other.milli,
+        }
+    }
+}
+
+impl AddAssign for Quantity {
+    fn add_assign(&mut self, other: Quantity) {
+        self.milli += other.milli;
+    }
+}
+
+impl Sub for Quantity {
+    type Output = Quantity;
+
+    fn sub(self, other: Quantity) -> Quantity {
+        Quantity {
+            milli: self.milli - other.milli,
+        }
+    }
+}
+
+impl Sum for Quantity {
+    fn sum<I: Iterator<Item = Quantity>>(iter: I) -> Quantity {
+        iter.fold(Quantity::default(), Add::add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q(s: &str) -> Quantity {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_label_selector() {
+        let sel = |v: Value| label_selector(&v).unwrap();
+        assert_eq!(sel(json!({})), "");
+        assert_eq!(sel(Value::Null), "");
+        assert_eq!(
+            sel(json!({"matchLabels": {"app": "web", "tier": "front"}})),
+            "app=web,tier=front"
+        );
+        assert_eq!(
+            sel(json!({
+                "matchLabels": {"app": "web"},
+                "matchExpressions": [
+                    {"key": "env", "operator": "In", "values": ["prod", "staging"]},
+                    {"key": "track", "operator": "NotIn", "values": ["canary"]},
+                    {"key": "owner", "operator": "Exists"},
+                    {"key": "legacy", "operator": "DoesNotExist", "values": []},
+                ],
+            })),
+            "app=web,env in (prod,staging),track notin (canary),owner,!legacy"
+        );
+        assert!(label_selector(&json!({
+            "matchExpressions": [{"key": "env", "operator": "Gt", "values": ["1"]}],
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_quantity() {
+        assert_eq!(q("250m").milli, 250);
+        assert_eq!(q("2").milli, 2000);
+        assert_eq!(q("0.5").milli, 500);
+        assert_eq!(q("1.5Gi").milli, 1536 * 1024 * 1024 * 1000);
+        assert_eq!(q("64Mi").milli, 64 * 1024 * 1024 * 1000);
+        assert_eq!(q("1G").milli, 1_000_000_000_000);
+        assert_eq!(q("1e3").milli, 1_000_000);
+        assert_eq!(q("2E").milli, 2_000_000_000_000_000_000_000);
+        assert_eq!(q("123456789n").milli, 124);
+        assert_eq!(q("-100m").milli, -100);
+        assert_eq!(q("2Ei").milli, (2i128 << 60) * 1000);
+        for bad in ["", "m", "1.2.3", "12Qi", "Gi", "1e"].iter() {
+            assert!(bad.parse::<Quantity>().is_err(), "{} parsed", bad);
+        }
+        // 2^68 Ei and 2^78 Pi are both 2^128, which would wrap around to 0 if shifted
+        for big in ["295147905179352825856Ei", "302231454903657293676544Pi"].iter() {
+            assert!(big.parse::<Quantity>().is_err(), "{} parsed", big);
+        }
+    }
+
+    #[test]
+    fn test_quantity_ops() {
+        assert!(q("900Mi") < q("1Gi"));
+        assert!(q("1500m") > q("1"));
+        assert_eq!(q("500m") + q("1"), q("1.5"));
+        assert_eq!(q("1Gi") - q("512Mi"), q("512Mi"));
+        assert_eq!(
+            vec![q("1"), q("2"), q("3")].into_iter().sum::<Quantity>(),
+            q("6")
+        );
+        assert_eq!(q("250m").percent_of(q("1")), Some(25));
+        assert_eq!(q("1").percent_of(Quantity::default()), None);
+    }
+
+    #[test]
+    fn test_fmt_quantity() {
+        assert_eq!(q("250m").fmt_cpu(), "250m");
+        assert_eq!(q("1500m").fmt_cpu(), "1.5");
+        assert_eq!(q("4").fmt_cpu(), "4");
+        assert_eq!(q("110").fmt_cpu(), "110");
+        // like the total of a big cluster
+        let total: Quantity = (0..40).map(|_| q("64")).sum();
+        assert_eq!(total.fmt_cpu(), "2560");
+        assert_eq!(q("1500").fmt_cpu(), "1500");
+        assert_eq!(q("-250m").fmt_cpu(), "-250m");
+
+        assert_eq!(q("512").fmt_memory(), "512");
+        assert_eq!(q("64Mi").fmt_memory(), "64Mi");
+        assert_eq!(q("1536Mi").fmt_memory(), "1.5Gi");
+        assert_eq!(q("3967852Ki").fmt_memory(), "3.8Gi");
+        assert_eq!(q("1G").fmt_memory(), "1G");
+        assert_eq!(q("500M").fmt_memory(), "500M");
+        assert_eq!(q("-2Gi").fmt_memory(), "-2Gi");
+
+        assert_eq!(q("2Gi").fmt_resource("ephemeral-storage"), "2Gi");
+        assert_eq!(q("2048").fmt_resource("cpu"), "2048");
+    }
+}
@@ -0,0 +1,252 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Watching lists of objects for changes, and redrawing them as changes arrive
+
+use crate::env::Env;
+use crate::error::KubeError;
+use crate::output::ClickWriter;
+use crate::values::{val_str, val_u64};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// An event sent by the api server on a watch stream
+#[derive(Debug, Deserialize)]
+pub struct WatchEvent {
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub object: Value,
+}
+
+/// The current state of a list of objects that is being watched
+pub struct WatchedList {
+    items: Vec<Value>,
+    resource_version: String,
+}
+
+impl WatchedList {
+    /// Create from a list returned by the api server
+    pub fn new(mut list: Value) -> WatchedList {
+        let resource_version = val_str("/metadata/resourceVersion", &list, "").into_owned();
+        let items = match list.get_mut("items").map(Value::take) {
+            Some(Value::Array(items)) => items,
+            _ => vec![],
+        };
+        WatchedList {
+            items,
+            resource_version,
+        }
+    }
+
+    /// Update the list with the change in event. Returns true if the list changed
+    pub fn apply(&mut self, event: WatchEvent) -> Result<bool, KubeError> {
+        if event.typ == "ERROR" {
+            return Err(KubeError::KubeServerError(
+                val_str("/message", &event.object, "Unknown error").into_owned(),
+            ));
+        }
+        if let Some(rv) = event.object.pointer("/metadata/resourceVersion") {
+            if let Some(rv) = rv.as_str() {
+                self.resource_version = rv.to_owned();
+            }
+        }
+        let uid = val_str("/metadata/uid", &event.object, "").into_owned();
+        let pos = self
+            .items
+            .iter()
+            .position(|item| val_str("/metadata/uid", item, "") == uid);
+        match event.typ.as_str() {
+            "ADDED" | "MODIFIED" => {
+                match pos {
+                    Some(pos) => self.items[pos] = event.object,
+                    None => self.items.push(event.object),
+                }
+                Ok(true)
+            }
+            "DELETED" => match pos {
+                Some(pos) => {
+                    self.items.remove(pos);
+                    Ok(true)
+                }
+                None => Ok(false),
+            },
+            _ => Ok(false), // BOOKMARK, or something we don't know about
+        }
+    }
+
+    /// Convert into one of the list types (PodList, NodeList, ...)
+    pub fn to_list<T: DeserializeOwned>(&self) -> Result<T, KubeError> {
+        serde_json::from_value(json!({ "items": self.items })).map_err(KubeError::from)
+    }
+}
+
+// Read lines from reader in a thread, and send them back over the returned channel
+fn read_lines<R: BufRead + Send + 'static>(mut reader: R) -> Receiver<String> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(amt) if amt > 0 => {
+                if sender.send(line).is_err() {
+                    // probably user hit ctrl-c, just stop
+                    break;
+                }
+            }
+            _ => break,
+        }
+    });
+    receiver
+}
+
+/// Fetch and print the list at url using print_list, then watch it for changes, redrawing the
+/// list each time one comes in. Stops on Ctrl-C, and returns the list as it was last printed.
+pub fn watch_list<T, F>(
+    env: &Env,
+    url: &str,
+    writer: &mut ClickWriter,
+    mut print_list: F,
+) -> Option<T>
+where
+    T: DeserializeOwned,
+    F: FnMut(T, usize, &mut ClickWriter) -> T,
+{
+    let mut watched = WatchedList::new(env.run_on_kluster(|k| k.get_value(url))?);
+    let mut printed = match watched.to_list() {
+        Ok(list) => {
+            writer.clear_screen();
+            print_list(list, 0, writer)
+        }
+        Err(e) => {
+            clickwriteln!(writer, "Could not decode list: {}", e);
+            return None;
+        }
+    };
+    let sep = if url.contains('?') { '&' } else { '?' };
+
+    env.ctrlcbool.store(false, Ordering::SeqCst);
+    'watch: while !env.ctrlcbool.load(Ordering::SeqCst) {
+        let watch_url = format!(
+            "{}{}watch=true&resourceVersion={}",
+            url, sep, watched.resource_version
+        );
+        let receiver = match env.run_on_kluster(|k| k.get_read(watch_url.as_str(), None, true)) {
+            Some(resp) => read_lines(BufReader::new(resp)),
+            None => break,
+        };
+        while !env.ctrlcbool.load(Ordering::SeqCst) {
+            let mut lines = match receiver.recv_timeout(Duration::new(1, 0)) {
+                Ok(line) => vec![line],
+                Err(RecvTimeoutError::Timeout) => continue,
+                // server closed the watch (they time out), start a new one
+                Err(RecvTimeoutError::Disconnected) => continue 'watch,
+            };
+            // grab anything else that's ready so a burst of changes is only drawn once
+            lines.extend(receiver.try_iter());
+
+            let mut changed = false;
+            let mut relist = false;
+            for line in lines {
+                let event: WatchEvent = match serde_json::from_str(&line) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        clickwriteln!(writer, "Could not parse watch event: {}", e);
+                        break 'watch;
+                    }
+                };
+                if event.typ == "ERROR" && val_u64("/code", &event.object, 0) == 410 {
+                    // our resourceVersion is too old, need to start over
+                    match env.run_on_kluster(|k| k.get_value(url)) {
+                        Some(list) => watched = WatchedList::new(list),
+                        None => break 'watch,
+                    }
+                    relist = true;
+                    break;
+                }
+                match watched.apply(event) {
+                    Ok(c) => changed |= c,
+                    Err(e) => {
+                        clickwriteln!(writer, "Watch failed: {}", e);
+                        break 'watch;
+                    }
+                }
+            }
+            if changed || relist {
+                match watched.to_list() {
+                    Ok(list) => {
+                        writer.clear_screen();
+                        printed = print_list(list, 0, writer);
+                    }
+                    Err(e) => {
+                        clickwriteln!(writer, "Could not decode watched list: {}", e);
+                        break 'watch;
+                    }
+                }
+            }
+            if relist {
+                continue 'watch;
+            }
+        }
+    }
+    Some(printed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(typ: &str, uid: &str, rv: &str) -> WatchEvent {
+        WatchEvent {
+            typ: typ.to_string(),
+            object: json!({"metadata": {"name": uid, "uid": uid, "resourceVersion": rv}}),
+        }
+    }
+
+    #[test]
+    fn test_watched_list_apply() {
+        let mut watched = WatchedList::new(json!({
+            "metadata": {"resourceVersion": "1"},
+            "items": [{"metadata": {"name": "a", "uid": "a"}}]
+        }));
+        assert_eq!(watched.resource_version, "1");
+        assert!(watched.apply(event("ADDED", "b", "2")).unwrap());
+        assert!(watched.apply(event("MODIFIED", "a", "3")).unwrap());
+        assert_eq!(watched.items.len(), 2);
+        assert_eq!(
+            val_str("/metadata/resourceVersion", &watched.items[0], ""),
+            "3"
+        );
+        assert!(watched.apply(event("DELETED", "a", "4")).unwrap());
+        assert!(!watched.apply(event("DELETED", "a", "5")).unwrap());
+        assert!(!watched.apply(event("BOOKMARK", "", "6")).unwrap());
+        assert_eq!(watched.items.len(), 1);
+        assert_eq!(watched.resource_version, "6");
+    }
+
+    #[test]
+    fn test_watched_list_error() {
+        let mut watched = WatchedList::new(json!({"items": []}));
+        let err = WatchEvent {
+            typ: "ERROR".to_string(),
+            object: json!({"code": 500, "message": "oh no"}),
+        };
+        assert!(watched.apply(err).is_err());
+    }
+}
@@ -0,0 +1,383 @@
+// Copyright 2017 Databricks, Inc.
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A minimal websocket client, enough to speak the kubernetes streaming protocols (exec,
+//! port-forward) over a connection made by a Kluster.
+//!
+//! The TLS streams we get from hyper hold a lock while reading, so a websocket can't be read from
+//! one thread while being written to by another. Instead the stream should be given a short read
+//! timeout, and a single thread should alternate between calling `read_message` (which returns
+//! `None` when the timeout is hit) and sending whatever it has to send.
+
+use crate::error::KubeError;
+
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::Value;
+
+use std::io::{self, Read, Write};
+
+static WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC11B65";
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// A message received over a websocket
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Data(Vec<u8>),
+    Close,
+}
+
+#[derive(Debug, PartialEq)]
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Try and parse a frame from the start of buf. Returns the frame and the number of bytes it used,
+/// or None if buf doesn't hold a complete frame yet
+fn parse_frame(buf: &[u8]) -> io::Result<Option<(Frame, usize)>> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let fin = buf[0] & 0x80 != 0;
+    let opcode = buf[0] & 0x0F;
+    let masked = buf[1] & 0x80 != 0;
+    let (len, mut pos) = match buf[1] & 0x7F {
+        126 => {
+            if buf.len() < 4 {
+                return Ok(None);
+            }
+            (u64::from(u16::from_be_bytes([buf[2], buf[3]])), 4)
+        }
+        127 => {
+            if buf.len() < 10 {
+                return Ok(None);
+            }
+            let mut len_bytes = [0; 8];
+            len_bytes.copy_from_slice(&buf[2..10]);
+            (u64::from_be_bytes(len_bytes), 10)
+        }
+        len => (u64::from(len), 2),
+    };
+    if len > (isize::MAX as u64) {
+        return Err(invalid_data("Websocket frame too large"));
+    }
+    let len = len as usize;
+    let mask = if masked {
+        if buf.len() < pos + 4 {
+            return Ok(None);
+        }
+        let mut mask = [0; 4];
+        mask.copy_from_slice(&buf[pos..(pos + 4)]);
+        pos += 4;
+        Some(mask)
+    } else {
+        None
+    };
+    if buf.len() < pos + len {
+        return Ok(None);
+    }
+    let mut payload = buf[pos..(pos + len)].to_vec();
+    if let Some(mask) = mask {
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+    }
+    Ok(Some((
+        Frame {
+            fin,
+            opcode,
+            payload,
+        },
+        pos + len,
+    )))
+}
+
+/// Encode a single (final) frame. Frames from a client must always be masked
+fn encode_frame(opcode: u8, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode);
+    if payload.len() < 126 {
+        frame.push(0x80 | payload.len() as u8);
+    } else if payload.len() <= u16::MAX as usize {
+        frame.push(0x80 | 126);
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    } else {
+        frame.push(0x80 | 127);
+        frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    frame
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+}
+
+pub struct WebSocket<S: Read + Write> {
+    stream: S,
+    rand: SystemRandom,
+    /// bytes read from the stream that aren't part of a complete frame yet
+    buf: Vec<u8>,
+    /// payload of a fragmented message we're in the middle of receiving
+    fragments: Vec<u8>,
+    /// the subprotocol the server picked, if any
+    protocol: Option<String>,
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    /// Send a websocket handshake over stream. headers should contain any extra headers (like
+    /// auth) to send, each terminated with \r\n
+    pub fn handshake(
+        mut stream: S,
+        host: &str,
+        path_and_query: &str,
+        headers: &str,
+        protocols: &[&str],
+    ) -> Result<WebSocket<S>, KubeError> {
+        let rand = SystemRandom::new();
+        let mut key_bytes = [0; 16];
+        rand.fill(&mut key_bytes)
+            .map_err(|_| KubeError::ParseErr("Could not generate websocket key".to_string()))?;
+        let key = base64::encode(&key_bytes);
+        let req = format!(
+            "GET {} HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\n\
+             Sec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Protocol: {}\r\n\
+             {}\r\n",
+            path_and_query,
+            host,
+            key,
+            protocols.join(","),
+            headers
+        );
+        stream.write_all(req.as_bytes())?;
+        stream.flush()?;
+
+        // read the response headers
+        let mut buf = Vec::new();
+        let mut chunk = [0; 4096];
+        let header_end = loop {
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            let amt = stream.read(&mut chunk)?;
+            if amt == 0 {
+                return Err(KubeError::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Connection closed during websocket handshake",
+                )));
+            }
+            buf.extend_from_slice(&chunk[..amt]);
+        };
+        let rest = buf.split_off(header_end);
+        let head = String::from_utf8_lossy(&buf).into_owned();
+        let mut lines = head.split("\r\n");
+        let status_line = lines.next().unwrap_or("");
+        let status = status_line.split_whitespace().nth(1).unwrap_or("");
+        let mut resp_headers = Vec::new();
+        for line in lines {
+            if let Some(pos) = line.find(':') {
+                resp_headers.push((
+                    line[..pos].trim().to_ascii_lowercase(),
+                    line[(pos + 1)..].trim().to_string(),
+                ));
+            }
+        }
+        let get_header = |name: &str| {
+            resp_headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+
+        if status != "101" {
+            return Err(KubeError::KubeServerError(handshake_error(
+                &mut stream,
+                status_line,
+                rest,
+                get_header("content-length").and_then(|l| l.parse().ok()),
+            )));
+        }
+
+        let mut accept_input = key.into_bytes();
+        accept_input.extend_from_slice(WEBSOCKET_GUID.as_bytes());
+        let expected = base64::encode(
+            digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &accept_input).as_ref(),
+        );
+        if get_header("sec-websocket-accept") != Some(expected.as_str()) {
+            return Err(KubeError::KubeServerError(
+                "Server sent an invalid websocket accept key".to_string(),
+            ));
+        }
+
+        let protocol = get_header("sec-websocket-protocol").map(str::to_owned);
+        Ok(WebSocket {
+            stream,
+            rand,
+            buf: rest,
+            fragments: Vec::new(),
+            protocol,
+        })
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// The subprotocol the server picked from the ones offered in the handshake
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Read the next message. Returns Ok(None) if the stream's read timeout is hit before a
+    /// complete message arrives.
+    pub fn read_message(&mut self) -> io::Result<Option<Message>> {
+        let mut chunk = [0; 16384];
+        loop {
+            while let Some((frame, used)) = parse_frame(&self.buf)? {
+                self.buf.drain(..used);
+                match frame.opcode {
+                    OP_PING => self.send_frame(OP_PONG, &frame.payload)?,
+                    OP_PONG => {}
+                    OP_CLOSE => return Ok(Some(Message::Close)),
+                    OP_TEXT | OP_BINARY | OP_CONTINUATION => {
+                        self.fragments.extend_from_slice(&frame.payload);
+                        if frame.fin {
+                            return Ok(Some(Message::Data(std::mem::take(&mut self.fragments))));
+                        }
+                    }
+                    _ => return Err(invalid_data("Unknown websocket opcode")),
+                }
+            }
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(Some(Message::Close)),
+                Ok(amt) => self.buf.extend_from_slice(&chunk[..amt]),
+                Err(ref e) if is_timeout(e) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut mask = [0; 4];
+        self.rand
+            .fill(&mut mask)
+            .map_err(|_| io::Error::other("Could not generate mask"))?;
+        self.stream
+            .write_all(&encode_frame(opcode, payload, mask))?;
+        self.stream.flush()
+    }
+
+    pub fn send_binary(&mut self, payload: &[u8]) -> io::Result<()> {
+        self.send_frame(OP_BINARY, payload)
+    }
+
+    /// Tell the server we're done. Errors are ignored, since the connection may already be gone
+    pub fn close(&mut self) {
+        self.send_frame(OP_CLOSE, &[]).unwrap_or(());
+    }
+}
+
+/// Build an error message from a failed handshake, using the Status the api server sent back if
+/// we can get it
+fn handshake_error<S: Read>(
+    stream: &mut S,
+    status_line: &str,
+    mut body: Vec<u8>,
+    content_length: Option<usize>,
+) -> String {
+    if let Some(len) = content_length {
+        if body.len() < len {
+            let mut remaining = vec![0; len - body.len()];
+            if stream.read_exact(&mut remaining).is_ok() {
+                body.append(&mut remaining);
+            }
+        }
+    }
+    let message = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|status| {
+            status
+                .get("message")
+                .and_then(|m| m.as_str().map(|s| s.to_string()))
+        });
+    match message {
+        Some(msg) => msg,
+        None => format!("Websocket upgrade failed: {}", status_line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        for len in [0, 5, 125, 126, 70000].iter() {
+            let payload: Vec<u8> = (0..*len).map(|i| (i % 251) as u8).collect();
+            let encoded = encode_frame(OP_BINARY, &payload, [1, 2, 3, 4]);
+            let (frame, used) = parse_frame(&encoded).unwrap().unwrap();
+            assert_eq!(used, encoded.len());
+            assert!(frame.fin);
+            assert_eq!(frame.opcode, OP_BINARY);
+            assert_eq!(frame.payload, payload);
+            // partial frames should wait for more data
+            assert!(parse_frame(&encoded[..(encoded.len() - 1)])
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn test_read_fragmented_message() {
+        // unmasked server frames: "he" (not fin), then ping, then "llo" (fin continuation)
+        let mut data = vec![
+            0x01, 0x02, b'h', b'e', 0x89, 0x00, 0x80, 0x03, b'l', b'l', b'o',
+        ];
+        data.extend_from_slice(&[0x88, 0x00]);
+        let mut ws = WebSocket {
+            stream: io::Cursor::new(Vec::new()),
+            rand: SystemRandom::new(),
+            buf: data,
+            fragments: Vec::new(),
+            protocol: None,
+        };
+        assert_eq!(
+            ws.read_message().unwrap(),
+            Some(Message::Data(b"hello".to_vec()))
+        );
+        assert_eq!(ws.read_message().unwrap(), Some(Message::Close));
+        // we should have replied to the ping with a masked pong
+        let written = ws.stream.into_inner();
+        assert_eq!(written[0], 0x80 | OP_PONG);
+        assert_eq!(written[1], 0x80);
+    }
+}